@@ -29,15 +29,8 @@ impl FileClient {
             )
         })?;
 
-        match response.status() {
-            http::StatusCode::OK => {}
-            http::StatusCode::NOT_FOUND => return Err(router::RouterError::NotFound),
-            code => {
-                return Err(router::RouterError::HandlerError(
-                    500,
-                    format!("Error while retrieving file: received code {}", code),
-                ))
-            }
+        if response.status() != http::StatusCode::OK {
+            return Err(map_status(response.status(), "Error while retrieving file"));
         }
 
         let sync_information = get_sync_information(response.headers());
@@ -80,10 +73,7 @@ impl FileClient {
 
         match response.status() {
             http::StatusCode::OK | http::StatusCode::CREATED => Ok(sync_information),
-            status => Err(router::RouterError::HandlerError(
-                500,
-                format!("Unexpected status code from repository: {}", status),
-            )),
+            status => Err(map_status(status, "Unexpected status code from repository")),
         }
     }
 
@@ -110,10 +100,7 @@ impl FileClient {
 
         match response.status() {
             http::StatusCode::OK | http::StatusCode::NO_CONTENT => Ok(sync_information),
-            status => Err(router::RouterError::HandlerError(
-                500,
-                format!("Unexpected status code from repository: {}", status),
-            )),
+            status => Err(map_status(status, "Unexpected status code from repository")),
         }
     }
 
@@ -165,10 +152,7 @@ impl FileClient {
                 from_sync_information,
                 to_sync_information,
             }),
-            status => Err(router::RouterError::HandlerError(
-                500,
-                format!("Unexpected status code from repository: {}", status),
-            )),
+            status => Err(map_status(status, "Unexpected status code from repository")),
         }
     }
 
@@ -189,6 +173,17 @@ impl FileClient {
     }
 }
 
+/// Maps an unexpected status of the repository to the error the sync worker can act upon
+fn map_status(status: http::StatusCode, context: &str) -> router::RouterError {
+    let message = format!("{}: received code {}", context, status);
+    match status {
+        http::StatusCode::NOT_FOUND => router::RouterError::NotFound,
+        http::StatusCode::PRECONDITION_FAILED => router::RouterError::Conflict(message),
+        status if status.is_server_error() => router::RouterError::ForwardingError(message),
+        _ => router::RouterError::HandlerError(500, message),
+    }
+}
+
 // TODO use files crate instead
 fn get_sync_information(headers: &http::HeaderMap) -> Option<crate::SyncInformation> {
     lazy_static::lazy_static! {
@@ -213,3 +208,74 @@ fn get_sync_information(headers: &http::HeaderMap) -> Option<crate::SyncInformat
         last_synced_timestamp: timestamp,
     });
 }
+
+#[cfg(test)]
+mod tests {
+    async fn get_client(status: u16) -> (wiremock::MockServer, super::FileClient) {
+        let mock_server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::path("/files/keepass/pdb.kdbx"))
+            .respond_with(wiremock::ResponseTemplate::new(status))
+            .mount(&mock_server)
+            .await;
+        let uri: hyper::Uri = mock_server.uri().parse().unwrap();
+        let client = super::FileClient {
+            scheme: String::from(uri.scheme_str().unwrap()),
+            authority: String::from(uri.authority().unwrap().as_str()),
+        };
+        (mock_server, client)
+    }
+
+    #[tokio::test]
+    async fn it_maps_not_found() {
+        let (_server, client) = get_client(404).await;
+
+        assert_eq!(
+            Some(router::RouterError::NotFound),
+            client.get("keepass", "pdb.kdbx").await.err()
+        );
+        assert_eq!(
+            Some(router::RouterError::NotFound),
+            client.delete("keepass", "pdb.kdbx").await.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_maps_precondition_failures_to_conflicts() {
+        let (_server, client) = get_client(412).await;
+
+        let result = client.save("keepass", "pdb.kdbx", vec![1, 2]).await;
+
+        assert!(matches!(result, Err(router::RouterError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn it_maps_server_errors_to_forwarding_errors() {
+        let (_server, client) = get_client(503).await;
+
+        let result = client.get("keepass", "pdb.kdbx").await;
+        assert!(matches!(
+            result,
+            Err(router::RouterError::ForwardingError(_))
+        ));
+
+        let result = client
+            .move_to("keepass", "pdb.kdbx", "keepass", "other.kdbx")
+            .await;
+        assert!(matches!(
+            result,
+            Err(router::RouterError::ForwardingError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_maps_other_statuses_to_handler_errors() {
+        let (_server, client) = get_client(400).await;
+
+        let result = client.save("keepass", "pdb.kdbx", vec![1, 2]).await;
+
+        assert!(matches!(
+            result,
+            Err(router::RouterError::HandlerError(500, _))
+        ));
+    }
+}
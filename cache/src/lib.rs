@@ -25,5 +25,9 @@ pub async fn serve_cache() {
     let addr = std::net::SocketAddr::from_str("[::]:3000")
         .expect("Incorrect host in server configuration");
 
-    router::serve(addr, None, |router| register_handlers(router)).await;
+    router::serve(addr, None, router::ServeOptions::default(), |router| {
+        router.set_json_errors(true);
+        register_handlers(router)
+    })
+    .await;
 }
@@ -0,0 +1,190 @@
+use std::io::Write;
+
+/// Append-only log of the mutations of the repository, one json document per line
+///
+/// Each line is written and flushed before the mutation returns. When `max_bytes` is set and a
+/// line would make the file bigger, the file is renamed with a `.1` suffix, replacing the previous
+/// one, and a new file is started.
+pub struct AuditLog {
+    path: std::path::PathBuf,
+    max_bytes: Option<u64>,
+    file: std::fs::File,
+}
+
+/// Line of the audit log
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub address: String,
+    pub operation: String,
+    pub path: String,
+    pub name: String,
+    /// Destination of a move or swap, as `path/name`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub destination: Option<String>,
+    /// Resulting version if the operation succeeded, else requested version
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub version: Option<i32>,
+    /// Number of lines of history removed by a purge
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub deleted_rows: Option<usize>,
+    /// `ok` or the description of the error
+    pub result: String,
+}
+
+fn open_file(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+}
+
+impl AuditLog {
+    pub fn open(
+        path: &std::path::Path,
+        max_bytes: Option<u64>,
+    ) -> Result<AuditLog, router::RouterError> {
+        log::info!("Opening audit log in {:?}", path);
+        Ok(AuditLog {
+            path: path.to_owned(),
+            max_bytes,
+            file: open_file(path)
+                .map_err(|error| super::map_error(&error, "Failed to open audit log", 500))?,
+        })
+    }
+
+    /// Appends the entry to the log
+    /// The operation already happened, so failures are only logged
+    pub fn record(&mut self, entry: &AuditEntry) {
+        if let Err(error) = self.write(entry) {
+            log::error!("Failed to write audit entry {:?}: {:?}", entry, error);
+        }
+    }
+
+    fn write(&mut self, entry: &AuditEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        if let Some(max_bytes) = self.max_bytes {
+            let size = self.file.metadata()?.len();
+            if size > 0 && size + line.len() as u64 > max_bytes {
+                let mut rotated = self.path.clone().into_os_string();
+                rotated.push(".1");
+                std::fs::rename(&self.path, rotated)?;
+                self.file = open_file(&self.path)?;
+            }
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    static TEST_PATH: &str = "target/test/audit_tests";
+
+    fn get_path(name: &str) -> std::path::PathBuf {
+        std::fs::create_dir_all(TEST_PATH).unwrap();
+        let path = std::path::PathBuf::from(TEST_PATH).join(name);
+        for suffix in ["", ".1"] {
+            let mut file = path.clone().into_os_string();
+            file.push(suffix);
+            let _ = std::fs::remove_file(file);
+        }
+        path
+    }
+
+    fn read_entries(path: &std::path::Path) -> Vec<super::AuditEntry> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_records_the_saves() {
+        let path = get_path("save.log");
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 0, 12));
+        let db_path = std::path::PathBuf::from(TEST_PATH).join("save_db");
+        if db_path.exists() {
+            std::fs::remove_dir_all(&db_path).unwrap();
+        }
+        let mut db = crate::db::FilesDB::new(db_path)
+            .unwrap()
+            .with_audit_log(Some(&path), None)
+            .unwrap();
+
+        db.save("keepass", "pdb.kdbx", &vec![1], None, &address)
+            .unwrap();
+        db.save("keepass", "pdb.kdbx", &vec![2], Some(3), &address)
+            .unwrap_err();
+
+        let entries = read_entries(&path);
+
+        assert_eq!(2, entries.len());
+        assert_eq!("192.168.0.12", entries[0].address);
+        assert_eq!("save", entries[0].operation);
+        assert_eq!("keepass", entries[0].path);
+        assert_eq!("pdb.kdbx", entries[0].name);
+        assert_eq!(Some(0), entries[0].version);
+        assert_eq!("ok", entries[0].result);
+        assert!(chrono::DateTime::parse_from_rfc3339(&entries[0].timestamp).is_ok());
+        assert_eq!(Some(3), entries[1].version);
+        assert!(entries[1].result.contains("412"));
+    }
+
+    #[test]
+    fn it_records_the_purges() {
+        let path = get_path("purge.log");
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 0, 12));
+        let db_path = std::path::PathBuf::from(TEST_PATH).join("purge_db");
+        if db_path.exists() {
+            std::fs::remove_dir_all(&db_path).unwrap();
+        }
+        let mut db = crate::db::FilesDB::new(db_path)
+            .unwrap()
+            .with_audit_log(Some(&path), None)
+            .unwrap();
+
+        db.save("keepass", "pdb.kdbx", &vec![1], None, &address)
+            .unwrap();
+        db.save("keepass", "pdb.kdbx", &vec![2], Some(0), &address)
+            .unwrap();
+        db.purge("keepass", "pdb.kdbx", 1, &address).unwrap();
+
+        let entries = read_entries(&path);
+
+        assert_eq!(3, entries.len());
+        assert_eq!("purge", entries[2].operation);
+        assert_eq!(Some(1), entries[2].version);
+        assert_eq!(Some(2), entries[2].deleted_rows);
+        assert_eq!("ok", entries[2].result);
+        assert_eq!(None, entries[1].deleted_rows);
+    }
+
+    #[test]
+    fn it_rotates_the_log() {
+        let path = get_path("rotate.log");
+        let mut audit_log = super::AuditLog::open(&path, Some(400)).unwrap();
+        let entry = super::AuditEntry {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            address: String::from("127.0.0.1"),
+            operation: String::from("delete"),
+            path: String::from("keepass"),
+            name: String::from("pdb.kdbx"),
+            destination: None,
+            version: Some(1),
+            deleted_rows: None,
+            result: String::from("ok"),
+        };
+
+        for _ in 0..3 {
+            audit_log.record(&entry);
+        }
+
+        let mut rotated = path.clone().into_os_string();
+        rotated.push(".1");
+        assert_eq!(2, read_entries(std::path::Path::new(&rotated)).len());
+        assert_eq!(1, read_entries(&path).len());
+    }
+}
@@ -0,0 +1,169 @@
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct FileConfiguration {
+    #[serde(rename = "rootPath", default = "file_default_root_path")]
+    pub root_path: std::path::PathBuf,
+    /// Maximum number of bytes stored in the repository (current files and history included)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "maxTotalBytes", default)]
+    pub max_total_bytes: Option<u64>,
+    /// Read-only replica of the database used by the reads, kept in sync by an external process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "readReplicaPath", default)]
+    pub read_replica_path: Option<std::path::PathBuf>,
+    /// Uploads bigger than this are spooled to a temporary file instead of being kept in memory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "spoolThresholdBytes", default)]
+    pub spool_threshold_bytes: Option<usize>,
+    /// Uploads bigger than this are refused with a 413, 512 MiB by default
+    #[serde(rename = "maxBodyBytes", default = "file_default_max_body_bytes")]
+    pub max_body_bytes: u64,
+    /// Maximum number of uploads handled at the same time, the others are refused with a 503
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "maxConcurrentUploads", default)]
+    pub max_concurrent_uploads: Option<usize>,
+    /// Uploads of files with these extensions are refused with a 422 if they do not start with the
+    /// given bytes
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(rename = "validateMagic", default)]
+    pub validate_magic: Vec<MagicRule>,
+    /// Roots of the tenants by API key, if not empty the file requests must give one of the keys
+    /// in an `Authorization: Bearer <key>` header and only access the files under its root
+    #[serde(skip_serializing_if = "std::collections::HashMap::is_empty")]
+    #[serde(rename = "apiKeys", default)]
+    pub api_keys: std::collections::HashMap<String, String>,
+    /// Key to give in an `Authorization: Bearer <key>` header to use the /maintenance/* handlers
+    /// when `apiKeys` is set, and the /maintenance/config* ones of the proxy in any case. They are
+    /// refused with a 401 if it is not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "adminKey", default)]
+    pub admin_key: Option<String>,
+    /// IANA name of the timezone in which the history timestamps are rendered, e.g.
+    /// `Europe/Paris`. They are still stored in UTC
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "historyTimezone", default)]
+    pub history_timezone: Option<chrono_tz::Tz>,
+    /// Deletions replace the files with an empty version, still sent on GET with an
+    /// `X-Deleted: true` header, instead of removing them
+    #[serde(rename = "deleteAsTombstone", default)]
+    pub delete_as_tombstone: bool,
+    /// Number of versions kept in the history of each file, the older ones being deleted on save
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "maxRetainedVersions", default)]
+    pub max_retained_versions: Option<u32>,
+    /// File to which every save, deletion, purge, move and swap is appended as a json line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "auditLog", default)]
+    pub audit_log: Option<std::path::PathBuf>,
+    /// Size beyond which the audit log is renamed with a `.1` suffix and a new one is started
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "auditLogMaxBytes", default)]
+    pub audit_log_max_bytes: Option<u64>,
+    /// Paths made of more segments than this, file name included, are refused with a 400
+    #[serde(rename = "maxPathDepth", default = "file_default_max_path_depth")]
+    pub max_path_depth: usize,
+    /// Replaces the backslashes of the paths, sent by some Windows clients, by slashes
+    #[serde(
+        rename = "normalizeBackslashes",
+        default = "file_default_normalize_backslashes"
+    )]
+    pub normalize_backslashes: bool,
+    /// Answers 400 instead of 428 to the DELETE and MOVE requests without a version, for the
+    /// clients that do not handle 428
+    #[serde(rename = "missingVersionAsBadRequest", default)]
+    pub missing_version_as_bad_request: bool,
+    #[serde(default)]
+    pub sqlite: SqliteConfiguration,
+    /// Registers the /maintenance/* handlers
+    #[serde(rename = "maintenanceEnabled", default)]
+    pub maintenance_enabled: bool,
+}
+
+/// Tuning of the sqlite database
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct SqliteConfiguration {
+    /// Size of the pages in bytes, a power of two between 512 and 65536. It is only applied when
+    /// the database is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "pageSize", default)]
+    pub page_size: Option<u32>,
+    /// Maximum size of the page cache in KiB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "cacheSizeKb", default)]
+    pub cache_size_kb: Option<u32>,
+    /// Time a statement waits for a lock held by another connection before failing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "busyTimeoutMs", default)]
+    pub busy_timeout_ms: Option<u32>,
+    /// Number of times a write is started again when the database stays busy, 2 if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "busyRetries", default)]
+    pub busy_retries: Option<u32>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct MagicRule {
+    /// Extension of the file names the rule applies to, e.g. `kdbx`
+    pub extension: String,
+    /// Hexadecimal representation of the bytes the files must start with, e.g. `03d9a29a`
+    pub prefix: String,
+}
+
+impl std::default::Default for FileConfiguration {
+    fn default() -> Self {
+        FileConfiguration {
+            root_path: file_default_root_path(),
+            max_total_bytes: None,
+            read_replica_path: None,
+            spool_threshold_bytes: None,
+            max_body_bytes: file_default_max_body_bytes(),
+            max_concurrent_uploads: None,
+            validate_magic: Vec::new(),
+            api_keys: std::collections::HashMap::new(),
+            admin_key: None,
+            history_timezone: None,
+            delete_as_tombstone: false,
+            max_retained_versions: None,
+            audit_log: None,
+            audit_log_max_bytes: None,
+            max_path_depth: file_default_max_path_depth(),
+            normalize_backslashes: file_default_normalize_backslashes(),
+            missing_version_as_bad_request: false,
+            sqlite: SqliteConfiguration::default(),
+            maintenance_enabled: false,
+        }
+    }
+}
+
+fn file_default_root_path() -> std::path::PathBuf {
+    std::path::PathBuf::from("test/path")
+}
+
+fn file_default_max_body_bytes() -> u64 {
+    512 * 1024 * 1024
+}
+
+fn file_default_max_path_depth() -> usize {
+    32
+}
+
+fn file_default_normalize_backslashes() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn it_validates_the_history_timezone() {
+        let configuration: super::FileConfiguration =
+            serde_json::from_str(r#"{"historyTimezone": "Europe/Paris"}"#).unwrap();
+
+        assert_eq!(
+            Some(chrono_tz::Europe::Paris),
+            configuration.history_timezone
+        );
+        assert!(serde_json::from_str::<super::FileConfiguration>(
+            r#"{"historyTimezone": "Mars/Olympus"}"#
+        )
+        .is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use base64::Engine;
+use rusqlite::OptionalExtension;
 use sha2::Digest;
 
 // Setup statements
@@ -25,32 +26,118 @@ static SQL_CREATE_FILES_HISTORY_TABLE: &str = "create table if not exists FILES_
     primary key (PATH, NAME, VERSION)
 )";
 
+/// Ordered steps bringing the schema to its current version, tracked in `PRAGMA user_version`
+///
+/// The databases created before the versioning are at version 0 and already contain the tables,
+/// hence the `if not exists`. New steps must only be appended.
+static MIGRATIONS: &[(&str, &[&str])] = &[
+    (
+        "Create FILES and FILES_HISTORY tables",
+        &[SQL_CREATE_FILES_TABLE, SQL_CREATE_FILES_HISTORY_TABLE],
+    ),
+    (
+        "Add CONTENT_TYPE column to FILES",
+        &["alter table FILES add column CONTENT_TYPE text"],
+    ),
+    (
+        "Add USER_AGENT and COMMENT columns to FILES_HISTORY",
+        &[
+            "alter table FILES_HISTORY add column USER_AGENT text",
+            "alter table FILES_HISTORY add column COMMENT text",
+        ],
+    ),
+    (
+        "Create FILE_METADATA table",
+        &["create table if not exists FILE_METADATA (
+            PATH text not null,
+            NAME text not null,
+            KEY text not null,
+            VALUE text not null,
+            primary key (PATH, NAME, KEY)
+        )"],
+    ),
+    (
+        "Add CONTENT_TYPE column to FILES_HISTORY",
+        &["alter table FILES_HISTORY add column CONTENT_TYPE text"],
+    ),
+];
+
 // FILES statements
-static SQL_UPSERT_FILE: &str = "insert into FILES (PATH, NAME, VERSION, TIMESTAMP, HASH, FILE)
-    values (?, ?, ?, ?, ?, ?)
+static SQL_UPSERT_FILE: &str =
+    "insert into FILES (PATH, NAME, VERSION, TIMESTAMP, HASH, FILE, CONTENT_TYPE)
+    values (?, ?, ?, ?, ?, ?, ?)
     on conflict(PATH, NAME) do update
-    set VERSION=excluded.VERSION, TIMESTAMP=excluded.TIMESTAMP, HASH=excluded.HASH, FILE=excluded.FILE";
+    set VERSION=excluded.VERSION, TIMESTAMP=excluded.TIMESTAMP, HASH=excluded.HASH,
+        FILE=excluded.FILE, CONTENT_TYPE=excluded.CONTENT_TYPE";
 
 static SQL_DELETE_FILE: &str = "delete from FILES where PATH=? and NAME=?";
 
-static SQL_SELECT_FILE: &str = "select VERSION, TIMESTAMP, FILE from FILES where PATH=? and NAME=?";
+static SQL_SELECT_FILE: &str =
+    "select VERSION, TIMESTAMP, HASH, CONTENT_TYPE, FILE from FILES where PATH=? and NAME=?";
 
 static SQL_SELECT_VERSION: &str = "select VERSION from FILES where PATH=? and NAME=?";
 
 static SQL_SELECT_FILE_NO_CONTENT: &str =
-    "select VERSION, TIMESTAMP from FILES where PATH=? and NAME=?";
+    "select VERSION, TIMESTAMP, HASH, CONTENT_TYPE from FILES where PATH=? and NAME=?";
+
+static SQL_SELECT_FILE_SIZE: &str = "select length(FILE) from FILES where PATH=? and NAME=?";
+
+static SQL_SELECT_BLOB_LOCATION: &str =
+    "select rowid, VERSION, length(FILE) from FILES where PATH=? and NAME=?";
+
+static SQL_SELECT_VERSION_BY_ROWID: &str = "select VERSION from FILES where rowid=?";
+
+static SQL_SELECT_STATUS: &str = "select
+    exists(select 1 from FILES where PATH=?1 and NAME=?2),
+    exists(select 1 from FILES_HISTORY where PATH=?1 and NAME=?2),
+    coalesce((select OPERATION='TOMBSTONE' from FILES_HISTORY where PATH=?1 and NAME=?2
+        order by VERSION desc limit 1), 0)";
+
+// Statistics statements
+static SQL_SELECT_STATS: &str = "select
+    (select count(*) from FILES),
+    (select count(*) from FILES_HISTORY),
+    (select coalesce(sum(length(FILE)), 0) from FILES)
+        + (select coalesce(sum(length(FILE)), 0) from FILES_HISTORY)";
 
 // FILES_HISTORY statements
 static SQL_INSERT_HISTORY_LINE: &str = "insert into FILES_HISTORY
-    (PATH, NAME, VERSION, TIMESTAMP, OPERATION, IP_ADDRESS, HASH, OLD_OR_NEW_PATH, FILE)
-    values (?, ?, ?, ?, ?, ?, ?, ?, ?)";
+    (PATH, NAME, VERSION, TIMESTAMP, OPERATION, IP_ADDRESS, HASH, OLD_OR_NEW_PATH, FILE,
+        USER_AGENT, COMMENT, CONTENT_TYPE)
+    values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+
+static SQL_DELETE_HISTORY: &str = "delete from FILES_HISTORY where PATH=? and NAME=?";
+
+static SQL_PRUNE_HISTORY: &str =
+    "delete from FILES_HISTORY where PATH=?1 and NAME=?2 and VERSION not in
+    (select VERSION from FILES_HISTORY where PATH=?1 and NAME=?2 order by VERSION desc limit ?3)";
+
+static SQL_SELECT_LAST_VERSION: &str = "select max(VERSION) from
+    (select VERSION from FILES where PATH=?1 and NAME=?2
+        union all select VERSION from FILES_HISTORY where PATH=?1 and NAME=?2)";
 
 static SQL_SELECT_HISTORY_VERSION: &str =
     "select max(VERSION) from FILES_HISTORY where PATH=? and NAME=?";
 
+static SQL_SELECT_HISTORY_FILE: &str =
+    "select VERSION, TIMESTAMP, HASH, FILE, CONTENT_TYPE, OPERATION from FILES_HISTORY
+    where PATH=? and NAME=? and VERSION=?";
+
 static SQL_SELECT_HISTORY: &str =
-    "select VERSION, TIMESTAMP, OPERATION, IP_ADDRESS, HASH, OLD_OR_NEW_PATH from FILES_HISTORY
-    where PATH=? and NAME=? order by VERSION";
+    "select VERSION, TIMESTAMP, OPERATION, IP_ADDRESS, HASH, OLD_OR_NEW_PATH, USER_AGENT, COMMENT
+    from FILES_HISTORY where PATH=? and NAME=? and VERSION > ? order by VERSION limit ?";
+
+// FILE_METADATA statements
+static SQL_UPSERT_METADATA: &str = "insert into FILE_METADATA (PATH, NAME, KEY, VALUE)
+    values (?, ?, ?, ?)
+    on conflict(PATH, NAME, KEY) do update set VALUE=excluded.VALUE";
+
+static SQL_SELECT_METADATA: &str =
+    "select KEY, VALUE from FILE_METADATA where PATH=? and NAME=? order by KEY";
+
+static SQL_DELETE_METADATA: &str = "delete from FILE_METADATA where PATH=? and NAME=? and KEY=?";
+
+static SQL_DELETE_ALL_METADATA: &str = "delete from FILE_METADATA where PATH=? and NAME=?";
 
 /// Contains the current state of a resource
 #[derive(Debug)]
@@ -61,17 +148,101 @@ pub struct FilesDbResponse {
     pub timestamp: chrono::DateTime<chrono::Utc>,
     /// Only present in the response of [`FilesDB::get()`], contains the resource
     pub file: Option<Vec<u8>>,
+    /// Base64 encoded SHA-256 digest of the resource, absent after a deletion
+    pub hash: Option<String>,
+    /// Content type given when the resource was saved, if any
+    pub content_type: Option<String>,
+}
+
+/// Position of the content of a resource in the database, read by chunks with
+/// [`FilesDB::get_stream()`]
+#[derive(Clone, Debug)]
+pub struct BlobLocation {
+    row_id: i64,
+    version: i32,
+    /// Size of the content in bytes
+    pub size: usize,
+}
+
+/// Size of the chunks in which [`FilesDB::get_stream()`] reads the content of the resources
+pub const STREAM_CHUNK_BYTES: usize = 256 * 1024;
+
+/// Details given by the client about a mutation, recorded in the history
+#[derive(Debug, Default)]
+pub struct HistoryMetadata {
+    /// User-Agent header of the request
+    pub user_agent: Option<String>,
+    /// X-Change-Comment header of the request
+    pub comment: Option<String>,
+}
+
+/// Whether a resource is present, was deleted or never existed
+#[derive(Debug, PartialEq)]
+pub enum FileStatus {
+    /// The resource currently exists
+    Present,
+    /// The resource was deleted but kept as an empty current version
+    Tombstone,
+    /// The resource has some history but is currently deleted or moved
+    Deleted,
+    /// The resource never existed
+    Unknown,
+}
+
+/// Aggregated figures about the content of the repository
+#[derive(Debug)]
+pub struct FilesDbStats {
+    /// Number of resources currently present
+    pub file_count: u64,
+    /// Number of lines in the history of all the resources
+    pub history_count: u64,
+    /// Number of bytes used by the blobs, current files and history included
+    pub total_bytes: u64,
 }
 
 pub struct FilesDB {
     connection: rusqlite::Connection,
+    max_total_bytes: Option<u64>,
+    audit_log: Option<crate::audit::AuditLog>,
+    delete_as_tombstone: bool,
+    max_retained_versions: Option<u32>,
+    busy_retries: u32,
 }
 
+/// Number of times a write is attempted again when the database is busy, if not configured
+const DEFAULT_BUSY_RETRIES: u32 = 2;
+
 impl FilesDB {
     pub fn new<T>(root_path: T) -> Result<FilesDB, router::RouterError>
     where
         T: std::convert::Into<std::path::PathBuf>,
     {
+        FilesDB::open(
+            root_path,
+            &crate::configuration::SqliteConfiguration::default(),
+        )
+    }
+
+    /// Opens the database with the given sqlite tuning
+    /// The page size only applies to a new database, since it is set before the tables are created
+    pub fn open<T>(
+        root_path: T,
+        sqlite: &crate::configuration::SqliteConfiguration,
+    ) -> Result<FilesDB, router::RouterError>
+    where
+        T: std::convert::Into<std::path::PathBuf>,
+    {
+        if let Some(page_size) = sqlite.page_size {
+            if !(512..=65536).contains(&page_size) || !page_size.is_power_of_two() {
+                return Err(router::HandlerError(
+                    500,
+                    format!(
+                        "Invalid sqlite page size {}: not a power of two between 512 and 65536",
+                        page_size
+                    ),
+                ));
+            }
+        }
         let root_path: std::path::PathBuf = root_path.into();
         if root_path.exists() && !root_path.is_dir() {
             log::error!("Path {:?} exists and is not a directory.", root_path);
@@ -96,49 +267,93 @@ impl FilesDB {
 
         log::info!("Opening database in {:?}", db_path);
         let connection = rusqlite::Connection::open(db_path);
-        let connection = map_sqlite_result(connection, "Failed to open sqlite database")?;
+        let mut connection = map_sqlite_result(connection, "Failed to open sqlite database")?;
 
-        let result = connection.execute(SQL_CREATE_FILES_TABLE, []);
-        map_sqlite_result(result, "Failed to create FILES table in sqlite database")?;
+        if let Some(page_size) = sqlite.page_size {
+            map_sqlite_result(
+                connection.pragma_update(None, "page_size", page_size),
+                "Failed to set sqlite page size",
+            )?;
+        }
+        if let Some(busy_timeout_ms) = sqlite.busy_timeout_ms {
+            map_sqlite_result(
+                connection.busy_timeout(std::time::Duration::from_millis(busy_timeout_ms.into())),
+                "Failed to set sqlite busy timeout",
+            )?;
+        }
+        if let Some(cache_size_kb) = sqlite.cache_size_kb {
+            // negative values are in KiB instead of pages
+            map_sqlite_result(
+                connection.pragma_update(None, "cache_size", -(cache_size_kb as i64)),
+                "Failed to set sqlite cache size",
+            )?;
+        }
 
-        let result = connection.execute(SQL_CREATE_FILES_HISTORY_TABLE, []);
-        map_sqlite_result(
-            result,
-            "Failed to create FILES_HISTORY table in sqlite database",
-        )?;
+        migrate(&mut connection)?;
 
-        Ok(FilesDB { connection })
+        Ok(FilesDB {
+            connection,
+            max_total_bytes: None,
+            audit_log: None,
+            delete_as_tombstone: false,
+            max_retained_versions: None,
+            busy_retries: sqlite.busy_retries.unwrap_or(DEFAULT_BUSY_RETRIES),
+        })
     }
 
-    /// Retrieves the latest version of a resource
-    /// if get_content is false, only the version and timestamp will be retrieved
-    pub fn get(
-        &self,
-        file_path: &str,
-        file_name: &str,
-        get_content: bool,
-    ) -> Result<FilesDbResponse, router::RouterError> {
+    /// Sets the maximum number of bytes the blobs can use in the repository
+    /// Writes that would go beyond are refused with a 507
+    pub fn with_max_total_bytes(mut self, max_total_bytes: Option<u64>) -> FilesDB {
+        self.max_total_bytes = max_total_bytes;
+        self
+    }
+
+    /// Makes [FilesDB::delete] replace the resource with an empty version instead of removing it,
+    /// so that it can still be read
+    pub fn with_delete_as_tombstone(mut self, delete_as_tombstone: bool) -> FilesDB {
+        self.delete_as_tombstone = delete_as_tombstone;
+        self
+    }
+
+    /// Only keeps the given number of versions in the history of a resource when saving it, the
+    /// older ones are deleted, the current version is always kept
+    pub fn with_max_retained_versions(mut self, max_retained_versions: Option<u32>) -> FilesDB {
+        self.max_retained_versions = max_retained_versions;
+        self
+    }
+
+    /// Records the saves, deletions, moves and swaps in an [crate::audit::AuditLog] at the given
+    /// path, rotated once it reaches `max_bytes`
+    pub fn with_audit_log(
+        mut self,
+        audit_log_path: Option<&std::path::Path>,
+        max_bytes: Option<u64>,
+    ) -> Result<FilesDB, router::RouterError> {
+        if let Some(audit_log_path) = audit_log_path {
+            self.audit_log = Some(crate::audit::AuditLog::open(audit_log_path, max_bytes)?);
+        }
+        Ok(self)
+    }
+
+    /// Writes a consistent snapshot of the whole database to the given path
+    pub fn backup(&self, destination: &std::path::Path) -> Result<(), router::RouterError> {
+        log::info!("Backing up database to {:?}", destination);
         self.connection
-            .query_row(
-                if get_content {
-                    SQL_SELECT_FILE
-                } else {
-                    SQL_SELECT_FILE_NO_CONTENT
-                },
-                rusqlite::params![file_path, file_name],
-                |row| {
-                    Ok(FilesDbResponse {
-                        version: row.get(0)?,
-                        timestamp: decode_timestamp(row.get(1)?)?,
-                        file: if get_content { Some(row.get(2)?) } else { None },
-                    })
-                },
-            )
-            .map_err(|error| super::map_error(&error, "Could not find file", 404))
+            .backup(rusqlite::DatabaseName::Main, destination, None)
+            .map_err(|error| super::map_error(&error, "Failed to backup database", 500))
+    }
+
+    /// Returns aggregated figures about the repository
+    pub fn stats(&self) -> Result<FilesDbStats, router::RouterError> {
+        get_stats(&self.connection)
+            .map_err(|error| super::map_error(&error, "Failed to compute statistics", 500))
     }
 
     /// Moves a resource
+    /// The version of the origin and the absence of the destination are checked in the same
+    /// transaction as the move
     /// If successful, the [FilesDbResponse] will contain the state of the initial resource
+    #[allow(clippy::too_many_arguments)]
     pub fn move_to(
         &mut self,
         file_path_from: &str,
@@ -147,6 +362,41 @@ impl FilesDB {
         file_path_to: &str,
         file_name_to: &str,
         address: &std::net::IpAddr,
+        metadata: &HistoryMetadata,
+    ) -> Result<FilesDbResponse, router::RouterError> {
+        let result = self.with_busy_retries(|db| {
+            db.move_to_inner(
+                file_path_from,
+                file_name_from,
+                file_version_from,
+                file_path_to,
+                file_name_to,
+                address,
+                metadata,
+            )
+        });
+        self.audit(
+            "move",
+            address,
+            (file_path_from, file_name_from),
+            Some((file_path_to, file_name_to)),
+            result.as_ref().map(|data| data.version),
+            Some(file_version_from),
+            None,
+        );
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn move_to_inner(
+        &mut self,
+        file_path_from: &str,
+        file_name_from: &str,
+        file_version_from: i32,
+        file_path_to: &str,
+        file_name_to: &str,
+        address: &std::net::IpAddr,
+        metadata: &HistoryMetadata,
     ) -> Result<FilesDbResponse, router::RouterError> {
         if file_name_from == file_name_to && file_path_from == file_path_to {
             return Err(router::InvalidRequest(String::from(
@@ -154,50 +404,76 @@ impl FilesDB {
             )));
         }
 
-        let db_version_from = self
-            .get_current_version(file_path_from, file_name_from)
-            .ok_or(router::HandlerError(404, String::from("File not found")))?;
+        let path_from = std::path::PathBuf::from(file_path_from).join(file_name_from);
+        let path_to = std::path::PathBuf::from(file_path_to).join(file_name_to);
+
+        log::info!(
+            "Starting move transaction from file {}/{} to {}/{}",
+            file_path_from,
+            file_name_from,
+            file_path_to,
+            file_name_to,
+        );
+
+        // the write lock is taken immediately so that the checks below still hold when the
+        // resources are modified, even if another connection writes to the database
+        let transaction = self
+            .connection
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(|error| map_write_error(&error, "Failed to move file"))?;
+
+        let (db_version_from, content_type, file_data) = transaction
+            .query_row(
+                SQL_SELECT_FILE,
+                rusqlite::params![file_path_from, file_name_from],
+                |row| {
+                    Ok((
+                        row.get::<_, i32>(0)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Vec<u8>>(4)?,
+                    ))
+                },
+            )
+            .map_err(|_| router::HandlerError(404, String::from("File not found")))?;
         if db_version_from != file_version_from {
             return Err(router::HandlerError(412, String::from("Version mismatch")));
         }
 
-        let db_version_to = self.get_current_version(file_path_to, file_name_to);
-        if let Some(_) = db_version_to {
+        let destination_exists = transaction
+            .query_row(
+                SQL_SELECT_VERSION,
+                rusqlite::params![file_path_to, file_name_to],
+                |row| row.get::<_, i32>(0),
+            )
+            .is_ok();
+        if destination_exists {
             return Err(router::HandlerError(
                 412,
                 String::from("Destination already exists"),
             ));
         }
 
-        let file_data = self
-            .get(file_path_from, file_name_from, true)?
-            .file
-            .ok_or(router::HandlerError(404, String::from("File not found")))?;
-
         let hash = digest(&file_data);
         let timestamp = chrono::Utc::now();
         let timestamp_str = chrono::Utc::now().to_rfc3339();
         let new_version_from = db_version_from + 1;
-        let new_version_to = self
-            .get_history_version(file_path_to, file_name_to)
-            .map_or(0, |v| v + 1);
+        let new_version_to = transaction
+            .query_row(
+                SQL_SELECT_HISTORY_VERSION,
+                rusqlite::params![file_path_to, file_name_to],
+                |row| row.get(0),
+            )
+            .ok()
+            .map_or(0, |v: i32| v + 1);
         let address = address.to_string();
 
-        let path_from = std::path::PathBuf::from(file_path_from).join(file_name_from);
-        let path_to = std::path::PathBuf::from(file_path_to).join(file_name_to);
-
-        log::info!(
-            "Starting move transaction from file {}/{} to {}/{}",
-            file_path_from,
-            file_name_from,
-            file_path_to,
-            file_name_to,
-        );
-
-        let transaction = self
-            .connection
-            .transaction()
-            .map_err(|error| super::map_error(&error, "Failed to move file", 500))?;
+        // the MOVE_FROM history line holds a new copy of the file
+        check_available_storage(
+            &transaction,
+            self.max_total_bytes,
+            file_data.len() as u64,
+            0,
+        )?;
 
         log::debug!("Inserting MOVE_TO history line");
         transaction
@@ -212,10 +488,13 @@ impl FilesDB {
                     &address,
                     &rusqlite::types::Null,
                     path_to.to_string_lossy(),
+                    &rusqlite::types::Null,
+                    &metadata.user_agent,
+                    &metadata.comment,
                     &rusqlite::types::Null
                 ],
             )
-            .map_err(|error| super::map_error(&error, "Failed to move file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to move file"))?;
 
         log::debug!("Inserting MOVE_FROM history line");
         transaction
@@ -230,10 +509,13 @@ impl FilesDB {
                     &address,
                     &hash,
                     path_from.to_string_lossy(),
-                    file_data
+                    file_data,
+                    &metadata.user_agent,
+                    &metadata.comment,
+                    content_type
                 ],
             )
-            .map_err(|error| super::map_error(&error, "Failed to move file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to move file"))?;
 
         log::debug!("Deleting file from old path");
         transaction
@@ -241,7 +523,7 @@ impl FilesDB {
                 SQL_DELETE_FILE,
                 rusqlite::params![file_path_from, file_name_from,],
             )
-            .map_err(|error| super::map_error(&error, "Failed to move file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to move file"))?;
 
         log::debug!("Creating file in new old path");
         transaction
@@ -253,22 +535,185 @@ impl FilesDB {
                     new_version_to,
                     timestamp_str,
                     &hash,
-                    &file_data
+                    &file_data,
+                    &content_type
                 ],
             )
-            .map_err(|error| super::map_error(&error, "Failed to move file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to move file"))?;
 
         transaction
             .commit()
-            .map_err(|error| super::map_error(&error, "Failed to move file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to move file"))?;
 
         Ok(FilesDbResponse {
             version: new_version_to,
             timestamp,
             file: None,
+            hash: Some(hash),
+            content_type,
         })
     }
 
+    /// Exchanges the content of two resources
+    /// Both resources get a new version so that the versions of a path keep increasing
+    pub fn swap(
+        &mut self,
+        file_path_a: &str,
+        file_name_a: &str,
+        file_path_b: &str,
+        file_name_b: &str,
+        address: &std::net::IpAddr,
+        metadata: &HistoryMetadata,
+    ) -> Result<(), router::RouterError> {
+        let result = self.with_busy_retries(|db| {
+            db.swap_inner(
+                file_path_a,
+                file_name_a,
+                file_path_b,
+                file_name_b,
+                address,
+                metadata,
+            )
+        });
+        self.audit(
+            "swap",
+            address,
+            (file_path_a, file_name_a),
+            Some((file_path_b, file_name_b)),
+            result.as_ref().map(|_| None),
+            None,
+            None,
+        );
+        result
+    }
+
+    fn swap_inner(
+        &mut self,
+        file_path_a: &str,
+        file_name_a: &str,
+        file_path_b: &str,
+        file_name_b: &str,
+        address: &std::net::IpAddr,
+        metadata: &HistoryMetadata,
+    ) -> Result<(), router::RouterError> {
+        if file_name_a == file_name_b && file_path_a == file_path_b {
+            return Err(router::InvalidRequest(String::from(
+                "Cannot swap a file with itself",
+            )));
+        }
+
+        let timestamp_str = chrono::Utc::now().to_rfc3339();
+        let address = address.to_string();
+
+        let path_a = std::path::PathBuf::from(file_path_a).join(file_name_a);
+        let path_b = std::path::PathBuf::from(file_path_b).join(file_name_b);
+
+        log::info!(
+            "Starting swap transaction between files {}/{} and {}/{}",
+            file_path_a,
+            file_name_a,
+            file_path_b,
+            file_name_b,
+        );
+
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|error| map_write_error(&error, "Failed to swap files"))?;
+
+        let get_file = |file_path: &str, file_name: &str| {
+            transaction
+                .query_row(
+                    SQL_SELECT_FILE,
+                    rusqlite::params![file_path, file_name],
+                    |row| {
+                        Ok((
+                            row.get::<_, i32>(0)?,
+                            row.get::<_, Option<String>>(3)?,
+                            row.get::<_, Vec<u8>>(4)?,
+                        ))
+                    },
+                )
+                .map_err(|error| super::map_error(&error, "Could not find file", 404))
+        };
+        let (version_a, content_type_a, file_data_a) = get_file(file_path_a, file_name_a)?;
+        let (version_b, content_type_b, file_data_b) = get_file(file_path_b, file_name_b)?;
+
+        // the SWAP history lines hold a new copy of both files
+        check_available_storage(
+            &transaction,
+            self.max_total_bytes,
+            (file_data_a.len() + file_data_b.len()) as u64,
+            0,
+        )?;
+
+        for (file_path, file_name, new_version, path_with, file_data, content_type) in [
+            (
+                file_path_a,
+                file_name_a,
+                version_a + 1,
+                &path_b,
+                &file_data_b,
+                &content_type_b,
+            ),
+            (
+                file_path_b,
+                file_name_b,
+                version_b + 1,
+                &path_a,
+                &file_data_a,
+                &content_type_a,
+            ),
+        ] {
+            let hash = digest(file_data);
+
+            log::debug!(
+                "Inserting SWAP history line for {}/{}",
+                file_path,
+                file_name
+            );
+            transaction
+                .execute(
+                    SQL_INSERT_HISTORY_LINE,
+                    rusqlite::params![
+                        file_path,
+                        file_name,
+                        new_version,
+                        timestamp_str,
+                        "SWAP",
+                        &address,
+                        &hash,
+                        path_with.to_string_lossy(),
+                        file_data,
+                        &metadata.user_agent,
+                        &metadata.comment,
+                        content_type
+                    ],
+                )
+                .map_err(|error| map_write_error(&error, "Failed to swap files"))?;
+
+            log::debug!("Updating file {}/{}", file_path, file_name);
+            transaction
+                .execute(
+                    SQL_UPSERT_FILE,
+                    rusqlite::params![
+                        file_path,
+                        file_name,
+                        new_version,
+                        timestamp_str,
+                        &hash,
+                        file_data,
+                        content_type
+                    ],
+                )
+                .map_err(|error| map_write_error(&error, "Failed to swap files"))?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|error| map_write_error(&error, "Failed to swap files"))
+    }
+
     /// Saves the new version of a resource
     /// This works to update or create a new resource
     pub fn save(
@@ -278,6 +723,106 @@ impl FilesDB {
         file_data: &Vec<u8>,
         file_version: Option<i32>,
         address: &std::net::IpAddr,
+    ) -> Result<FilesDbResponse, router::RouterError> {
+        self.save_with_content_type(
+            file_path,
+            file_name,
+            file_data,
+            None,
+            file_version,
+            address,
+            &HistoryMetadata::default(),
+        )
+    }
+
+    /// Saves a resource that must not currently exist, failing with a 412 otherwise
+    /// A resource that was deleted can be created again
+    pub fn create_only(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        file_data: &Vec<u8>,
+        address: &std::net::IpAddr,
+    ) -> Result<FilesDbResponse, router::RouterError> {
+        self.create_only_with_content_type(
+            file_path,
+            file_name,
+            file_data,
+            None,
+            address,
+            &HistoryMetadata::default(),
+        )
+    }
+
+    /// Saves a resource that must not currently exist along with its content type, failing with a
+    /// 412 otherwise
+    pub fn create_only_with_content_type(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        file_data: &Vec<u8>,
+        content_type: Option<&str>,
+        address: &std::net::IpAddr,
+        metadata: &HistoryMetadata,
+    ) -> Result<FilesDbResponse, router::RouterError> {
+        // a save without version is refused when the resource currently exists
+        self.save_with_content_type(
+            file_path,
+            file_name,
+            file_data,
+            content_type,
+            None,
+            address,
+            metadata,
+        )
+    }
+
+    /// Saves the new version of a resource along with its content type
+    /// The content type replaces the one of the previous version, even if `None`
+    #[allow(clippy::too_many_arguments)]
+    pub fn save_with_content_type(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        file_data: &Vec<u8>,
+        content_type: Option<&str>,
+        file_version: Option<i32>,
+        address: &std::net::IpAddr,
+        metadata: &HistoryMetadata,
+    ) -> Result<FilesDbResponse, router::RouterError> {
+        let result = self.with_busy_retries(|db| {
+            db.save_inner(
+                file_path,
+                file_name,
+                file_data,
+                content_type,
+                file_version,
+                address,
+                metadata,
+            )
+        });
+        self.audit(
+            "save",
+            address,
+            (file_path, file_name),
+            None,
+            result.as_ref().map(|data| data.version),
+            file_version,
+            None,
+        );
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn save_inner(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        file_data: &Vec<u8>,
+        content_type: Option<&str>,
+        file_version: Option<i32>,
+        address: &std::net::IpAddr,
+        metadata: &HistoryMetadata,
     ) -> Result<FilesDbResponse, router::RouterError> {
         let hash = digest(file_data);
         let timestamp = chrono::Utc::now();
@@ -292,12 +837,20 @@ impl FilesDB {
             file_version
         );
 
-        let db_version = self.get_current_version(file_path, file_name);
-
+        // the version is read in a transaction holding the write lock, so that a concurrent
+        // creation or move cannot happen between the check and the write
         let transaction = self
             .connection
-            .transaction()
-            .map_err(|error| super::map_error(&error, "Failed to save file", 500))?;
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(|error| map_write_error(&error, "Failed to save file"))?;
+
+        let db_version: Option<i32> = transaction
+            .query_row(
+                SQL_SELECT_VERSION,
+                rusqlite::params![file_path, file_name],
+                |row| row.get(0),
+            )
+            .ok();
 
         log::debug!("File version: {:?}", db_version);
 
@@ -308,6 +861,21 @@ impl FilesDB {
             ));
         }
 
+        // the file is stored both in the history and as current version, replacing the previous one
+        let freed_bytes = transaction
+            .query_row(
+                SQL_SELECT_FILE_SIZE,
+                rusqlite::params![file_path, file_name],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+        check_available_storage(
+            &transaction,
+            self.max_total_bytes,
+            2 * file_data.len() as u64,
+            freed_bytes,
+        )?;
+
         let new_version = db_version.map(|v| v + 1).unwrap_or(
             transaction
                 .query_row(
@@ -336,10 +904,13 @@ impl FilesDB {
                     &address,
                     &hash,
                     &rusqlite::types::Null,
-                    file_data
+                    file_data,
+                    &metadata.user_agent,
+                    &metadata.comment,
+                    content_type
                 ],
             )
-            .map_err(|error| super::map_error(&error, "Failed to save file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to save file"))?;
 
         log::debug!("Updating file");
         transaction
@@ -351,21 +922,86 @@ impl FilesDB {
                     new_version,
                     timestamp_str,
                     &hash,
-                    file_data
+                    file_data,
+                    content_type
                 ],
             )
-            .map_err(|error| super::map_error(&error, "Failed to save file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to save file"))?;
+
+        FilesDB::prune_history(
+            &transaction,
+            file_path,
+            file_name,
+            self.max_retained_versions,
+        )?;
 
         transaction
             .commit()
-            .map_err(|error| super::map_error(&error, "Failed to save file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to save file"))?;
         Ok(FilesDbResponse {
             version: new_version,
             timestamp,
             file: None,
+            hash: Some(hash),
+            content_type: content_type.map(str::to_owned),
         })
     }
 
+    /// Deletes the oldest lines of history of a resource beyond the retained number of versions
+    fn prune_history(
+        transaction: &rusqlite::Transaction,
+        file_path: &str,
+        file_name: &str,
+        max_retained_versions: Option<u32>,
+    ) -> Result<(), router::RouterError> {
+        let max_retained_versions = match max_retained_versions {
+            Some(max_retained_versions) => max_retained_versions.max(1),
+            None => return Ok(()),
+        };
+        let pruned = transaction
+            .execute(
+                SQL_PRUNE_HISTORY,
+                rusqlite::params![file_path, file_name, max_retained_versions],
+            )
+            .map_err(|error| map_write_error(&error, "Failed to prune the history"))?;
+        if pruned > 0 {
+            log::debug!("Pruned {} versions of {}/{}", pruned, file_path, file_name);
+        }
+        Ok(())
+    }
+
+    /// Checks whether a save of `file_size` bytes would be accepted, without writing anything
+    /// This lets the uploads be refused before their body is received, [FilesDB::save] still
+    /// checks again in its transaction
+    pub fn check_save(
+        &self,
+        file_path: &str,
+        file_name: &str,
+        file_version: Option<i32>,
+        file_size: u64,
+    ) -> Result<(), router::RouterError> {
+        if file_version != self.get_current_version(file_path, file_name) {
+            return Err(router::RouterError::HandlerError(
+                412,
+                String::from("Version mismatch"),
+            ));
+        }
+        let freed_bytes = self
+            .connection
+            .query_row(
+                SQL_SELECT_FILE_SIZE,
+                rusqlite::params![file_path, file_name],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+        check_available_storage(
+            &self.connection,
+            self.max_total_bytes,
+            2 * file_size,
+            freed_bytes,
+        )
+    }
+
     /// Deletes a resource
     pub fn delete(
         &mut self,
@@ -373,6 +1009,30 @@ impl FilesDB {
         file_name: &str,
         file_version: i32,
         address: &std::net::IpAddr,
+        metadata: &HistoryMetadata,
+    ) -> Result<FilesDbResponse, router::RouterError> {
+        let result = self.with_busy_retries(|db| {
+            db.delete_inner(file_path, file_name, file_version, address, metadata)
+        });
+        self.audit(
+            "delete",
+            address,
+            (file_path, file_name),
+            None,
+            result.as_ref().map(|data| data.version),
+            Some(file_version),
+            None,
+        );
+        result
+    }
+
+    fn delete_inner(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        file_version: i32,
+        address: &std::net::IpAddr,
+        metadata: &HistoryMetadata,
     ) -> Result<FilesDbResponse, router::RouterError> {
         let timestamp = chrono::Utc::now();
         let timestamp_str = timestamp.to_rfc3339();
@@ -392,7 +1052,7 @@ impl FilesDB {
         let transaction = self
             .connection
             .transaction()
-            .map_err(|error| super::map_error(&error, "Failed to delete", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to delete"))?;
 
         if file_version != db_version {
             return Err(router::RouterError::HandlerError(
@@ -403,10 +1063,58 @@ impl FilesDB {
 
         let new_version = db_version + 1;
 
+        if self.delete_as_tombstone {
+            let hash = digest(&Vec::new());
+            log::debug!("Replacing file with a tombstone");
+            transaction
+                .execute(
+                    SQL_INSERT_HISTORY_LINE,
+                    rusqlite::params![
+                        file_path,
+                        file_name,
+                        new_version,
+                        timestamp_str,
+                        "TOMBSTONE",
+                        &address,
+                        &hash,
+                        &rusqlite::types::Null,
+                        &Vec::<u8>::new(),
+                        &metadata.user_agent,
+                        &metadata.comment,
+                        &rusqlite::types::Null
+                    ],
+                )
+                .map_err(|error| map_write_error(&error, "Failed to delete file"))?;
+            transaction
+                .execute(
+                    SQL_UPSERT_FILE,
+                    rusqlite::params![
+                        file_path,
+                        file_name,
+                        new_version,
+                        timestamp_str,
+                        &hash,
+                        &Vec::<u8>::new(),
+                        &rusqlite::types::Null
+                    ],
+                )
+                .map_err(|error| map_write_error(&error, "Failed to delete file"))?;
+            transaction
+                .commit()
+                .map_err(|error| map_write_error(&error, "Failed to delete file"))?;
+            return Ok(FilesDbResponse {
+                version: new_version,
+                timestamp,
+                file: None,
+                hash: Some(hash),
+                content_type: None,
+            });
+        }
+
         log::debug!("Deleting file");
         let rows_updated = transaction
             .execute(SQL_DELETE_FILE, rusqlite::params![file_path, file_name,])
-            .map_err(|error| super::map_error(&error, "Failed to delete file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to delete file"))?;
 
         if rows_updated != 0 {
             log::debug!("Inserting history line");
@@ -422,92 +1130,206 @@ impl FilesDB {
                         &address,
                         &rusqlite::types::Null,
                         &rusqlite::types::Null,
+                        &rusqlite::types::Null,
+                        &metadata.user_agent,
+                        &metadata.comment,
                         &rusqlite::types::Null
                     ],
                 )
-                .map_err(|error| super::map_error(&error, "Failed to delete file", 500))?;
+                .map_err(|error| map_write_error(&error, "Failed to delete file"))?;
         } else {
             log::debug!("No row deleted");
         }
 
         transaction
             .commit()
-            .map_err(|error| super::map_error(&error, "Failed to delete file", 500))?;
+            .map_err(|error| map_write_error(&error, "Failed to delete file"))?;
 
         Ok(FilesDbResponse {
             version: new_version,
             timestamp,
             file: None,
+            hash: None,
+            content_type: None,
         })
     }
 
-    /// Returns the history of a resource as a [crate::log::FileLog]
-    pub fn get_history(
-        &self,
+    /// Removes a resource with all its versions and metadata, returning the number of lines of
+    /// history deleted
+    ///
+    /// The version must be the last one of the resource, including its deletion if it was
+    /// deleted. Unlike [`FilesDB::delete()`], nothing is kept: the version of the path starts again
+    /// from 0
+    pub fn purge(
+        &mut self,
         file_path: &str,
         file_name: &str,
-    ) -> Result<crate::log::FileLog, router::RouterError> {
-        let history = self
-            .get_history_inner(file_path, file_name)
-            .map_err(|error| super::map_error(&error, "Failed to retrieve history", 500));
-        if let Ok(log) = &history {
-            if log.entries.is_empty() {
-                return Err(router::RouterError::NotFound);
+        file_version: i32,
+        address: &std::net::IpAddr,
+    ) -> Result<usize, router::RouterError> {
+        let result =
+            self.with_busy_retries(|db| db.purge_inner(file_path, file_name, file_version));
+        self.audit(
+            "purge",
+            address,
+            (file_path, file_name),
+            None,
+            result.as_ref().map(|_| file_version),
+            Some(file_version),
+            result.as_ref().ok().copied(),
+        );
+        result
+    }
+
+    fn purge_inner(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        file_version: i32,
+    ) -> Result<usize, router::RouterError> {
+        log::info!(
+            "Purging file {}/{} with version {}",
+            file_path,
+            file_name,
+            file_version
+        );
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|error| map_write_error(&error, "Failed to purge file"))?;
+        let params = rusqlite::params![file_path, file_name];
+        let db_version: Option<i32> = transaction
+            .query_row(SQL_SELECT_LAST_VERSION, params, |row| row.get(0))
+            .map_err(|error| map_write_error(&error, "Failed to purge file"))?;
+        match db_version {
+            None => return Err(router::RouterError::NotFound),
+            Some(db_version) if db_version != file_version => {
+                return Err(router::RouterError::HandlerError(
+                    412,
+                    String::from("Version mismatch"),
+                ));
             }
+            _ => (),
         }
-        history
+        let history_rows = transaction
+            .execute(SQL_DELETE_HISTORY, params)
+            .map_err(|error| map_write_error(&error, "Failed to purge file"))?;
+        transaction
+            .execute(SQL_DELETE_FILE, params)
+            .map_err(|error| map_write_error(&error, "Failed to purge file"))?;
+        transaction
+            .execute(SQL_DELETE_ALL_METADATA, params)
+            .map_err(|error| map_write_error(&error, "Failed to purge file"))?;
+        transaction
+            .commit()
+            .map_err(|error| map_write_error(&error, "Failed to purge file"))?;
+        Ok(history_rows)
     }
 
-    fn get_current_version(&self, file_path: &str, file_name: &str) -> Option<i32> {
+    /// Sets a metadata key of a resource, replacing its previous value if any
+    ///
+    /// The metadata are kept across the versions of the resource, which must currently exist.
+    pub fn set_meta(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), router::RouterError> {
+        if self.get_current_version(file_path, file_name).is_none() {
+            return Err(router::RouterError::NotFound);
+        }
         self.connection
-            .query_row(
-                SQL_SELECT_VERSION,
-                rusqlite::params![file_path, file_name],
-                |row| row.get(0),
+            .execute(
+                SQL_UPSERT_METADATA,
+                rusqlite::params![file_path, file_name, key, value],
             )
-            .ok()
+            .map_err(|error| map_write_error(&error, "Failed to set metadata"))?;
+        Ok(())
+    }
+
+    /// Removes a metadata key of a resource, failing with a 404 if it was not set
+    pub fn delete_meta(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        key: &str,
+    ) -> Result<(), router::RouterError> {
+        let deleted = self
+            .connection
+            .execute(
+                SQL_DELETE_METADATA,
+                rusqlite::params![file_path, file_name, key],
+            )
+            .map_err(|error| map_write_error(&error, "Failed to delete metadata"))?;
+        if deleted == 0 {
+            return Err(router::RouterError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Records a mutation in the audit log, if any
+    /// The version is the resulting one if the mutation succeeded, else the requested one
+    #[allow(clippy::too_many_arguments)]
+    fn audit<T: Into<Option<i32>>>(
+        &mut self,
+        operation: &str,
+        address: &std::net::IpAddr,
+        (file_path, file_name): (&str, &str),
+        destination: Option<(&str, &str)>,
+        result: Result<T, &router::RouterError>,
+        requested_version: Option<i32>,
+        deleted_rows: Option<usize>,
+    ) {
+        if let Some(audit_log) = &mut self.audit_log {
+            let (version, result) = match result {
+                Ok(version) => (version.into(), String::from("ok")),
+                Err(error) => (requested_version, format!("{:?}", error)),
+            };
+            audit_log.record(&crate::audit::AuditEntry {
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                address: address.to_string(),
+                operation: operation.to_owned(),
+                path: file_path.to_owned(),
+                name: file_name.to_owned(),
+                destination: destination.map(|(path, name)| format!("{}/{}", path, name)),
+                version,
+                deleted_rows,
+                result,
+            });
+        }
+    }
+
+    /// Runs the write again, from the start of its transaction, while the database is busy
+    /// Once the retries are exhausted, the write is refused with a 503
+    fn with_busy_retries<T, F>(&mut self, mut write: F) -> Result<T, router::RouterError>
+    where
+        F: FnMut(&mut FilesDB) -> Result<T, router::RouterError>,
+    {
+        let mut attempt = 0;
+        loop {
+            match write(self) {
+                Err(router::HandlerError(503, message)) if attempt < self.busy_retries => {
+                    attempt += 1;
+                    log::warn!("{}, retrying ({}/{})", message, attempt, self.busy_retries);
+                }
+                result => return result,
+            }
+        }
     }
 
-    fn get_history_version(&self, file_path: &str, file_name: &str) -> Option<i32> {
+    fn get_current_version(&self, file_path: &str, file_name: &str) -> Option<i32> {
         self.connection
             .query_row(
-                SQL_SELECT_HISTORY_VERSION,
+                SQL_SELECT_VERSION,
                 rusqlite::params![file_path, file_name],
                 |row| row.get(0),
             )
             .ok()
     }
 
-    fn get_history_inner(
-        &self,
-        file_path: &str,
-        file_name: &str,
-    ) -> Result<crate::log::FileLog, rusqlite::Error> {
-        log::info!("Retrieving history for file {}/{}", file_path, file_name);
-        let mut statement = self.connection.prepare(SQL_SELECT_HISTORY)?;
-        let mut rows = statement.query(rusqlite::params![file_path, file_name])?;
-        let mut entries: Vec<crate::log::FileLogEntry> = vec![];
-        while let Some(row) = rows.next()? {
-            log::debug!(
-                "Decoding new history line for file {}/{}",
-                file_path,
-                file_name
-            );
-            if let Some(entry) = FilesDB::decode_history_row(row) {
-                entries.push(entry);
-            } else {
-                log::warn!(
-                    "Ignoring invalid history line for file {}/{}",
-                    file_path,
-                    file_name
-                );
-            }
-        }
-        Ok(crate::log::FileLog { entries })
-    }
-
     fn decode_history_row(row: &rusqlite::Row) -> Option<crate::log::FileLogEntry> {
-        // VERSION, TIMESTAMP, OPERATION, IP_ADDRESS, HASH, OLD_OR_NEW_PATH
+        // VERSION, TIMESTAMP, OPERATION, IP_ADDRESS, HASH, OLD_OR_NEW_PATH, USER_AGENT, COMMENT
         let version: u32 = row.get(0).ok()?;
         log::trace!("Decoding version {}", version);
         let timestamp: String = row.get(1).ok()?;
@@ -523,12 +1345,16 @@ impl FilesDB {
             <std::net::IpAddr as std::str::FromStr>::from_str(address.as_ref()).ok()?;
         let hash: Option<String> = row.get(4).ok()?;
         let path: Option<String> = row.get(5).ok()?;
+        let user_agent: Option<String> = row.get(6).ok()?;
+        let comment: Option<String> = row.get(7).ok()?;
         log::trace!("Creating entry");
         match crate::log::FileLogEntryType::new(operation, version, hash, path) {
             Ok(entry) => Some(crate::log::FileLogEntry {
                 timestamp,
                 address,
                 entry,
+                user_agent,
+                comment,
             }),
             Err(msg) => {
                 log::info!("Hisory entry was invalid: {}", msg);
@@ -538,12 +1364,404 @@ impl FilesDB {
     }
 }
 
+/// Reads of the resources, done by the [FilesDB] on its own connection, or by a [ReadReplica]
+/// without waiting for the writes
+pub trait FilesReader {
+    /// Connection the reads are done on
+    fn read_connection(&self) -> &rusqlite::Connection;
+
+    /// Retrieves the latest version of a resource
+    /// if get_content is false, only the version and timestamp will be retrieved
+    fn get(
+        &self,
+        file_path: &str,
+        file_name: &str,
+        get_content: bool,
+    ) -> Result<FilesDbResponse, router::RouterError> {
+        get_file(self.read_connection(), file_path, file_name, get_content)
+    }
+    /// Retrieves the content of a resource at the given version of its history
+    /// The versions without content, e.g. deletions or moves to another path, are not found
+    fn get_version(
+        &self,
+        file_path: &str,
+        file_name: &str,
+        version: i32,
+    ) -> Result<FilesDbResponse, router::RouterError> {
+        let (response, operation): (FilesDbResponse, String) = self
+            .read_connection()
+            .query_row(
+                SQL_SELECT_HISTORY_FILE,
+                rusqlite::params![file_path, file_name, version],
+                |row| {
+                    Ok((
+                        FilesDbResponse {
+                            version: row.get(0)?,
+                            timestamp: decode_timestamp(row.get(1)?)?,
+                            file: row.get(3)?,
+                            hash: row.get(2)?,
+                            content_type: row.get(4)?,
+                        },
+                        row.get(5)?,
+                    ))
+                },
+            )
+            .map_err(|error| super::map_error(&error, "Could not find version", 404))?;
+        // the tombstones keep an empty content rather than none
+        match response.file {
+            Some(_) if operation != "TOMBSTONE" => Ok(response),
+            _ => Err(router::HandlerError(
+                404,
+                format!("Version {} has no content", version),
+            )),
+        }
+    }
+    /// Tells whether a resource is present, was deleted or never existed
+    fn get_status(
+        &self,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<FileStatus, router::RouterError> {
+        let (present, has_history, tombstone): (bool, bool, bool) = self
+            .read_connection()
+            .query_row(
+                SQL_SELECT_STATUS,
+                rusqlite::params![file_path, file_name],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|error| super::map_error(&error, "Failed to get file status", 500))?;
+        Ok(match (present, has_history, tombstone) {
+            (true, _, true) => FileStatus::Tombstone,
+            (true, _, false) => FileStatus::Present,
+            (false, true, _) => FileStatus::Deleted,
+            (false, false, _) => FileStatus::Unknown,
+        })
+    }
+    /// Locates the content of the latest version of a resource, to stream it
+    fn get_blob_location(
+        &self,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<BlobLocation, router::RouterError> {
+        self.read_connection()
+            .query_row(
+                SQL_SELECT_BLOB_LOCATION,
+                rusqlite::params![file_path, file_name],
+                |row| {
+                    Ok(BlobLocation {
+                        row_id: row.get(0)?,
+                        version: row.get(1)?,
+                        size: row.get(2)?,
+                    })
+                },
+            )
+            .map_err(|error| super::map_error(&error, "Could not find file", 404))
+    }
+    /// Returns the history of a resource as a [crate::log::FileLog]
+    fn get_history(
+        &self,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<crate::log::FileLog, router::RouterError> {
+        // a negative limit means no limit for sqlite
+        let history = get_history_inner(self.read_connection(), file_path, file_name, -1, -1)
+            .map_err(|error| super::map_error(&error, "Failed to retrieve history", 500));
+        if let Ok(log) = &history {
+            if log.entries.is_empty() {
+                return Err(router::RouterError::NotFound);
+            }
+        }
+        history
+    }
+    /// Returns at most `limit` entries of the history of a resource, starting after the given
+    /// version or from the first one if `None`
+    ///
+    /// Unlike [`FilesReader::get_history()`], a page after the end of the history is empty instead of
+    /// being an error
+    fn get_history_after(
+        &self,
+        file_path: &str,
+        file_name: &str,
+        after_version: Option<u32>,
+        limit: u32,
+    ) -> Result<crate::log::FileLog, router::RouterError> {
+        let history = get_history_inner(
+            self.read_connection(),
+            file_path,
+            file_name,
+            after_version.map_or(-1, i64::from),
+            i64::from(limit),
+        )
+        .map_err(|error| super::map_error(&error, "Failed to retrieve history", 500))?;
+        if after_version.is_none() && history.entries.is_empty() {
+            return Err(router::RouterError::NotFound);
+        }
+        Ok(history)
+    }
+    /// Returns the metadata of a resource, sorted by key
+    fn get_meta_map(
+        &self,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<std::collections::BTreeMap<String, String>, router::RouterError> {
+        let map_error =
+            |error: rusqlite::Error| super::map_error(&error, "Failed to retrieve metadata", 500);
+        let mut statement = self
+            .read_connection()
+            .prepare(SQL_SELECT_METADATA)
+            .map_err(map_error)?;
+        let rows = statement
+            .query_map(rusqlite::params![file_path, file_name], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(map_error)?;
+        rows.collect::<Result<_, _>>().map_err(map_error)
+    }
+}
+
+impl FilesReader for FilesDB {
+    fn read_connection(&self) -> &rusqlite::Connection {
+        &self.connection
+    }
+}
+
+/// Read-only connection to a replica of the database, kept in sync by an external process
+///
+/// It is locked separately from the [FilesDB], so the reads done on it do not wait for the writes,
+/// but they may not reflect the latest ones.
+pub struct ReadReplica {
+    connection: rusqlite::Connection,
+}
+
+impl ReadReplica {
+    pub fn open(read_replica_path: &std::path::Path) -> Result<ReadReplica, router::RouterError> {
+        log::info!("Opening read replica in {:?}", read_replica_path);
+        let connection = rusqlite::Connection::open_with_flags(
+            read_replica_path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        );
+        Ok(ReadReplica {
+            connection: map_sqlite_result(connection, "Failed to open sqlite read replica")?,
+        })
+    }
+}
+
+impl FilesReader for ReadReplica {
+    fn read_connection(&self) -> &rusqlite::Connection {
+        &self.connection
+    }
+}
+
+/// Streams the given range of the content located by [`FilesReader::get_blob_location()`], by
+/// chunks of [`STREAM_CHUNK_BYTES`]
+/// The lock is only held while each chunk is read, so the stream fails if the resource is
+/// modified before it ends
+pub fn get_stream<R: FilesReader + Send + 'static>(
+    reader: std::sync::Arc<std::sync::Mutex<R>>,
+    location: BlobLocation,
+    range: std::ops::Range<usize>,
+) -> impl futures::Stream<Item = Result<Vec<u8>, std::io::Error>> {
+    futures::stream::unfold(Some(range.start), move |offset| {
+        let next = offset.filter(|offset| *offset < range.end).map(|offset| {
+            let length = std::cmp::min(STREAM_CHUNK_BYTES, range.end - offset);
+            let reader = reader.lock().unwrap();
+            match read_chunk(reader.read_connection(), &location, offset, length) {
+                Ok(chunk) => (Ok(chunk), Some(offset + length)),
+                Err(error) => (Err(std::io::Error::other(format!("{:?}", error))), None),
+            }
+        });
+        futures::future::ready(next)
+    })
+}
+
+fn get_history_inner(
+    connection: &rusqlite::Connection,
+    file_path: &str,
+    file_name: &str,
+    after_version: i64,
+    limit: i64,
+) -> Result<crate::log::FileLog, rusqlite::Error> {
+    log::info!("Retrieving history for file {}/{}", file_path, file_name);
+    let mut statement = connection.prepare(SQL_SELECT_HISTORY)?;
+    let mut rows = statement.query(rusqlite::params![
+        file_path,
+        file_name,
+        after_version,
+        limit
+    ])?;
+    let mut entries: Vec<crate::log::FileLogEntry> = vec![];
+    while let Some(row) = rows.next()? {
+        log::debug!(
+            "Decoding new history line for file {}/{}",
+            file_path,
+            file_name
+        );
+        if let Some(entry) = FilesDB::decode_history_row(row) {
+            entries.push(entry);
+        } else {
+            log::warn!(
+                "Ignoring invalid history line for file {}/{}",
+                file_path,
+                file_name
+            );
+        }
+    }
+    Ok(crate::log::FileLog { entries })
+}
+
+fn read_chunk(
+    connection: &rusqlite::Connection,
+    location: &BlobLocation,
+    offset: usize,
+    length: usize,
+) -> Result<Vec<u8>, router::RouterError> {
+    let version: Option<i32> = connection
+        .query_row(SQL_SELECT_VERSION_BY_ROWID, [location.row_id], |row| {
+            row.get(0)
+        })
+        .optional()
+        .map_err(|error| super::map_error(&error, "Failed to read file", 500))?;
+    if version != Some(location.version) {
+        log::warn!("File changed while it was being streamed");
+        return Err(router::HandlerError(
+            409,
+            String::from("File changed while it was being streamed"),
+        ));
+    }
+    let mut chunk = vec![0; length];
+    connection
+        .blob_open(
+            rusqlite::DatabaseName::Main,
+            "FILES",
+            "FILE",
+            location.row_id,
+            true,
+        )
+        .and_then(|blob| blob.read_at_exact(&mut chunk, offset))
+        .map_err(|error| super::map_error(&error, "Failed to read file", 500))?;
+    Ok(chunk)
+}
+
+/// Applies the [MIGRATIONS] the database is missing, each one in its own transaction
+fn migrate(connection: &mut rusqlite::Connection) -> Result<(), router::RouterError> {
+    let version: usize = map_sqlite_result(
+        connection.query_row("pragma user_version", [], |row| row.get(0)),
+        "Failed to read schema version",
+    )?;
+    if version > MIGRATIONS.len() {
+        log::error!(
+            "Database schema version {} is more recent than the supported one {}",
+            version,
+            MIGRATIONS.len()
+        );
+        return Err(router::HandlerError(
+            500,
+            String::from("Unsupported database schema version"),
+        ));
+    }
+    for (index, (description, statements)) in MIGRATIONS.iter().enumerate().skip(version) {
+        log::info!("Applying migration {}: {}", index + 1, description);
+        let transaction = map_sqlite_result(
+            connection.transaction(),
+            "Failed to start migration transaction",
+        )?;
+        for statement in statements.iter() {
+            map_sqlite_result(
+                transaction.execute(statement, []),
+                "Failed to apply migration",
+            )?;
+        }
+        map_sqlite_result(
+            transaction.pragma_update(None, "user_version", index + 1),
+            "Failed to update schema version",
+        )?;
+        map_sqlite_result(transaction.commit(), "Failed to commit migration")?;
+    }
+    Ok(())
+}
+
 fn decode_timestamp(timestamp: String) -> Result<chrono::DateTime<chrono::Utc>, rusqlite::Error> {
     chrono::DateTime::parse_from_rfc3339(timestamp.as_ref())
         .map(|ts| ts.with_timezone(&chrono::Utc))
         .map_err(|_| rusqlite::Error::InvalidColumnName(String::from("Failed to decode timestamp")))
 }
 
+fn get_stats(connection: &rusqlite::Connection) -> Result<FilesDbStats, rusqlite::Error> {
+    connection.query_row(SQL_SELECT_STATS, [], |row| {
+        Ok(FilesDbStats {
+            file_count: row.get(0)?,
+            history_count: row.get(1)?,
+            total_bytes: row.get(2)?,
+        })
+    })
+}
+
+/// Checks the repository can accommodate the given number of additional bytes
+fn check_available_storage(
+    connection: &rusqlite::Connection,
+    max_total_bytes: Option<u64>,
+    added_bytes: u64,
+    freed_bytes: u64,
+) -> Result<(), router::RouterError> {
+    if let Some(max_total_bytes) = max_total_bytes {
+        let total_bytes = get_stats(connection)
+            .map_err(|error| super::map_error(&error, "Failed to compute repository size", 500))?
+            .total_bytes;
+        let new_total_bytes = (total_bytes + added_bytes).saturating_sub(freed_bytes);
+        if new_total_bytes > max_total_bytes {
+            log::warn!(
+                "Refusing write of {} bytes: repository would use {} bytes out of {}",
+                added_bytes,
+                new_total_bytes,
+                max_total_bytes
+            );
+            return Err(router::HandlerError(
+                507,
+                String::from("Insufficient storage"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn get_file(
+    connection: &rusqlite::Connection,
+    file_path: &str,
+    file_name: &str,
+    get_content: bool,
+) -> Result<FilesDbResponse, router::RouterError> {
+    connection
+        .query_row(
+            if get_content {
+                SQL_SELECT_FILE
+            } else {
+                SQL_SELECT_FILE_NO_CONTENT
+            },
+            rusqlite::params![file_path, file_name],
+            |row| {
+                Ok(FilesDbResponse {
+                    version: row.get(0)?,
+                    timestamp: decode_timestamp(row.get(1)?)?,
+                    file: if get_content { Some(row.get(4)?) } else { None },
+                    hash: Some(row.get(2)?),
+                    content_type: row.get(3)?,
+                })
+            },
+        )
+        .map_err(|error| super::map_error(&error, "Could not find file", 404))
+}
+
+/// Maps the errors of the writes, a busy or locked database giving a 503 that can be retried
+fn map_write_error(error: &rusqlite::Error, message: &str) -> router::RouterError {
+    match error.sqlite_error_code() {
+        Some(rusqlite::ErrorCode::DatabaseBusy) | Some(rusqlite::ErrorCode::DatabaseLocked) => {
+            log::info!("{}: {:?}", message, error);
+            router::HandlerError(503, format!("{}: database is busy", message))
+        }
+        _ => super::map_error(error, message, 500),
+    }
+}
+
 fn map_sqlite_result<T, E>(result: Result<T, E>, message: &str) -> Result<T, router::RouterError>
 where
     E: std::fmt::Debug,
@@ -592,6 +1810,207 @@ mod tests {
         assert_eq!(file_data, retrieved_data.file.unwrap());
     }
 
+    #[test]
+    fn it_only_creates_absent_files() {
+        let mut db = get_repo("create_only");
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+
+        let created = db
+            .create_only("keepass", "pdb.kdbx", &vec![1], &address)
+            .unwrap();
+
+        assert_eq!(0, created.version);
+
+        let error = db
+            .create_only("keepass", "pdb.kdbx", &vec![2], &address)
+            .unwrap_err();
+
+        assert!(matches!(error, router::RouterError::HandlerError(412, _)));
+        assert_eq!(
+            Some(vec![1]),
+            db.get("keepass", "pdb.kdbx", true).unwrap().file
+        );
+    }
+
+    #[test]
+    fn it_applies_the_sqlite_tuning() {
+        let root_path = std::path::PathBuf::from(TEST_PATH).join("sqlite_tuning");
+        if root_path.exists() {
+            std::fs::remove_dir_all(&root_path).unwrap();
+        }
+        let sqlite = crate::configuration::SqliteConfiguration {
+            page_size: Some(8192),
+            cache_size_kb: Some(4096),
+            ..Default::default()
+        };
+
+        let db = FilesDB::open(&root_path, &sqlite).unwrap();
+        let page_size: u32 = db
+            .connection
+            .query_row("pragma page_size", [], |row| row.get(0))
+            .unwrap();
+        let cache_size: i64 = db
+            .connection
+            .query_row("pragma cache_size", [], |row| row.get(0))
+            .unwrap();
+
+        assert_eq!(8192, page_size);
+        assert_eq!(-4096, cache_size);
+
+        let sqlite = crate::configuration::SqliteConfiguration {
+            page_size: Some(3000),
+            ..Default::default()
+        };
+        assert!(FilesDB::open(&root_path, &sqlite).is_err());
+    }
+
+    #[test]
+    fn it_retries_the_writes_on_a_busy_database() {
+        let root_path = std::path::PathBuf::from(TEST_PATH).join("busy");
+        if root_path.exists() {
+            std::fs::remove_dir_all(&root_path).unwrap();
+        }
+        let sqlite = crate::configuration::SqliteConfiguration {
+            busy_timeout_ms: Some(10),
+            busy_retries: Some(20),
+            ..Default::default()
+        };
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(0, 0, 0, 0));
+        let mut db = FilesDB::open(&root_path, &sqlite).unwrap();
+
+        // a read transaction keeps the writes from committing until it ends
+        let hold_read_lock = |duration: Option<std::time::Duration>| {
+            let connection =
+                rusqlite::Connection::open(root_path.join("file_repository.db3")).unwrap();
+            connection.execute_batch("begin").unwrap();
+            let _: i64 = connection
+                .query_row("select count(*) from FILES", [], |row| row.get(0))
+                .unwrap();
+            if let Some(duration) = duration {
+                std::thread::spawn(move || {
+                    std::thread::sleep(duration);
+                    connection.execute_batch("commit").unwrap();
+                });
+                None
+            } else {
+                Some(connection)
+            }
+        };
+
+        hold_read_lock(Some(std::time::Duration::from_millis(60)));
+        db.save("keepass", "pdb.kdbx", &vec![1], None, &address)
+            .unwrap();
+
+        let _reader = hold_read_lock(None);
+        db.busy_retries = 2;
+        match db.save("keepass", "pdb.kdbx", &vec![2], Some(0), &address) {
+            Err(router::HandlerError(503, _)) => (),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert_eq!(Some(0), db.get_current_version("keepass", "pdb.kdbx"));
+    }
+
+    #[test]
+    fn it_migrates_unversioned_databases() {
+        let root_path = std::path::PathBuf::from(TEST_PATH).join("migration");
+        if root_path.exists() {
+            std::fs::remove_dir_all(&root_path).unwrap();
+        }
+        std::fs::create_dir_all(&root_path).unwrap();
+        {
+            // database created before the schema was versioned
+            let connection =
+                rusqlite::Connection::open(root_path.join("file_repository.db3")).unwrap();
+            connection.execute(SQL_CREATE_FILES_TABLE, []).unwrap();
+            connection
+                .execute(SQL_CREATE_FILES_HISTORY_TABLE, [])
+                .unwrap();
+            connection
+                .execute(
+                    "insert into FILES (PATH, NAME, VERSION, TIMESTAMP, HASH, FILE)
+                    values (?, ?, ?, ?, ?, ?)",
+                    rusqlite::params![
+                        "path",
+                        "name",
+                        3,
+                        chrono::Utc::now().to_rfc3339(),
+                        "hash",
+                        vec![1u8]
+                    ],
+                )
+                .unwrap();
+        }
+
+        for _ in 0..2 {
+            let db = FilesDB::new(&root_path).unwrap();
+            let version: usize = db
+                .connection
+                .query_row("pragma user_version", [], |row| row.get(0))
+                .unwrap();
+
+            assert_eq!(MIGRATIONS.len(), version);
+            let migrated = db.get("path", "name", false).unwrap();
+
+            assert_eq!(3, migrated.version);
+            assert_eq!(None, migrated.content_type);
+        }
+
+        rusqlite::Connection::open(root_path.join("file_repository.db3"))
+            .unwrap()
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+
+        assert!(FilesDB::new(&root_path).is_err());
+    }
+
+    #[test]
+    fn it_stores_the_metadata_across_versions() {
+        let mut db = get_repo("metadata");
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+
+        assert_eq!(
+            router::RouterError::NotFound,
+            db.set_meta("keepass", "pdb.kdbx", "device", "phone")
+                .unwrap_err()
+        );
+
+        db.save("keepass", "pdb.kdbx", &vec![1], None, &address)
+            .unwrap();
+        db.set_meta("keepass", "pdb.kdbx", "device", "phone")
+            .unwrap();
+        db.set_meta("keepass", "pdb.kdbx", "synced-from", "laptop")
+            .unwrap();
+        db.set_meta("keepass", "pdb.kdbx", "device", "tablet")
+            .unwrap();
+        db.save("keepass", "pdb.kdbx", &vec![2], Some(0), &address)
+            .unwrap();
+
+        let metadata = db.get_meta_map("keepass", "pdb.kdbx").unwrap();
+
+        assert_eq!(
+            vec![
+                (String::from("device"), String::from("tablet")),
+                (String::from("synced-from"), String::from("laptop")),
+            ],
+            metadata.into_iter().collect::<Vec<_>>()
+        );
+
+        db.delete_meta("keepass", "pdb.kdbx", "device").unwrap();
+
+        assert_eq!(
+            router::RouterError::NotFound,
+            db.delete_meta("keepass", "pdb.kdbx", "device").unwrap_err()
+        );
+        assert_eq!(
+            vec!["synced-from"],
+            db.get_meta_map("keepass", "pdb.kdbx")
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>()
+        );
+        assert!(db.get_meta_map("keepass", "other").unwrap().is_empty());
+    }
+
     #[test]
     fn it_allows_saving_and_resaving() {
         let mut db = get_repo("saving");
@@ -629,7 +2048,14 @@ mod tests {
         db.save(file_path, file_name, &file_data_1, None, &address)
             .unwrap();
 
-        db.delete(file_path, file_name, 0, &address).unwrap();
+        db.delete(
+            file_path,
+            file_name,
+            0,
+            &address,
+            &HistoryMetadata::default(),
+        )
+        .unwrap();
 
         let error = db.get(file_path, file_name, true).unwrap_err();
 
@@ -656,6 +2082,7 @@ mod tests {
             file_path_to,
             file_name_to,
             &address,
+            &HistoryMetadata::default(),
         )
         .unwrap();
 
@@ -668,6 +2095,160 @@ mod tests {
         assert_eq!(file_data, saved_data.file.unwrap());
     }
 
+    #[test]
+    fn it_never_moves_onto_a_concurrently_created_destination() {
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+        let root_path = std::path::PathBuf::from(TEST_PATH).join("concurrent_move");
+
+        for i in 0..20 {
+            let mut db = get_repo("concurrent_move");
+            db.save("from", "file", &vec![1], None, &address).unwrap();
+            // a second connection to the same database, as another process would have
+            let mut other_db = FilesDB::new(&root_path).unwrap();
+
+            let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+            let move_barrier = barrier.clone();
+            let moving = std::thread::spawn(move || {
+                move_barrier.wait();
+                db.move_to(
+                    "from",
+                    "file",
+                    0,
+                    "to",
+                    "file",
+                    &address,
+                    &HistoryMetadata::default(),
+                )
+                .is_ok()
+            });
+            let creating = std::thread::spawn(move || {
+                barrier.wait();
+                other_db
+                    .save("to", "file", &vec![2], None, &address)
+                    .is_ok()
+            });
+            let moved = moving.join().unwrap();
+            let created = creating.join().unwrap();
+
+            assert!(!(moved && created), "iteration {}", i);
+
+            let db = FilesDB::new(&root_path).unwrap();
+            if moved {
+                assert_eq!(vec![1], db.get("to", "file", true).unwrap().file.unwrap());
+                assert!(db.get("from", "file", false).is_err());
+            } else {
+                assert_eq!(0, db.get("from", "file", false).unwrap().version);
+            }
+        }
+    }
+
+    #[test]
+    fn it_allows_swapping() {
+        let mut db = get_repo("swapping");
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+        let file_data_current = std::vec::Vec::from("CURRENT".as_bytes());
+        let file_data_staging = std::vec::Vec::from("STAGING".as_bytes());
+
+        db.save("conf", "current", &file_data_current, None, &address)
+            .unwrap();
+        db.save("conf", "staging", &file_data_staging, None, &address)
+            .unwrap();
+        db.save("conf", "staging", &file_data_staging, Some(0), &address)
+            .unwrap();
+
+        db.swap(
+            "conf",
+            "current",
+            "conf",
+            "staging",
+            &address,
+            &HistoryMetadata::default(),
+        )
+        .unwrap();
+
+        let current = db.get("conf", "current", true).unwrap();
+        assert_eq!(file_data_staging, current.file.unwrap());
+        assert_eq!(1, current.version);
+        let staging = db.get("conf", "staging", true).unwrap();
+        assert_eq!(file_data_current, staging.file.unwrap());
+        assert_eq!(2, staging.version);
+
+        let history = db.get_history("conf", "current").unwrap();
+        assert!(matches!(
+            history.entries.last().unwrap().entry,
+            crate::log::FileLogEntryType::Swap { version: 1, ref path_with, .. }
+                if path_with == &std::path::PathBuf::from("conf/staging")
+        ));
+        let history = db.get_history("conf", "staging").unwrap();
+        assert!(matches!(
+            history.entries.last().unwrap().entry,
+            crate::log::FileLogEntryType::Swap { version: 2, ref path_with, .. }
+                if path_with == &std::path::PathBuf::from("conf/current")
+        ));
+
+        let error = db
+            .swap(
+                "conf",
+                "current",
+                "conf",
+                "missing",
+                &address,
+                &HistoryMetadata::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(error, router::RouterError::HandlerError(404, _)));
+    }
+
+    #[test]
+    fn it_reads_from_the_replica() {
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+        let file_data = std::vec::Vec::from("SOME_DATA".as_bytes());
+        let replica_path = std::path::PathBuf::from(TEST_PATH).join("replica.db3");
+        let mut db = get_repo("replica");
+
+        db.save("test/path", "file_1", &file_data, None, &address)
+            .unwrap();
+        db.backup(&replica_path).unwrap();
+
+        let replica = ReadReplica::open(&replica_path).unwrap();
+
+        assert_eq!(
+            file_data,
+            replica
+                .get("test/path", "file_1", true)
+                .unwrap()
+                .file
+                .unwrap()
+        );
+
+        db.save("test/path", "file_2", &file_data, None, &address)
+            .unwrap();
+
+        // the replica has not been synced yet
+        let error = replica.get("test/path", "file_2", true).unwrap_err();
+        assert!(matches!(error, router::RouterError::HandlerError(404, _)));
+        assert!(db.get("test/path", "file_2", true).is_ok());
+
+        db.backup(&replica_path).unwrap();
+
+        assert_eq!(
+            file_data,
+            replica
+                .get("test/path", "file_2", true)
+                .unwrap()
+                .file
+                .unwrap()
+        );
+        assert_eq!(
+            1,
+            replica
+                .get_history("test/path", "file_2")
+                .unwrap()
+                .entries
+                .len()
+        );
+    }
+
     #[test]
     fn it_tracks_history() {
         let mut db = get_repo("history");
@@ -685,8 +2266,22 @@ mod tests {
             .unwrap();
         db.save(file_path, file_name, &file_data_2, Some(0), &address)
             .unwrap();
-        db.delete(file_path, file_name, 1, &address).unwrap();
-        db.delete(file_path, file_name, 1, &address).unwrap_err(); // we delete twice to check the second time does not add anything in history
+        db.delete(
+            file_path,
+            file_name,
+            1,
+            &address,
+            &HistoryMetadata::default(),
+        )
+        .unwrap();
+        db.delete(
+            file_path,
+            file_name,
+            1,
+            &address,
+            &HistoryMetadata::default(),
+        )
+        .unwrap_err(); // we delete twice to check the second time does not add anything in history
         db.save(file_path, file_name, &file_data_3, None, &address)
             .unwrap();
         db.move_to(
@@ -696,6 +2291,7 @@ mod tests {
             file_path_to,
             file_name_to,
             &address,
+            &HistoryMetadata::default(),
         )
         .unwrap();
         db.save(file_path, file_name, &file_data_4, None, &address)
@@ -755,6 +2351,158 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_pages_through_the_history() {
+        let mut db = get_repo("history_pages");
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+        db.save("keepass", "pdb.kdbx", &vec![0], None, &address)
+            .unwrap();
+        for version in 0..4 {
+            db.save("keepass", "pdb.kdbx", &vec![1], Some(version), &address)
+                .unwrap();
+        }
+
+        let versions = |after_version| -> Vec<u32> {
+            db.get_history_after("keepass", "pdb.kdbx", after_version, 2)
+                .unwrap()
+                .entries
+                .iter()
+                .map(|entry| entry.entry.version())
+                .collect()
+        };
+
+        assert_eq!(vec![0, 1], versions(None));
+        assert_eq!(vec![2, 3], versions(Some(1)));
+        assert_eq!(vec![4], versions(Some(3)));
+        assert!(versions(Some(4)).is_empty());
+        assert!(matches!(
+            db.get_history_after("keepass", "other", None, 2),
+            Err(router::RouterError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn it_gets_the_old_versions() {
+        let mut db = get_repo("get_version");
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+        db.save("keepass", "pdb.kdbx", &vec![1, 2, 3], None, &address)
+            .unwrap();
+        db.save("keepass", "pdb.kdbx", &vec![4], Some(0), &address)
+            .unwrap();
+        db.delete(
+            "keepass",
+            "pdb.kdbx",
+            1,
+            &address,
+            &HistoryMetadata::default(),
+        )
+        .unwrap();
+
+        let old = db.get_version("keepass", "pdb.kdbx", 0).unwrap();
+
+        assert_eq!(0, old.version);
+        assert_eq!(Some(vec![1, 2, 3]), old.file);
+        assert_eq!(Some(digest(&vec![1, 2, 3])), old.hash);
+        assert_eq!(
+            Some(vec![4]),
+            db.get_version("keepass", "pdb.kdbx", 1).unwrap().file
+        );
+        for version in [2, 3] {
+            let result = db.get_version("keepass", "pdb.kdbx", version);
+
+            assert!(matches!(result, Err(router::HandlerError(404, _))));
+        }
+
+        let mut db = get_repo("get_version_tombstone").with_delete_as_tombstone(true);
+        db.save("keepass", "pdb.kdbx", &vec![1, 2, 3], None, &address)
+            .unwrap();
+        db.delete(
+            "keepass",
+            "pdb.kdbx",
+            0,
+            &address,
+            &HistoryMetadata::default(),
+        )
+        .unwrap();
+
+        assert!(db.get_version("keepass", "pdb.kdbx", 0).is_ok());
+        assert!(matches!(
+            db.get_version("keepass", "pdb.kdbx", 1),
+            Err(router::HandlerError(404, _))
+        ));
+    }
+
+    #[test]
+    fn it_only_retains_the_latest_versions() {
+        let mut db = get_repo("retained_versions").with_max_retained_versions(Some(3));
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+        db.save("keepass", "pdb.kdbx", &vec![0], None, &address)
+            .unwrap();
+        for version in 0..9 {
+            db.save("keepass", "pdb.kdbx", &vec![1], Some(version), &address)
+                .unwrap();
+        }
+
+        let history = db.get_history("keepass", "pdb.kdbx").unwrap();
+        let versions: Vec<u32> = history
+            .entries
+            .iter()
+            .map(|entry| entry.entry.version())
+            .collect();
+
+        assert_eq!(vec![7, 8, 9], versions);
+        assert_eq!(9, db.get("keepass", "pdb.kdbx", false).unwrap().version);
+    }
+
+    #[test]
+    fn it_purges_all_the_versions() {
+        let mut db = get_repo("purge");
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+        db.save("keepass", "pdb.kdbx", &vec![0], None, &address)
+            .unwrap();
+        for version in 0..3 {
+            db.save("keepass", "pdb.kdbx", &vec![1], Some(version), &address)
+                .unwrap();
+        }
+        db.set_meta("keepass", "pdb.kdbx", "device", "phone")
+            .unwrap();
+        db.save("keepass", "other.kdbx", &vec![2], None, &address)
+            .unwrap();
+
+        assert_eq!(
+            router::RouterError::HandlerError(412, String::from("Version mismatch")),
+            db.purge("keepass", "pdb.kdbx", 2, &address).unwrap_err()
+        );
+        assert_eq!(4, db.purge("keepass", "pdb.kdbx", 3, &address).unwrap());
+        assert!(matches!(
+            db.get_history("keepass", "pdb.kdbx"),
+            Err(router::RouterError::NotFound)
+        ));
+        assert!(db.get("keepass", "pdb.kdbx", false).is_err());
+        assert!(db.get_meta_map("keepass", "pdb.kdbx").unwrap().is_empty());
+        assert_eq!(
+            FileStatus::Unknown,
+            db.get_status("keepass", "pdb.kdbx").unwrap()
+        );
+        assert_eq!(
+            1,
+            db.get_history("keepass", "other.kdbx")
+                .unwrap()
+                .entries
+                .len()
+        );
+        assert_eq!(
+            router::RouterError::NotFound,
+            db.purge("keepass", "pdb.kdbx", 3, &address).unwrap_err()
+        );
+
+        let saved = db
+            .save("keepass", "pdb.kdbx", &vec![3], None, &address)
+            .unwrap();
+
+        assert_eq!(0, saved.version);
+    }
+
     #[test]
     fn it_prevents_deleting_when_version_is_wrong() {
         let mut db = get_repo("version_delete");
@@ -768,7 +2516,15 @@ mod tests {
             .unwrap();
         db.save(file_path, file_name, &file_data_2, Some(0), &address)
             .unwrap();
-        let error = db.delete(file_path, file_name, 0, &address).unwrap_err();
+        let error = db
+            .delete(
+                file_path,
+                file_name,
+                0,
+                &address,
+                &HistoryMetadata::default(),
+            )
+            .unwrap_err();
 
         assert!(matches!(error, router::RouterError::HandlerError(412, _)));
     }
@@ -811,7 +2567,15 @@ mod tests {
             .unwrap();
 
         let error = db
-            .move_to(file_path, file_name, 1, file_path, "test_to", &address)
+            .move_to(
+                file_path,
+                file_name,
+                1,
+                file_path,
+                "test_to",
+                &address,
+                &HistoryMetadata::default(),
+            )
             .unwrap_err();
 
         assert!(matches!(error, router::RouterError::HandlerError(412, _)));
@@ -832,9 +2596,67 @@ mod tests {
             .unwrap();
 
         let error = db
-            .move_to(file_path, file_name, 0, file_path, file_name_to, &address)
+            .move_to(
+                file_path,
+                file_name,
+                0,
+                file_path,
+                file_name_to,
+                &address,
+                &HistoryMetadata::default(),
+            )
             .unwrap_err();
 
         assert!(matches!(error, router::RouterError::HandlerError(412, _)));
     }
+
+    #[test]
+    fn it_refuses_writes_beyond_the_maximum_size() {
+        // each saved file is stored twice: in the history and as current version
+        let mut db = get_repo("max_size").with_max_total_bytes(Some(100));
+        let address = std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1));
+        let file_path = "test/path";
+        let file_data_1 = std::vec::Vec::from([0u8; 40].as_ref());
+        let file_data_2 = std::vec::Vec::from([0u8; 20].as_ref());
+
+        db.save(file_path, "file_1", &file_data_1, None, &address)
+            .unwrap();
+
+        assert_eq!(80, db.stats().unwrap().total_bytes);
+
+        let error = db
+            .save(file_path, "file_2", &file_data_2, None, &address)
+            .unwrap_err();
+
+        assert!(matches!(error, router::RouterError::HandlerError(507, _)));
+
+        let error = db
+            .move_to(
+                file_path,
+                "file_1",
+                0,
+                file_path,
+                "file_3",
+                &address,
+                &HistoryMetadata::default(),
+            )
+            .unwrap_err();
+
+        assert!(matches!(error, router::RouterError::HandlerError(507, _)));
+
+        // deleting the file frees its current version
+        db.delete(
+            file_path,
+            "file_1",
+            0,
+            &address,
+            &HistoryMetadata::default(),
+        )
+        .unwrap();
+
+        db.save(file_path, "file_2", &file_data_2, None, &address)
+            .unwrap();
+
+        assert_eq!(80, db.stats().unwrap().total_bytes);
+    }
 }
@@ -1,34 +1,448 @@
+use crate::db::FilesReader;
 use std::convert::TryInto;
 
 /// Handler that takes care of DELETE requests
 pub struct DeleteFileHandler {
     pub file_repo: std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
     pub matcher: Box<dyn router::matcher::Matcher>,
+    /// Status of the answer to the requests without a version, 428 unless the clients need a 400
+    pub missing_version_status: u16,
 }
 
 /// Handler that takes care of GET requests
 pub struct GetFileHandler {
     pub file_repo: std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
+    /// Replica the reads are done on, if configured, without waiting for the writes on the
+    /// primary database
+    pub read_replica: Option<std::sync::Arc<std::sync::Mutex<crate::db::ReadReplica>>>,
     pub matcher: Box<dyn router::matcher::Matcher>,
+    pub coalescer: ReadCoalescer,
+}
+
+/// Makes the concurrent GET of the same file share a single read of the database
+///
+/// The read runs in its own blocking task, so it completes even if the request that started it
+/// is cancelled. It is forgotten before the lock of the database is released, so a request
+/// arriving after a write always starts a new read.
+#[derive(Default)]
+pub struct ReadCoalescer {
+    in_flight: std::sync::Arc<std::sync::Mutex<InFlightReads>>,
+    started_reads: std::sync::atomic::AtomicU64,
+}
+
+#[derive(Default)]
+struct InFlightReads {
+    next_id: u64,
+    reads: std::collections::HashMap<(String, String), (u64, SharedRead)>,
+}
+
+type SharedRead = futures::future::Shared<
+    futures::future::BoxFuture<'static, Result<FileRead, router::RouterError>>,
+>;
+
+/// File read from the database, the content is shared by the responses without being copied
+#[derive(Clone)]
+struct FileRead {
+    tombstone: bool,
+    data: std::sync::Arc<crate::db::FilesDbResponse>,
+    content: Option<FileContent>,
+}
+
+/// Content of a file, the big ones being streamed instead of being read at once
+#[derive(Clone)]
+enum FileContent {
+    Buffered(hyper::body::Bytes),
+    Streamed(crate::db::BlobLocation),
+}
+
+impl FileContent {
+    fn len(&self) -> usize {
+        match self {
+            FileContent::Buffered(bytes) => bytes.len(),
+            FileContent::Streamed(location) => location.size,
+        }
+    }
 }
 
 /// Handler that takes care of MOVE requests
 pub struct MoveFileHandler {
     pub file_repo: std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
     pub matcher: Box<dyn router::matcher::Matcher>,
+    /// Status of the answer to the requests without a version, 428 unless the clients need a 400
+    pub missing_version_status: u16,
+}
+
+/// Handler that takes care of SWAP requests, exchanging the content with the destination
+pub struct SwapFileHandler {
+    pub file_repo: std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
+    pub matcher: Box<dyn router::matcher::Matcher>,
 }
 
 /// Handler that takes care of PUT requests
 pub struct PutFileHandler {
     pub file_repo: std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
     pub matcher: Box<dyn router::matcher::Matcher>,
+    /// Bodies bigger than this are spooled to a temporary file while being received
+    pub spool_threshold_bytes: Option<usize>,
+    /// Uploads of files with the given extensions must start with the associated bytes
+    pub magic_rules: Vec<(String, Vec<u8>)>,
+    /// Limits the number of uploads handled at the same time, the others are refused with a 503
+    pub upload_permits: Option<std::sync::Arc<tokio::sync::Semaphore>>,
+    /// Bodies bigger than this are refused with a 413, whether declared or actually received
+    pub max_body_bytes: u64,
 }
 
 pub struct FileVersionsHandler {
+    pub file_repo: std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
+    /// Replica the reads are done on, if configured, without waiting for the writes on the
+    /// primary database
+    pub read_replica: Option<std::sync::Arc<std::sync::Mutex<crate::db::ReadReplica>>>,
+    pub matcher: Box<dyn router::matcher::Matcher>,
+    /// Timezone in which the timestamps are rendered, UTC if not given
+    pub timezone: Option<chrono_tz::Tz>,
+}
+
+/// Handler that takes care of the metadata of the files, one key at a time
+///
+/// PUT sets the key to the body of the request, GET sends its value and DELETE removes it.
+pub struct FileMetadataHandler {
+    pub file_repo: std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
+    /// Replica the reads are done on, if configured, without waiting for the writes on the
+    /// primary database
+    pub read_replica: Option<std::sync::Arc<std::sync::Mutex<crate::db::ReadReplica>>>,
+    pub matcher: Box<dyn router::matcher::Matcher>,
+}
+
+/// Handler that sends a snapshot of the whole database
+pub struct BackupHandler {
     pub file_repo: std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
     pub matcher: Box<dyn router::matcher::Matcher>,
 }
 
+/// Root under which the files of the tenant of the request are stored
+#[derive(Clone, Debug)]
+pub struct TenantRoot(pub String);
+
+/// Wraps a file handler to isolate the tenants identified by their API key
+///
+/// The key is read from an `Authorization: Bearer <key>` header, and the associated root is given
+/// to the wrapped handler as a [TenantRoot] extension. Requests without a known key get a 401.
+pub struct TenantHandler {
+    pub handler: Box<dyn router::Handler>,
+    /// Tenant roots by API key
+    pub api_keys: std::sync::Arc<std::collections::HashMap<String, String>>,
+}
+
+/// Wraps a maintenance handler to only let through the requests giving the admin key
+///
+/// The key is read from an `Authorization: Bearer <key>` header, all the requests get a 401 if no
+/// admin key is configured.
+pub struct AdminHandler {
+    pub handler: Box<dyn router::Handler>,
+    pub admin_key: Option<String>,
+}
+
+/// Wraps a file handler to refuse the paths, and destinations, that are nested too deeply
+pub struct PathDepthHandler {
+    pub handler: Box<dyn router::Handler>,
+    pub max_path_depth: usize,
+}
+
+/// Wraps a file handler to replace the backslashes of the path, and destination, by slashes
+pub struct NormalizeBackslashesHandler {
+    pub handler: Box<dyn router::Handler>,
+}
+
+/// Temporary file removed when dropped
+struct TempFile(std::path::PathBuf);
+
+impl TempFile {
+    fn new(prefix: &str) -> TempFile {
+        TempFile(std::env::temp_dir().join(format!(
+            "{}-{}-{}.tmp",
+            prefix,
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos()
+        )))
+    }
+}
+
+impl std::ops::Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove temporary file {:?}: {:?}", self.0, e);
+            }
+        }
+    }
+}
+
+fn body_too_large(max_body_bytes: u64) -> router::RouterError {
+    log::warn!("Refusing upload bigger than {} bytes", max_body_bytes);
+    router::HandlerError(
+        413,
+        format!(
+            "Content is bigger than the {} bytes allowed",
+            max_body_bytes
+        ),
+    )
+}
+
+/// Reads the whole body, spooling it to a temporary file once it exceeds the threshold
+///
+/// The reading stops with a 413 as soon as more than `max_body_bytes` are received
+async fn read_body(
+    mut body: hyper::Body,
+    spool_threshold_bytes: Option<usize>,
+    max_body_bytes: u64,
+) -> Result<Vec<u8>, router::RouterError> {
+    use hyper::body::HttpBody;
+    use tokio::io::AsyncWriteExt;
+
+    let spool_error = |e: &std::io::Error| super::map_error(e, "Failed to spool content", 500);
+
+    let mut content = Vec::new();
+    let mut spool: Option<(TempFile, tokio::fs::File)> = None;
+    let mut received_bytes: u64 = 0;
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| super::map_error(&e, "Invalid content", 400))?;
+        received_bytes += chunk.len() as u64;
+        if received_bytes > max_body_bytes {
+            return Err(body_too_large(max_body_bytes));
+        }
+        match &mut spool {
+            Some((_, file)) => file.write_all(&chunk).await.map_err(|e| spool_error(&e))?,
+            None => {
+                content.extend_from_slice(&chunk);
+                if matches!(spool_threshold_bytes, Some(threshold) if content.len() > threshold) {
+                    let temp_file = TempFile::new("file_upload");
+                    log::debug!("Spooling upload to {:?}", temp_file.0);
+                    let mut file = tokio::fs::File::create(&temp_file.0)
+                        .await
+                        .map_err(|e| spool_error(&e))?;
+                    file.write_all(&content)
+                        .await
+                        .map_err(|e| spool_error(&e))?;
+                    content = Vec::new();
+                    spool = Some((temp_file, file));
+                }
+            }
+        }
+    }
+
+    if let Some((temp_file, mut file)) = spool {
+        file.flush().await.map_err(|e| spool_error(&e))?;
+        drop(file);
+        content = tokio::fs::read(&temp_file.0)
+            .await
+            .map_err(|e| spool_error(&e))?;
+    }
+
+    Ok(content)
+}
+
+/// Key given in the `Authorization: Bearer <key>` header of the request
+fn get_bearer_key(request: &hyper::Request<hyper::Body>) -> Option<&str> {
+    request
+        .headers()
+        .get("authorization")
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .map(|key| key.trim())
+}
+
+#[async_trait::async_trait]
+impl router::Handler for TenantHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        self.handler.get_matcher()
+    }
+
+    async fn handle(
+        &self,
+        mut request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        let tenant_root = get_bearer_key(&request)
+            .and_then(|key| self.api_keys.get(key))
+            .ok_or(router::HandlerError(401, String::from("Invalid API key")))?;
+        request
+            .extensions_mut()
+            .insert(TenantRoot(tenant_root.to_owned()));
+        self.handler.handle(request).await
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        self.handler.get_timeout()
+    }
+
+    fn name(&self) -> &str {
+        self.handler.name()
+    }
+}
+
+#[async_trait::async_trait]
+impl router::Handler for AdminHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        self.handler.get_matcher()
+    }
+
+    async fn handle(
+        &self,
+        request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        match (get_bearer_key(&request), &self.admin_key) {
+            (Some(key), Some(admin_key)) if key == admin_key => self.handler.handle(request).await,
+            _ => Err(router::HandlerError(401, String::from("Invalid admin key"))),
+        }
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        self.handler.get_timeout()
+    }
+
+    fn name(&self) -> &str {
+        self.handler.name()
+    }
+}
+
+#[async_trait::async_trait]
+impl router::Handler for PathDepthHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        self.handler.get_matcher()
+    }
+
+    async fn handle(
+        &self,
+        request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        super::check_path_depth(request.uri(), self.max_path_depth)?;
+        let destination = request
+            .headers()
+            .get("destination")
+            .and_then(|destination| destination.to_str().ok())
+            .and_then(|destination| http::Uri::try_from(destination).ok());
+        if let Some(destination) = destination {
+            super::check_path_depth(&destination, self.max_path_depth)?;
+        }
+        self.handler.handle(request).await
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        self.handler.get_timeout()
+    }
+
+    fn name(&self) -> &str {
+        self.handler.name()
+    }
+}
+
+#[async_trait::async_trait]
+impl router::Handler for NormalizeBackslashesHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        self.handler.get_matcher()
+    }
+
+    async fn handle(
+        &self,
+        mut request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        *request.uri_mut() = super::normalize_backslashes(request.uri())?;
+        let destination = request
+            .headers()
+            .get("destination")
+            .and_then(|destination| destination.to_str().ok())
+            .and_then(|destination| http::Uri::try_from(destination).ok());
+        if let Some(destination) = destination {
+            let destination = super::normalize_backslashes(&destination)?;
+            request.headers_mut().insert(
+                "destination",
+                http::HeaderValue::try_from(destination.to_string())
+                    .map_err(|e| super::map_error(&e, "Invalid destination", 400))?,
+            );
+        }
+        self.handler.handle(request).await
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        self.handler.get_timeout()
+    }
+
+    fn name(&self) -> &str {
+        self.handler.name()
+    }
+}
+
+/// Guesses the content type of a file from its extension, for the files saved without one
+fn guess_content_type(file_name: &str) -> &'static str {
+    let extension = match file_name.rsplit_once('.') {
+        Some((_, extension)) => extension.to_ascii_lowercase(),
+        None => String::new(),
+    };
+    match extension.as_str() {
+        "txt" => "text/plain",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Content type saved with the file, or guessed from its name if it was saved without one
+fn get_content_type(data: &crate::db::FilesDbResponse, file_name: &str) -> String {
+    match &data.content_type {
+        Some(content_type) => content_type.to_owned(),
+        None => String::from(guess_content_type(file_name)),
+    }
+}
+
+/// Reads the details of a mutation recorded in the history from the request headers
+fn get_history_metadata(headers: &hyper::HeaderMap) -> crate::db::HistoryMetadata {
+    let get_header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    };
+    crate::db::HistoryMetadata {
+        user_agent: get_header(hyper::header::USER_AGENT.as_str()),
+        comment: get_header("x-change-comment"),
+    }
+}
+
+/// Version sent in the ETag header, without which the existing files cannot be modified
+fn get_required_version(
+    headers: &http::HeaderMap,
+    missing_version_status: u16,
+) -> Result<i32, router::RouterError> {
+    let (version, _timestamp) = super::get_version_info_from_headers(headers);
+    version.ok_or_else(|| {
+        router::HandlerError(
+            missing_version_status,
+            String::from("Missing version: the current version must be sent in the ETag header"),
+        )
+    })
+}
+
+/// Reads the X-Min-Version header, the lowest version the client accepts to read
+fn get_min_version(headers: &http::HeaderMap) -> Result<Option<i32>, router::RouterError> {
+    headers
+        .get("x-min-version")
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| {
+                    router::HandlerError(400, String::from("Invalid X-Min-Version header"))
+                })
+        })
+        .transpose()
+}
+
 fn get_response_builder(data: &crate::db::FilesDbResponse, status: u16) -> http::response::Builder {
     hyper::Response::builder()
         .status(status)
@@ -46,21 +460,35 @@ impl router::Handler for DeleteFileHandler {
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
-        let (file_path, file_name) = crate::get_path_and_name_from_uri(&request.uri())?;
-        let (version, _timestamp) = super::get_version_info_from_headers(&request.headers());
-        let version = version.ok_or(router::HandlerError(400, String::from("Missing version")))?;
+        let (file_path, file_name) =
+            crate::get_tenant_path_and_name(request.uri(), request.extensions())?;
+
+        let version = get_required_version(request.headers(), self.missing_version_status)?;
+        let address = request
+            .extensions()
+            .get::<std::net::SocketAddr>()
+            .unwrap_or(&DEFAULT_SOCK_ADDRESS)
+            .ip();
 
         let mut repo = self.file_repo.lock().unwrap();
 
+        let purge = form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes())
+            .any(|(param, value)| param == "purge" && value == "true");
+        if purge {
+            let purged = repo.purge(file_path.as_ref(), file_name.as_ref(), version, &address)?;
+            log::info!("Purged {} versions of {}/{}", purged, file_path, file_name);
+            return Ok(hyper::Response::builder()
+                .status(204)
+                .body(hyper::Body::empty())
+                .unwrap());
+        }
+
         let data = repo.delete(
             file_path.as_ref(),
             file_name.as_ref(),
             version,
-            &request
-                .extensions()
-                .get::<std::net::SocketAddr>()
-                .unwrap_or(&DEFAULT_SOCK_ADDRESS)
-                .ip(),
+            &address,
+            &get_history_metadata(request.headers()),
         )?;
 
         Ok(get_response_builder(&data, 204)
@@ -68,8 +496,158 @@ impl router::Handler for DeleteFileHandler {
             .unwrap())
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(1)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
+    }
+}
+
+impl ReadCoalescer {
+    /// Reads the file, or joins the read of the same file that is already in progress
+    fn read(
+        &self,
+        file_repo: &std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
+        read_replica: &Option<std::sync::Arc<std::sync::Mutex<crate::db::ReadReplica>>>,
+        file_path: &str,
+        file_name: &str,
+    ) -> SharedRead {
+        use futures::FutureExt;
+
+        let key = (file_path.to_owned(), file_name.to_owned());
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some((_, read)) = in_flight.reads.get(&key) {
+            log::debug!(
+                "Joining the read of {}/{} in progress",
+                file_path,
+                file_name
+            );
+            return read.clone();
+        }
+
+        let id = in_flight.next_id;
+        in_flight.next_id += 1;
+        let started_reads = self
+            .started_reads
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        log::debug!(
+            "Starting read {} of {}/{}",
+            started_reads,
+            file_path,
+            file_name
+        );
+
+        let file_repo = file_repo.clone();
+        let read_replica = read_replica.clone();
+        let reads = self.in_flight.clone();
+        let task_key = key.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            read_with(&file_repo, &read_replica, |repo| {
+                let read = read_file(repo, &task_key.0, &task_key.1);
+                let mut reads = reads.lock().unwrap();
+                if matches!(reads.reads.get(&task_key), Some((read_id, _)) if *read_id == id) {
+                    reads.reads.remove(&task_key);
+                }
+                read
+            })
+        });
+        let read = async move {
+            task.await
+                .unwrap_or_else(|e| Err(super::map_error(&e, "Failed to read file", 500)))
+        }
+        .boxed()
+        .shared();
+        in_flight.reads.insert(key, (id, read.clone()));
+        read
+    }
+}
+
+/// Runs the read on the replica if one is configured, without locking the primary database, and
+/// on the primary database otherwise
+fn read_with<T>(
+    file_repo: &std::sync::Arc<std::sync::Mutex<crate::db::FilesDB>>,
+    read_replica: &Option<std::sync::Arc<std::sync::Mutex<crate::db::ReadReplica>>>,
+    read: impl FnOnce(&dyn FilesReader) -> T,
+) -> T {
+    match read_replica {
+        Some(read_replica) => read(&*read_replica.lock().unwrap()),
+        None => read(&*file_repo.lock().unwrap()),
+    }
+}
+
+/// Checks the status of the file and reads it with its content
+fn read_file(
+    repo: &dyn FilesReader,
+    file_path: &str,
+    file_name: &str,
+) -> Result<FileRead, router::RouterError> {
+    let tombstone = get_tombstone(repo, file_path, file_name)?;
+    let location = repo.get_blob_location(file_path, file_name)?;
+    if location.size > crate::db::STREAM_CHUNK_BYTES {
+        return Ok(FileRead {
+            tombstone,
+            data: std::sync::Arc::new(repo.get(file_path, file_name, false)?),
+            content: Some(FileContent::Streamed(location)),
+        });
+    }
+    let mut data = repo.get(file_path, file_name, true)?;
+    let content = data
+        .file
+        .take()
+        .map(|file| FileContent::Buffered(hyper::body::Bytes::from(file)));
+    Ok(FileRead {
+        tombstone,
+        data: std::sync::Arc::new(data),
+        content,
+    })
+}
+
+/// Checks the status of the file and reads its version, timestamp and hash
+fn read_metadata(
+    repo: &dyn FilesReader,
+    file_path: &str,
+    file_name: &str,
+) -> Result<FileRead, router::RouterError> {
+    Ok(FileRead {
+        tombstone: get_tombstone(repo, file_path, file_name)?,
+        data: std::sync::Arc::new(repo.get(file_path, file_name, false)?),
+        content: None,
+    })
+}
+
+/// Tells whether the file is a tombstone, refusing the files that are deleted or unknown
+fn get_tombstone(
+    repo: &dyn FilesReader,
+    file_path: &str,
+    file_name: &str,
+) -> Result<bool, router::RouterError> {
+    match repo.get_status(file_path, file_name)? {
+        crate::db::FileStatus::Present => Ok(false),
+        crate::db::FileStatus::Tombstone => Ok(true),
+        crate::db::FileStatus::Deleted => {
+            Err(router::HandlerError(410, String::from("File deleted")))
+        }
+        crate::db::FileStatus::Unknown => {
+            Err(router::HandlerError(404, String::from("File not found")))
+        }
+    }
+}
+
+impl GetFileHandler {
+    fn get_body(&self, content: FileContent, range: std::ops::Range<usize>) -> hyper::Body {
+        match content {
+            FileContent::Buffered(bytes) => hyper::Body::from(bytes.slice(range)),
+            FileContent::Streamed(location) => match &self.read_replica {
+                Some(read_replica) => hyper::Body::wrap_stream(crate::db::get_stream(
+                    read_replica.clone(),
+                    location,
+                    range,
+                )),
+                None => hyper::Body::wrap_stream(crate::db::get_stream(
+                    self.file_repo.clone(),
+                    location,
+                    range,
+                )),
+            },
+        }
     }
 }
 
@@ -83,36 +661,117 @@ impl router::Handler for GetFileHandler {
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
-        let (file_path, file_name) = crate::get_path_and_name_from_uri(&request.uri())?;
+        let (file_path, file_name) =
+            crate::get_tenant_path_and_name(request.uri(), request.extensions())?;
 
         let is_get = request.method() == http::Method::GET;
 
-        let repo = self.file_repo.lock().unwrap();
-
-        let data = repo.get(file_path.as_ref(), file_name.as_ref(), is_get)?;
+        // the content is only read once it is known that the client does not already have it
+        let if_none_match = super::get_version_from_header(request.headers(), "if-none-match");
+        let min_version = get_min_version(request.headers())?;
+        let metadata = if !is_get || if_none_match.is_some() || min_version.is_some() {
+            Some(read_with(&self.file_repo, &self.read_replica, |repo| {
+                read_metadata(repo, file_path.as_ref(), file_name.as_ref())
+            })?)
+        } else {
+            None
+        };
 
-        if is_get {
-            log::info!(
-                "Sending file with size {}",
-                &data.file.as_ref().unwrap().len()
-            );
+        if let Some(FileRead { data, .. }) = &metadata {
+            if let Some(min_version) = min_version.filter(|min_version| data.version < *min_version)
+            {
+                return Ok(get_response_builder(data, 412)
+                    .body(hyper::Body::from(format!(
+                        "Current version {} is below the minimum version {}",
+                        data.version, min_version
+                    )))
+                    .unwrap());
+            }
+            if if_none_match == Some(data.version) {
+                return Ok(get_response_builder(data, 304)
+                    .body(hyper::Body::empty())
+                    .unwrap());
+            }
         }
 
-        Ok(get_response_builder(&data, 200)
+        // the lock is only held while the row is copied out of the database, the response is
+        // built once it is released so that the other requests are not serialized behind it
+        let FileRead {
+            tombstone,
+            data,
+            content,
+        } = match metadata {
+            Some(metadata) if !is_get => metadata,
+            _ => {
+                self.coalescer
+                    .read(
+                        &self.file_repo,
+                        &self.read_replica,
+                        file_path.as_ref(),
+                        file_name.as_ref(),
+                    )
+                    .await?
+            }
+        };
+
+        let mut builder = get_response_builder(&data, 200)
+            .header("content-type", get_content_type(&data, &file_name))
+            .header("accept-ranges", "bytes")
             .header(
                 "content-disposition",
                 format!("attachment; filename=\"{}\"", file_name),
-            )
-            .body(if is_get {
-                hyper::Body::from(data.file.unwrap())
-            } else {
-                hyper::Body::empty()
-            })
-            .unwrap())
+            );
+
+        if let Some(hash) = &data.hash {
+            builder = builder.header("x-content-sha256", hash);
+        }
+
+        if tombstone {
+            builder = builder.header("x-deleted", "true");
+        }
+
+        if !is_get {
+            return Ok(builder.body(hyper::Body::empty()).unwrap());
+        }
+
+        let content = content.unwrap();
+        let length = content.len();
+
+        match crate::get_requested_range(request.headers(), data.version, length) {
+            crate::RequestedRange::Full => {
+                log::info!("Sending file with size {}", length);
+                Ok(builder
+                    .header("content-length", length)
+                    .body(self.get_body(content, 0..length))
+                    .unwrap())
+            }
+            crate::RequestedRange::Partial(start, end) => {
+                log::info!(
+                    "Sending bytes {}-{} of file with size {}",
+                    start,
+                    end,
+                    length
+                );
+                Ok(builder
+                    .status(206)
+                    .header(
+                        "content-range",
+                        format!("bytes {}-{}/{}", start, end, length),
+                    )
+                    .header("content-length", end + 1 - start)
+                    .body(self.get_body(content, start..end + 1))
+                    .unwrap())
+            }
+            crate::RequestedRange::Unsatisfiable => Ok(builder
+                .status(416)
+                .header("content-range", format!("bytes */{}", length))
+                .body(hyper::Body::empty())
+                .unwrap()),
+        }
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(1)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
     }
 }
 
@@ -138,10 +797,11 @@ impl router::Handler for MoveFileHandler {
             .try_into()
             .map_err(|e| super::map_error(&e, "Invalid destination", 400))?;
 
-        let (file_path_from, file_name_from) = crate::get_path_and_name_from_uri(&request.uri())?;
-        let (file_path_to, file_name_to) = crate::get_path_and_name_from_uri(&destination)?;
-        let (version, _timestamp) = super::get_version_info_from_headers(&request.headers());
-        let version = version.ok_or(router::HandlerError(400, String::from("Missing version")))?;
+        let (file_path_from, file_name_from) =
+            crate::get_tenant_path_and_name(request.uri(), request.extensions())?;
+        let (file_path_to, file_name_to) =
+            crate::get_tenant_path_and_name(&destination, request.extensions())?;
+        let version = get_required_version(request.headers(), self.missing_version_status)?;
 
         let mut repo = self.file_repo.lock().unwrap();
 
@@ -156,6 +816,7 @@ impl router::Handler for MoveFileHandler {
                 .get::<std::net::SocketAddr>()
                 .unwrap_or(&DEFAULT_SOCK_ADDRESS)
                 .ip(),
+            &get_history_metadata(request.headers()),
         )?;
 
         Ok(get_response_builder(&data, 204)
@@ -163,13 +824,13 @@ impl router::Handler for MoveFileHandler {
             .unwrap())
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(1)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
     }
 }
 
 #[async_trait::async_trait]
-impl router::Handler for PutFileHandler {
+impl router::Handler for SwapFileHandler {
     fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
         &self.matcher
     }
@@ -178,42 +839,72 @@ impl router::Handler for PutFileHandler {
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
-        let remote_address = request
-            .extensions()
-            .get::<std::net::SocketAddr>()
-            .unwrap()
-            .ip();
-        let (parts, body) = request.into_parts();
-        let (file_path, file_name) = crate::get_path_and_name_from_uri(&parts.uri)?;
-        let (version, _timestamp) = super::get_version_info_from_headers(&parts.headers);
+        let destination: http::Uri = request
+            .headers()
+            .get("destination")
+            .ok_or(router::RouterError::HandlerError(
+                400,
+                String::from("Missing destination"),
+            ))?
+            .to_str()
+            .map_err(|e| super::map_error(&e, "Invalid destination", 400))?
+            .try_into()
+            .map_err(|e| super::map_error(&e, "Invalid destination", 400))?;
 
-        let file_content = hyper::body::to_bytes(body)
-            .await
-            .map(|b| b.to_vec())
-            .map_err(|e| super::map_error(&e, "Invalid content", 400))?;
+        let (file_path_a, file_name_a) =
+            crate::get_tenant_path_and_name(request.uri(), request.extensions())?;
+        let (file_path_b, file_name_b) =
+            crate::get_tenant_path_and_name(&destination, request.extensions())?;
 
         let mut repo = self.file_repo.lock().unwrap();
 
-        let data = repo.save(
-            file_path.as_ref(),
-            file_name.as_ref(),
-            &file_content,
-            version,
-            &remote_address,
+        repo.swap(
+            file_path_a.as_ref(),
+            file_name_a.as_ref(),
+            file_path_b.as_ref(),
+            file_name_b.as_ref(),
+            &request
+                .extensions()
+                .get::<std::net::SocketAddr>()
+                .unwrap_or(&DEFAULT_SOCK_ADDRESS)
+                .ip(),
+            &get_history_metadata(request.headers()),
         )?;
 
-        Ok(get_response_builder(&data, 201)
+        Ok(hyper::Response::builder()
+            .status(204)
             .body(hyper::Body::empty())
             .unwrap())
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(1)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
+    }
+}
+
+impl PutFileHandler {
+    /// Refuses the content if it does not start with the magic bytes configured for its extension
+    fn check_magic(&self, file_name: &str, file_content: &[u8]) -> Result<(), router::RouterError> {
+        let extension = std::path::Path::new(file_name)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        if let Some(extension) = extension {
+            for (rule_extension, magic) in &self.magic_rules {
+                if rule_extension.to_lowercase() == extension && !file_content.starts_with(magic) {
+                    log::warn!("Refusing {} with an invalid signature", file_name);
+                    return Err(router::HandlerError(
+                        422,
+                        String::from("Invalid file signature"),
+                    ));
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 #[async_trait::async_trait]
-impl router::Handler for FileVersionsHandler {
+impl router::Handler for PutFileHandler {
     fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
         &self.matcher
     }
@@ -222,33 +913,436 @@ impl router::Handler for FileVersionsHandler {
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
-        let (file_path, file_name) = crate::get_path_and_name_from_uri(&request.uri())?;
+        // the permit is kept until the upload is saved
+        let _permit = match &self.upload_permits {
+            Some(permits) => match permits.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    log::warn!("Too many concurrent uploads, refusing upload");
+                    return Ok(hyper::Response::builder()
+                        .status(503)
+                        .header("retry-after", "1")
+                        .body(hyper::Body::from("Too many concurrent uploads"))
+                        .unwrap());
+                }
+            },
+            None => None,
+        };
+        let remote_address = request
+            .extensions()
+            .get::<std::net::SocketAddr>()
+            .unwrap()
+            .ip();
+        let (parts, body) = request.into_parts();
+        let (file_path, file_name) =
+            crate::get_tenant_path_and_name(&parts.uri, &parts.extensions)?;
+        let (version, _timestamp) = super::get_version_info_from_headers(&parts.headers);
+        // `If-None-Match: *` only allows the creation of the file, whatever the version sent
+        let create_only = matches!(
+            parts.headers.get(hyper::header::IF_NONE_MATCH),
+            Some(value) if value.to_str().map(str::trim).ok() == Some("*")
+        );
+        let version = if create_only { None } else { version };
+        let metadata = get_history_metadata(&parts.headers);
+        let content_type = parts
+            .headers
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        // hyper only sends the 100 Continue once the body is polled, so a doomed upload can be
+        // answered with its final status before the client sends the body
+        let expects_continue = matches!(
+            parts.headers.get(hyper::header::EXPECT),
+            Some(value) if value.as_bytes().eq_ignore_ascii_case(b"100-continue")
+        );
+        let file_size = parts
+            .headers
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0);
+        if file_size > self.max_body_bytes {
+            return Err(body_too_large(self.max_body_bytes));
+        }
+        if expects_continue {
+            self.file_repo.lock().unwrap().check_save(
+                file_path.as_ref(),
+                file_name.as_ref(),
+                version,
+                file_size,
+            )?;
+        }
 
-        let repo = self.file_repo.lock().unwrap();
-        let log = repo.get_history(file_path.as_ref(), file_name.as_ref())?;
+        let file_content = read_body(body, self.spool_threshold_bytes, self.max_body_bytes).await?;
 
-        Ok(hyper::Response::builder()
-            .status(200)
-            .body(hyper::Body::from(
-                serde_json::to_string(&log.entries).unwrap(),
-            ))
+        self.check_magic(&file_name, &file_content)?;
+
+        let mut repo = self.file_repo.lock().unwrap();
+
+        let data = if create_only {
+            repo.create_only_with_content_type(
+                file_path.as_ref(),
+                file_name.as_ref(),
+                &file_content,
+                content_type.as_deref(),
+                &remote_address,
+                &metadata,
+            )?
+        } else {
+            repo.save_with_content_type(
+                file_path.as_ref(),
+                file_name.as_ref(),
+                &file_content,
+                content_type.as_deref(),
+                version,
+                &remote_address,
+                &metadata,
+            )?
+        };
+
+        Ok(get_response_builder(&data, 201)
+            .body(hyper::Body::empty())
             .unwrap())
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(1)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
     }
 }
 
-lazy_static::lazy_static!(
-    static ref DEFAULT_SOCK_ADDRESS: std::net::SocketAddr
-        = std::net::SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::new(0, 0, 0, 0)), 0);
-);
+/// Number of entries of a page of history when only its cursor is given
+const DEFAULT_HISTORY_PAGE_LIMIT: u32 = 100;
 
-#[cfg(test)]
-mod tests {
-    use router::Handler;
-    use test_log::test;
+/// Page of history sent when the client gives a cursor or a limit
+#[derive(serde::Serialize)]
+struct HistoryPage<T> {
+    entries: T,
+    /// Cursor of the next page, `None` on the last page
+    next: Option<u32>,
+}
+
+/// Decodes the `after_version` cursor and the `limit` of the requested page of history, if any
+fn get_history_page(uri: &http::Uri) -> Result<Option<(Option<u32>, u32)>, router::RouterError> {
+    let mut after_version = None;
+    let mut limit = None;
+    for (param, value) in form_urlencoded::parse(uri.query().unwrap_or("").as_bytes()) {
+        let parameter = match param.as_ref() {
+            "after_version" => &mut after_version,
+            "limit" => &mut limit,
+            _ => continue,
+        };
+        *parameter = Some(
+            value
+                .parse::<u32>()
+                .map_err(|_| router::InvalidRequest(format!("Invalid {} parameter", param)))?,
+        );
+    }
+    if limit == Some(0) {
+        return Err(router::InvalidRequest(String::from(
+            "Invalid limit parameter",
+        )));
+    }
+    Ok(match (after_version, limit) {
+        (None, None) => None,
+        (after_version, limit) => {
+            Some((after_version, limit.unwrap_or(DEFAULT_HISTORY_PAGE_LIMIT)))
+        }
+    })
+}
+
+/// Reads the `version` query parameter, the version of the history whose content is requested
+fn get_requested_version(uri: &http::Uri) -> Result<Option<i32>, router::RouterError> {
+    form_urlencoded::parse(uri.query().unwrap_or("").as_bytes())
+        .find(|(param, _)| param == "version")
+        .map(|(_, value)| {
+            value
+                .parse::<u32>()
+                .ok()
+                .and_then(|version| i32::try_from(version).ok())
+                .ok_or_else(|| router::InvalidRequest(String::from("Invalid version parameter")))
+        })
+        .transpose()
+}
+
+/// Renders the timestamps of the entries in the given timezone
+fn in_timezone(
+    entries: &[crate::log::FileLogEntry],
+    timezone: chrono_tz::Tz,
+) -> Vec<serde_json::Value> {
+    entries
+        .iter()
+        .map(|entry| {
+            let mut json = serde_json::to_value(entry).unwrap();
+            json["timestamp"] =
+                serde_json::Value::from(entry.timestamp.with_timezone(&timezone).to_rfc3339());
+            json
+        })
+        .collect()
+}
+
+impl FileVersionsHandler {
+    /// Sends the content of the file at the given version of its history
+    fn get_version(
+        &self,
+        file_path: &str,
+        file_name: &str,
+        version: i32,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        let mut data = read_with(&self.file_repo, &self.read_replica, |repo| {
+            repo.get_version(file_path, file_name, version)
+        })?;
+        let content = data.file.take().unwrap_or_default();
+
+        let mut builder = get_response_builder(&data, 200)
+            .header("content-type", get_content_type(&data, file_name))
+            .header("content-length", content.len())
+            .header(
+                "content-disposition",
+                format!("attachment; filename=\"{}\"", file_name),
+            );
+        if let Some(hash) = &data.hash {
+            builder = builder.header("x-content-sha256", hash);
+        }
+
+        Ok(builder.body(hyper::Body::from(content)).unwrap())
+    }
+
+    /// Sends the requested page of the history, without any etag since its content also depends
+    /// on the entries after it
+    fn get_page(
+        &self,
+        file_path: &str,
+        file_name: &str,
+        (after_version, limit): (Option<u32>, u32),
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        // one more entry is read to know whether there is a next page
+        let mut log = read_with(&self.file_repo, &self.read_replica, |repo| {
+            repo.get_history_after(file_path, file_name, after_version, limit.saturating_add(1))
+        })?;
+        let next = if log.entries.len() > limit as usize {
+            log.entries.truncate(limit as usize);
+            log.entries.last().map(|entry| entry.entry.version())
+        } else {
+            None
+        };
+        let body = match self.timezone {
+            Some(timezone) => serde_json::to_string(&HistoryPage {
+                entries: in_timezone(&log.entries, timezone),
+                next,
+            }),
+            None => serde_json::to_string(&HistoryPage {
+                entries: &log.entries,
+                next,
+            }),
+        }
+        .unwrap();
+        Ok(hyper::Response::builder()
+            .status(200)
+            .body(hyper::Body::from(body))
+            .unwrap())
+    }
+}
+
+#[async_trait::async_trait]
+impl router::Handler for FileVersionsHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        &self.matcher
+    }
+
+    async fn handle(
+        &self,
+        request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        let (file_path, file_name) =
+            crate::get_tenant_path_and_name(request.uri(), request.extensions())?;
+
+        if let Some(version) = get_requested_version(request.uri())? {
+            return self.get_version(&file_path, &file_name, version);
+        }
+
+        if let Some(page) = get_history_page(request.uri())? {
+            return self.get_page(&file_path, &file_name, page);
+        }
+
+        let log = read_with(&self.file_repo, &self.read_replica, |repo| {
+            repo.get_history(file_path.as_ref(), file_name.as_ref())
+        })?;
+
+        // the history only changes when a new version is added
+        let latest_version = log
+            .entries
+            .iter()
+            .map(|entry| entry.entry.version())
+            .max()
+            .unwrap_or(0);
+        let builder =
+            hyper::Response::builder().header("etag", format!("W/\"{}\"", latest_version));
+
+        if super::get_version_from_header(request.headers(), "if-none-match")
+            == Some(latest_version as i32)
+        {
+            return Ok(builder.status(304).body(hyper::Body::empty()).unwrap());
+        }
+
+        let body = match self.timezone {
+            Some(timezone) => serde_json::to_string(&in_timezone(&log.entries, timezone)).unwrap(),
+            None => serde_json::to_string(&log.entries).unwrap(),
+        };
+
+        Ok(builder.status(200).body(hyper::Body::from(body)).unwrap())
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
+    }
+}
+
+/// Metadata values bigger than this are refused with a 413
+const MAX_METADATA_VALUE_BYTES: u64 = 64 * 1024;
+
+#[async_trait::async_trait]
+impl router::Handler for FileMetadataHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        &self.matcher
+    }
+
+    async fn handle(
+        &self,
+        request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        let (file_path, file_name, key) =
+            crate::get_tenant_path_name_and_key(request.uri(), request.extensions())?;
+
+        match *request.method() {
+            hyper::Method::GET => {
+                let metadata = read_with(&self.file_repo, &self.read_replica, |repo| {
+                    // like for PUT, the file must currently exist, the metadata being only
+                    // removed by a purge
+                    match repo.get_status(&file_path, &file_name)? {
+                        crate::db::FileStatus::Present | crate::db::FileStatus::Tombstone => {
+                            repo.get_meta_map(&file_path, &file_name)
+                        }
+                        _ => Err(router::RouterError::NotFound),
+                    }
+                })?;
+                let value = metadata.get(&key).ok_or(router::RouterError::NotFound)?;
+                Ok(hyper::Response::builder()
+                    .status(200)
+                    .header("content-type", "text/plain; charset=utf-8")
+                    .body(hyper::Body::from(value.to_owned()))
+                    .unwrap())
+            }
+            hyper::Method::PUT => {
+                let body = read_body(request.into_body(), None, MAX_METADATA_VALUE_BYTES).await?;
+                let value = String::from_utf8(body)
+                    .map_err(|e| super::map_error(&e, "Metadata value is not valid utf-8", 400))?;
+                self.file_repo
+                    .lock()
+                    .unwrap()
+                    .set_meta(&file_path, &file_name, &key, &value)?;
+                Ok(hyper::Response::builder()
+                    .status(204)
+                    .body(hyper::Body::empty())
+                    .unwrap())
+            }
+            _ => {
+                self.file_repo
+                    .lock()
+                    .unwrap()
+                    .delete_meta(&file_path, &file_name, &key)?;
+                Ok(hyper::Response::builder()
+                    .status(204)
+                    .body(hyper::Body::empty())
+                    .unwrap())
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl router::Handler for BackupHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        &self.matcher
+    }
+
+    async fn handle(
+        &self,
+        _request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        let backup_path = std::env::temp_dir().join(format!(
+            "file_repository-{}-{}.db3",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos()
+        ));
+
+        self.file_repo.lock().unwrap().backup(&backup_path)?;
+
+        let file = tokio::fs::File::open(&backup_path).await.map_err(|e| {
+            let _ = std::fs::remove_file(&backup_path);
+            super::map_error(&e, "Failed to open backup", 500)
+        })?;
+
+        let (sender, body) = hyper::Body::channel();
+        tokio::spawn(send_backup(file, backup_path, sender));
+
+        Ok(hyper::Response::builder()
+            .status(200)
+            .header("content-type", "application/octet-stream")
+            .header(
+                "content-disposition",
+                "attachment; filename=\"file_repository.db3\"",
+            )
+            .body(body)
+            .unwrap())
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(30))
+    }
+}
+
+/// Streams the backup file to the client, then removes it
+async fn send_backup(
+    mut file: tokio::fs::File,
+    backup_path: std::path::PathBuf,
+    mut sender: hyper::body::Sender,
+) {
+    use tokio::io::AsyncReadExt;
+
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        match file.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(n) => {
+                let chunk = hyper::body::Bytes::copy_from_slice(&buffer[..n]);
+                if let Err(e) = sender.send_data(chunk).await {
+                    log::warn!("Failed to send backup: {:?}", e);
+                    break;
+                }
+            }
+            Err(e) => {
+                log::warn!("Failed to read backup: {:?}", e);
+                sender.abort();
+                break;
+            }
+        }
+    }
+    if let Err(e) = tokio::fs::remove_file(&backup_path).await {
+        log::warn!("Failed to remove backup {:?}: {:?}", backup_path, e);
+    }
+}
+
+lazy_static::lazy_static!(
+    static ref DEFAULT_SOCK_ADDRESS: std::net::SocketAddr
+        = std::net::SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::new(0, 0, 0, 0)), 0);
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::db::FilesReader;
+    use router::Handler;
+    use test_log::test;
 
     static TEST_PATH: &str = "target/test/file_handlers_tests";
 
@@ -289,7 +1383,9 @@ mod tests {
 
         let file_handler = super::GetFileHandler {
             file_repo,
+            read_replica: None,
             matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
         };
 
         let (parts, body) = file_handler.handle(req).await.unwrap().into_parts();
@@ -367,6 +1463,7 @@ mod tests {
         let file_handler = super::DeleteFileHandler {
             file_repo: file_repo.clone(),
             matcher: crate::get_matcher(&hyper::Method::DELETE),
+            missing_version_status: 428,
         };
 
         let (parts, _body) = file_handler.handle(req).await.unwrap().into_parts();
@@ -386,6 +1483,239 @@ mod tests {
         }
     }
 
+    #[test(tokio::test)]
+    async fn it_purges_with_the_last_version() {
+        let file_repo = get_repo("purge");
+        {
+            let mut repo = file_repo.lock().unwrap();
+
+            repo.save("keepass", "pdb.kdbx", &vec![0], None, &ADDRESS)
+                .unwrap();
+            repo.save("keepass", "pdb.kdbx", &vec![1], Some(0), &ADDRESS)
+                .unwrap();
+        }
+
+        let file_handler = super::DeleteFileHandler {
+            file_repo: file_repo.clone(),
+            matcher: crate::get_matcher(&hyper::Method::DELETE),
+            missing_version_status: 428,
+        };
+        let request = |etag: Option<&str>| {
+            let mut builder = hyper::Request::builder()
+                .uri("/files/keepass/pdb.kdbx?purge=true")
+                .method("DELETE");
+            if let Some(etag) = etag {
+                builder = builder.header("ETag", etag);
+            }
+            builder.body(hyper::Body::empty()).unwrap()
+        };
+
+        for (etag, expected_status) in [(None, 428), (Some("\"0\""), 412)] {
+            let result = file_handler.handle(request(etag)).await;
+
+            assert!(
+                matches!(result, Err(router::RouterError::HandlerError(status, _)) if status == expected_status),
+                "{:?}",
+                etag
+            );
+        }
+        assert_eq!(
+            2,
+            file_repo
+                .lock()
+                .unwrap()
+                .get_history("keepass", "pdb.kdbx")
+                .unwrap()
+                .entries
+                .len()
+        );
+
+        let (parts, _body) = file_handler
+            .handle(request(Some("\"1\"")))
+            .await
+            .unwrap()
+            .into_parts();
+
+        assert_eq!(204, parts.status);
+        assert!(parts.headers.get("ETag").is_none());
+        assert!(matches!(
+            file_repo.lock().unwrap().get_history("keepass", "pdb.kdbx"),
+            Err(router::RouterError::NotFound)
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn it_deletes_as_tombstone_when_configured() {
+        for (tombstone, expected_status) in [(false, 410), (true, 200)] {
+            let path = std::path::PathBuf::from(TEST_PATH).join(format!("tombstone_{}", tombstone));
+            if path.exists() {
+                std::fs::remove_dir_all(&path).unwrap();
+            }
+            let file_repo = std::sync::Arc::new(std::sync::Mutex::new(
+                crate::db::FilesDB::new(path)
+                    .unwrap()
+                    .with_delete_as_tombstone(tombstone),
+            ));
+            file_repo
+                .lock()
+                .unwrap()
+                .save("keepass", "pdb.kdbx", &vec![1, 2, 3], None, &ADDRESS)
+                .unwrap();
+
+            let delete_handler = super::DeleteFileHandler {
+                file_repo: file_repo.clone(),
+                matcher: crate::get_matcher(&hyper::Method::DELETE),
+                missing_version_status: 428,
+            };
+            let get_handler = super::GetFileHandler {
+                file_repo: file_repo.clone(),
+                read_replica: None,
+                matcher: crate::get_matcher(&hyper::Method::GET),
+                coalescer: Default::default(),
+            };
+
+            let req = hyper::Request::builder()
+                .uri("/files/keepass/pdb.kdbx")
+                .method("DELETE")
+                .header("ETag", "\"0\"")
+                .body(hyper::Body::empty())
+                .unwrap();
+            let response = delete_handler.handle(req).await.unwrap();
+            assert_eq!(204, response.status());
+
+            let req = hyper::Request::builder()
+                .uri("/files/keepass/pdb.kdbx")
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap();
+            let status = match get_handler.handle(req).await {
+                Ok(response) => {
+                    let (parts, body) = response.into_parts();
+                    assert_eq!("true", parts.headers.get("X-Deleted").unwrap());
+                    assert_eq!("\"1\"", parts.headers.get("ETag").unwrap());
+                    assert!(hyper::body::to_bytes(body).await.unwrap().is_empty());
+                    parts.status.as_u16()
+                }
+                Err(router::RouterError::HandlerError(status, _)) => status,
+                Err(e) => panic!("Unexpected error {:?}", e),
+            };
+            assert_eq!(expected_status, status, "with tombstone {}", tombstone);
+
+            let history = file_repo
+                .lock()
+                .unwrap()
+                .get_history("keepass", "pdb.kdbx")
+                .unwrap();
+            assert_eq!(
+                tombstone,
+                matches!(
+                    history.entries[1].entry,
+                    crate::log::FileLogEntryType::Tombstone { version: 1 }
+                )
+            );
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_sends_the_content_hash() {
+        use base64::Engine;
+        use sha2::Digest;
+
+        let file_repo = get_repo("hash");
+        file_repo
+            .lock()
+            .unwrap()
+            .save(
+                "hash",
+                "file.txt",
+                "content to hash".as_bytes().to_owned().as_ref(),
+                None,
+                &ADDRESS,
+            )
+            .unwrap();
+
+        let file_handler = super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+
+        for method in ["GET", "HEAD"] {
+            let req = hyper::Request::builder()
+                .uri("/files/hash/file.txt")
+                .method(method)
+                .body(hyper::Body::empty())
+                .unwrap();
+
+            let (parts, body) = file_handler.handle(req).await.unwrap().into_parts();
+            let hash = parts.headers.get("X-Content-SHA256").unwrap();
+
+            if method == "GET" {
+                let body = hyper::body::to_bytes(body).await.unwrap();
+                let expected_hash = base64::engine::general_purpose::STANDARD_NO_PAD
+                    .encode(sha2::Sha256::digest(&body));
+                assert_eq!(expected_hash, hash.to_str().unwrap());
+            } else {
+                assert!(!hash.is_empty());
+            }
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_distinguishes_deleted_files() {
+        let file_repo = get_repo("gone");
+        {
+            let mut repo = file_repo.lock().unwrap();
+
+            for file_name in ["present.txt", "deleted.txt"] {
+                repo.save(
+                    "gone",
+                    file_name,
+                    "content".as_bytes().to_owned().as_ref(),
+                    None,
+                    &ADDRESS,
+                )
+                .unwrap();
+            }
+            repo.delete(
+                "gone",
+                "deleted.txt",
+                0,
+                &ADDRESS,
+                &crate::db::HistoryMetadata::default(),
+            )
+            .unwrap();
+        }
+
+        let file_handler = super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+
+        for (file_name, expected_status) in [
+            ("present.txt", 200),
+            ("deleted.txt", 410),
+            ("unknown.txt", 404),
+        ] {
+            let req = hyper::Request::builder()
+                .uri(format!("/files/gone/{}", file_name))
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap();
+
+            let status = match file_handler.handle(req).await {
+                Ok(response) => response.status().as_u16(),
+                Err(router::RouterError::HandlerError(status, _)) => status,
+                Err(e) => panic!("Unexpected error {:?}", e),
+            };
+
+            assert_eq!(expected_status, status, "for {}", file_name);
+        }
+    }
+
     #[test(tokio::test)]
     async fn it_moves() {
         let file_repo = get_repo("move");
@@ -413,6 +1743,7 @@ mod tests {
         let file_handler = super::MoveFileHandler {
             file_repo: file_repo.clone(),
             matcher: crate::get_matcher("MOVE"),
+            missing_version_status: 428,
         };
 
         let (parts, _body) = file_handler.handle(req).await.unwrap().into_parts();
@@ -429,7 +1760,9 @@ mod tests {
 
         let versions_handlers = super::FileVersionsHandler {
             file_repo: file_repo.clone(),
+            read_replica: None,
             matcher: crate::get_matcher("GET"),
+            timezone: None,
         };
 
         let (parts, body) = versions_handlers.handle(req).await.unwrap().into_parts();
@@ -438,8 +1771,1330 @@ mod tests {
 
         let body = hyper::body::to_bytes(body).await.unwrap();
         let body = String::from_utf8(body.to_vec()).unwrap();
-        let re = regex::Regex::new(r#"^\[\{"timestamp":"[^"]+","address":"127.0.0.1","entry":\{"type":"Creation","version":0,"hash":"[^"]+"}},\{"timestamp":"[^"]+","address":"0.0.0.0","entry":\{"type":"MoveTo","version":1,"pathTo":"keepass/pdb.kdbx"}}\]$"#).unwrap();
+        let re = regex::Regex::new(r#"^\[\{"timestamp":"[^"]+","address":"127.0.0.1","entry":\{"type":"Creation","version":0,"hash":"[^"]+"},"userAgent":null,"comment":null},\{"timestamp":"[^"]+","address":"0.0.0.0","entry":\{"type":"MoveTo","version":1,"pathTo":"keepass/pdb.kdbx"},"userAgent":null,"comment":null}\]$"#).unwrap();
         log::error!("{}", body);
         assert!(re.is_match(&body));
     }
+
+    #[test(tokio::test)]
+    async fn it_sends_an_etag_with_the_versions() {
+        let file_repo = get_repo("versions_etag");
+        {
+            let mut repo = file_repo.lock().unwrap();
+
+            repo.save("keepass", "pdb.kdbx", &vec![1], None, &ADDRESS)
+                .unwrap();
+            repo.save("keepass", "pdb.kdbx", &vec![2], Some(0), &ADDRESS)
+                .unwrap();
+        }
+
+        let versions_handler = super::FileVersionsHandler {
+            file_repo: file_repo.clone(),
+            read_replica: None,
+            matcher: crate::get_matcher("GET"),
+            timezone: None,
+        };
+
+        for (if_none_match, expected_status) in
+            [(None, 200), (Some("W/\"1\""), 304), (Some("W/\"0\""), 200)]
+        {
+            let mut builder = hyper::Request::builder()
+                .uri("/file-versions/keepass/pdb.kdbx")
+                .method("GET");
+            if let Some(if_none_match) = if_none_match {
+                builder = builder.header("if-none-match", if_none_match);
+            }
+            let req = builder.body(hyper::Body::empty()).unwrap();
+
+            let (parts, body) = versions_handler.handle(req).await.unwrap().into_parts();
+            let body = hyper::body::to_bytes(body).await.unwrap();
+
+            assert_eq!(expected_status, parts.status, "for {:?}", if_none_match);
+            assert_eq!("W/\"1\"", parts.headers.get("etag").unwrap());
+            assert_eq!(expected_status == 304, body.is_empty());
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_sends_the_content_of_a_version() {
+        let file_repo = get_repo("versions_content");
+        {
+            let mut repo = file_repo.lock().unwrap();
+
+            repo.save_with_content_type(
+                "keepass",
+                "pdb.kdbx",
+                &vec![1, 2],
+                Some("application/x-keepass2"),
+                None,
+                &ADDRESS,
+                &crate::db::HistoryMetadata::default(),
+            )
+            .unwrap();
+            repo.save("keepass", "pdb.kdbx", &vec![3], Some(0), &ADDRESS)
+                .unwrap();
+        }
+
+        let versions_handler = super::FileVersionsHandler {
+            file_repo: file_repo.clone(),
+            read_replica: None,
+            matcher: crate::get_matcher("GET"),
+            timezone: None,
+        };
+
+        let get_version = |version: &str| {
+            hyper::Request::builder()
+                .uri(format!(
+                    "/file-versions/keepass/pdb.kdbx?version={}",
+                    version
+                ))
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap()
+        };
+
+        let (parts, body) = versions_handler
+            .handle(get_version("0"))
+            .await
+            .unwrap()
+            .into_parts();
+
+        assert_eq!(200, parts.status);
+        assert_eq!("\"0\"", parts.headers["etag"]);
+        assert_eq!("application/x-keepass2", parts.headers["content-type"]);
+        assert_eq!(
+            vec![1, 2],
+            hyper::body::to_bytes(body).await.unwrap().to_vec()
+        );
+
+        let response = versions_handler.handle(get_version("1")).await.unwrap();
+
+        assert_eq!(
+            "application/octet-stream",
+            response.headers()["content-type"]
+        );
+
+        let result = versions_handler.handle(get_version("2")).await;
+
+        assert!(matches!(result, Err(router::HandlerError(404, _))));
+
+        let result = versions_handler.handle(get_version("last")).await;
+
+        assert!(matches!(
+            result,
+            Err(router::RouterError::InvalidRequest(_))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn it_pages_through_the_versions_with_a_cursor() {
+        let file_repo = get_repo("versions_pages");
+        {
+            let mut repo = file_repo.lock().unwrap();
+
+            repo.save("keepass", "pdb.kdbx", &vec![0], None, &ADDRESS)
+                .unwrap();
+            for version in 0..4 {
+                repo.save("keepass", "pdb.kdbx", &vec![1], Some(version), &ADDRESS)
+                    .unwrap();
+            }
+        }
+
+        let versions_handler = super::FileVersionsHandler {
+            file_repo: file_repo.clone(),
+            read_replica: None,
+            matcher: crate::get_matcher("GET"),
+            timezone: None,
+        };
+
+        let mut query = String::from("limit=2");
+        let mut versions = Vec::new();
+        loop {
+            let req = hyper::Request::builder()
+                .uri(format!("/file-versions/keepass/pdb.kdbx?{}", query))
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap();
+
+            let (parts, body) = versions_handler.handle(req).await.unwrap().into_parts();
+
+            assert_eq!(200, parts.status);
+            assert!(!parts.headers.contains_key("etag"));
+
+            let body = hyper::body::to_bytes(body).await.unwrap();
+            let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let entries = page["entries"].as_array().unwrap();
+            assert!(entries.len() <= 2);
+            versions.extend(
+                entries
+                    .iter()
+                    .map(|entry| entry["entry"]["version"].clone()),
+            );
+
+            match page["next"].as_u64() {
+                Some(next) => query = format!("after_version={}&limit=2", next),
+                None => break,
+            }
+        }
+
+        assert_eq!(
+            serde_json::json!([0, 1, 2, 3, 4]),
+            serde_json::Value::from(versions)
+        );
+
+        let req = hyper::Request::builder()
+            .uri("/file-versions/keepass/pdb.kdbx?limit=zero")
+            .method("GET")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        assert!(matches!(
+            versions_handler.handle(req).await,
+            Err(router::RouterError::InvalidRequest(_))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn it_renders_the_versions_in_the_configured_timezone() {
+        let file_repo = get_repo("versions_timezone");
+        file_repo
+            .lock()
+            .unwrap()
+            .save("keepass", "pdb.kdbx", &vec![1], None, &ADDRESS)
+            .unwrap();
+
+        let mut timestamps = Vec::new();
+        for timezone in [None, Some(chrono_tz::Etc::GMTMinus2)] {
+            let versions_handler = super::FileVersionsHandler {
+                file_repo: file_repo.clone(),
+                read_replica: None,
+                matcher: crate::get_matcher("GET"),
+                timezone,
+            };
+            let req = hyper::Request::builder()
+                .uri("/file-versions/keepass/pdb.kdbx")
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap();
+
+            let body =
+                hyper::body::to_bytes(versions_handler.handle(req).await.unwrap().into_body())
+                    .await
+                    .unwrap();
+            let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            timestamps.push(String::from(entries[0]["timestamp"].as_str().unwrap()));
+        }
+
+        let utc = chrono::DateTime::parse_from_rfc3339(&timestamps[0]).unwrap();
+        let local = chrono::DateTime::parse_from_rfc3339(&timestamps[1]).unwrap();
+
+        assert_eq!(0, utc.offset().local_minus_utc());
+        assert_eq!(2 * 3600, local.offset().local_minus_utc());
+        assert_eq!(utc, local);
+    }
+
+    #[test(tokio::test)]
+    async fn it_sets_reads_and_deletes_the_metadata() {
+        let file_repo = get_repo("metadata");
+        file_repo
+            .lock()
+            .unwrap()
+            .save("keepass", "pdb.kdbx", &vec![1], None, &ADDRESS)
+            .unwrap();
+
+        let metadata_handler = super::FileMetadataHandler {
+            file_repo: file_repo.clone(),
+            read_replica: None,
+            matcher: crate::get_matcher("GET"),
+        };
+
+        let send = |method: &str, uri: &str, body: &'static str| {
+            let req = hyper::Request::builder()
+                .uri(uri)
+                .method(method)
+                .body(hyper::Body::from(body))
+                .unwrap();
+            metadata_handler.handle(req)
+        };
+
+        let response = send("PUT", "/files-meta/keepass/pdb.kdbx/synced-from", "laptop")
+            .await
+            .unwrap();
+
+        assert_eq!(204, response.status());
+
+        let response = send("GET", "/files-meta/keepass/pdb.kdbx/synced-from", "")
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        assert_eq!("laptop", body);
+
+        let response = send("DELETE", "/files-meta/keepass/pdb.kdbx/synced-from", "")
+            .await
+            .unwrap();
+
+        assert_eq!(204, response.status());
+
+        for (method, uri) in [
+            ("GET", "/files-meta/keepass/pdb.kdbx/synced-from"),
+            ("DELETE", "/files-meta/keepass/pdb.kdbx/synced-from"),
+            ("PUT", "/files-meta/keepass/other.kdbx/synced-from"),
+        ] {
+            assert_eq!(
+                router::RouterError::NotFound,
+                send(method, uri, "laptop").await.unwrap_err(),
+                "for {} {}",
+                method,
+                uri
+            );
+        }
+
+        let req = hyper::Request::builder()
+            .uri("/files-meta/keepass/pdb.kdbx/synced-from")
+            .method("PUT")
+            .body(hyper::Body::from(vec![b'a'; 64 * 1024 + 1]))
+            .unwrap();
+
+        assert!(matches!(
+            metadata_handler.handle(req).await,
+            Err(router::HandlerError(413, _))
+        ));
+
+        let response = send("PUT", "/files-meta/keepass/pdb.kdbx/device", "phone")
+            .await
+            .unwrap();
+
+        assert_eq!(204, response.status());
+
+        file_repo
+            .lock()
+            .unwrap()
+            .delete(
+                "keepass",
+                "pdb.kdbx",
+                0,
+                &ADDRESS,
+                &crate::db::HistoryMetadata::default(),
+            )
+            .unwrap();
+
+        for method in ["GET", "PUT"] {
+            assert_eq!(
+                router::RouterError::NotFound,
+                send(method, "/files-meta/keepass/pdb.kdbx/device", "tablet")
+                    .await
+                    .unwrap_err(),
+                "for {} of a deleted file",
+                method
+            );
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_sends_a_backup() {
+        let file_repo = get_repo("backup");
+        {
+            let mut repo = file_repo.lock().unwrap();
+
+            repo.save(
+                "keepass",
+                "pdb.kdbx",
+                "content of current file".as_bytes().to_owned().as_ref(),
+                None,
+                &ADDRESS,
+            )
+            .unwrap();
+        }
+
+        let req = hyper::Request::builder()
+            .uri("/maintenance/backup")
+            .method("GET")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        let backup_handler = super::BackupHandler {
+            file_repo,
+            matcher: crate::get_matcher("GET"),
+        };
+
+        let (parts, body) = backup_handler.handle(req).await.unwrap().into_parts();
+
+        assert_eq!(200, parts.status);
+        assert_eq!(
+            "application/octet-stream",
+            parts.headers.get("Content-Type").unwrap().to_str().unwrap()
+        );
+
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        let backup_path = std::path::PathBuf::from(TEST_PATH).join("backup/downloaded.db3");
+        std::fs::write(&backup_path, body).unwrap();
+
+        let connection = rusqlite::Connection::open(&backup_path).unwrap();
+        let content: Vec<u8> = connection
+            .query_row(
+                "select FILE from FILES where PATH=? and NAME=?",
+                rusqlite::params!["keepass", "pdb.kdbx"],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        assert_eq!("content of current file".as_bytes(), content.as_slice());
+    }
+
+    async fn get_with_headers(
+        file_handler: &super::GetFileHandler,
+        headers: &[(&str, &str)],
+    ) -> (http::response::Parts, String) {
+        let mut req = hyper::Request::builder()
+            .uri("/files/keepass/pdb.kdbx")
+            .method("GET");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = req.body(hyper::Body::empty()).unwrap();
+
+        let (parts, body) = file_handler.handle(req).await.unwrap().into_parts();
+        let body = String::from_utf8(hyper::body::to_bytes(body).await.unwrap().to_vec()).unwrap();
+        (parts, body)
+    }
+
+    #[test]
+    fn it_serves_concurrent_reads_without_holding_the_lock() {
+        let file_repo = get_repo("concurrent_reads");
+        {
+            let mut repo = file_repo.lock().unwrap();
+            repo.save("keepass", "big.kdbx", &vec![7; 1 << 20], None, &ADDRESS)
+                .unwrap();
+            repo.save("keepass", "small.kdbx", &vec![1, 2], None, &ADDRESS)
+                .unwrap();
+        }
+        let file_handler = std::sync::Arc::new(super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        });
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+
+        let get = |handler: std::sync::Arc<super::GetFileHandler>, name: &str| {
+            let req = hyper::Request::builder()
+                .uri(format!("/files/keepass/{}", name))
+                .method("GET")
+                .header("range", "bytes=1-")
+                .body(hyper::Body::empty())
+                .unwrap();
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(async { handler.handle(req).await.unwrap() })
+        };
+
+        let handler = file_handler.clone();
+        let big_barrier = barrier.clone();
+        let big_read = std::thread::spawn(move || {
+            let response = get(handler, "big.kdbx");
+            // the other read happens while this response is still being sent
+            big_barrier.wait();
+            big_barrier.wait();
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(hyper::body::to_bytes(response.into_body()))
+                .unwrap()
+                .len()
+        });
+        let handler = file_handler.clone();
+        let small_read = std::thread::spawn(move || {
+            barrier.wait();
+            let response = get(handler, "small.kdbx");
+            barrier.wait();
+            response.status()
+        });
+
+        assert_eq!(206, small_read.join().unwrap());
+        assert_eq!((1 << 20) - 1, big_read.join().unwrap());
+    }
+
+    #[test(tokio::test)]
+    #[allow(clippy::await_holding_lock)]
+    async fn it_reads_from_the_replica_while_the_database_is_locked() {
+        let file_repo = get_repo("replica_reads");
+        let replica_path = std::path::PathBuf::from(TEST_PATH).join("replica_reads.db3");
+        {
+            let mut repo = file_repo.lock().unwrap();
+            repo.save("keepass", "pdb.kdbx", &vec![1, 2, 3], None, &ADDRESS)
+                .unwrap();
+            repo.set_meta("keepass", "pdb.kdbx", "device", "laptop")
+                .unwrap();
+            repo.backup(&replica_path).unwrap();
+        }
+        let read_replica = Some(std::sync::Arc::new(std::sync::Mutex::new(
+            crate::db::ReadReplica::open(&replica_path).unwrap(),
+        )));
+        let file_handler = super::GetFileHandler {
+            file_repo: file_repo.clone(),
+            read_replica: read_replica.clone(),
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+        let versions_handler = super::FileVersionsHandler {
+            file_repo: file_repo.clone(),
+            read_replica: read_replica.clone(),
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            timezone: None,
+        };
+        let metadata_handler = super::FileMetadataHandler {
+            file_repo: file_repo.clone(),
+            read_replica,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+        };
+        let get = |uri: &str| {
+            hyper::Request::builder()
+                .uri(uri)
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap()
+        };
+
+        // a write in progress holds the lock of the primary database
+        let _write = file_repo.lock().unwrap();
+
+        let response = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            file_handler.handle(get("/files/keepass/pdb.kdbx")),
+        )
+        .await
+        .expect("The read waited for the lock of the database")
+        .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(vec![1, 2, 3], body);
+
+        let response = versions_handler
+            .handle(get("/file-versions/keepass/pdb.kdbx"))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status());
+
+        let response = metadata_handler
+            .handle(get("/files-meta/keepass/pdb.kdbx/device"))
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!("laptop", body);
+    }
+
+    #[test(tokio::test)]
+    #[allow(clippy::await_holding_lock)]
+    async fn it_coalesces_the_concurrent_reads_of_a_file() {
+        let file_repo = get_repo("coalesced_reads");
+        file_repo
+            .lock()
+            .unwrap()
+            .save("keepass", "pdb.kdbx", &vec![1, 2, 3], None, &ADDRESS)
+            .unwrap();
+        let file_handler = super::GetFileHandler {
+            file_repo: file_repo.clone(),
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+        let get = || {
+            file_handler.handle(
+                hyper::Request::builder()
+                    .uri("/files/keepass/pdb.kdbx")
+                    .method("GET")
+                    .body(hyper::Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        // the read cannot complete while the lock is held, so all the requests find it in progress
+        let lock = file_repo.lock().unwrap();
+        let mut first = get();
+        assert!(futures::poll!(&mut first).is_pending());
+        let mut others = futures::future::join_all((0..4).map(|_| get()));
+        assert!(futures::poll!(&mut others).is_pending());
+        // the request that started the read is cancelled without cancelling the read
+        drop(first);
+        drop(lock);
+
+        for response in others.await {
+            let response = response.unwrap();
+            assert_eq!(200, response.status());
+            assert_eq!(
+                vec![1, 2, 3],
+                hyper::body::to_bytes(response.into_body()).await.unwrap()
+            );
+        }
+        assert_eq!(
+            1,
+            file_handler
+                .coalescer
+                .started_reads
+                .load(std::sync::atomic::Ordering::Relaxed)
+        );
+
+        // a read started once the previous one completed goes to the database again
+        assert_eq!(200, get().await.unwrap().status());
+        assert_eq!(
+            2,
+            file_handler
+                .coalescer
+                .started_reads
+                .load(std::sync::atomic::Ordering::Relaxed)
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn it_streams_the_big_files() {
+        let file_repo = get_repo("streamed_reads");
+        let file: Vec<u8> = (0..crate::db::STREAM_CHUNK_BYTES * 3 + 100)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        file_repo
+            .lock()
+            .unwrap()
+            .save("keepass", "big.kdbx", &file, None, &ADDRESS)
+            .unwrap();
+        let file_handler = super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+        let get = |range: Option<&str>| {
+            let mut builder = hyper::Request::builder()
+                .uri("/files/keepass/big.kdbx")
+                .method("GET");
+            if let Some(range) = range {
+                builder = builder.header("range", range);
+            }
+            file_handler.handle(builder.body(hyper::Body::empty()).unwrap())
+        };
+
+        let response = get(None).await.unwrap();
+
+        assert_eq!(200, response.status());
+        assert_eq!(
+            file.len().to_string(),
+            response.headers()["content-length"].to_str().unwrap()
+        );
+        assert_eq!(
+            file,
+            hyper::body::to_bytes(response.into_body()).await.unwrap()
+        );
+
+        let start = crate::db::STREAM_CHUNK_BYTES - 10;
+        let end = crate::db::STREAM_CHUNK_BYTES * 2 + 10;
+        let response = get(Some(&format!("bytes={}-{}", start, end)))
+            .await
+            .unwrap();
+
+        assert_eq!(206, response.status());
+        assert_eq!(
+            file[start..end + 1],
+            hyper::body::to_bytes(response.into_body()).await.unwrap()
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn it_serves_byte_ranges() {
+        let file_repo = get_repo("byte_ranges");
+        file_repo
+            .lock()
+            .unwrap()
+            .save(
+                "keepass",
+                "pdb.kdbx",
+                &"content of current file".as_bytes().to_vec(),
+                None,
+                &ADDRESS,
+            )
+            .unwrap();
+
+        let file_handler = super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+        let content_range = |parts: &http::response::Parts| {
+            parts.headers["Content-Range"].to_str().unwrap().to_owned()
+        };
+
+        let (parts, body) = get_with_headers(&file_handler, &[("Range", "bytes=11-17")]).await;
+
+        assert_eq!(206, parts.status);
+        assert_eq!("bytes 11-17/23", content_range(&parts));
+        assert_eq!("current", body);
+
+        let (parts, body) = get_with_headers(&file_handler, &[("Range", "bytes=19-")]).await;
+
+        assert_eq!(206, parts.status);
+        assert_eq!("bytes 19-22/23", content_range(&parts));
+        assert_eq!("file", body);
+
+        let (parts, body) = get_with_headers(&file_handler, &[("Range", "bytes=23-30")]).await;
+
+        assert_eq!(416, parts.status);
+        assert_eq!("bytes */23", content_range(&parts));
+        assert_eq!("", body);
+
+        let (parts, body) = get_with_headers(&file_handler, &[]).await;
+
+        assert_eq!(200, parts.status);
+        assert!(!parts.headers.contains_key("Content-Range"));
+        assert_eq!("content of current file", body);
+    }
+
+    #[test(tokio::test)]
+    async fn it_honors_if_none_match() {
+        let file_repo = get_repo("get_if_none_match");
+        file_repo
+            .lock()
+            .unwrap()
+            .save("keepass", "pdb.kdbx", &vec![1, 2, 3], None, &ADDRESS)
+            .unwrap();
+
+        let file_handler = super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+
+        let (parts, body) = get_with_headers(&file_handler, &[("If-None-Match", "\"0\"")]).await;
+
+        assert_eq!(304, parts.status);
+        assert_eq!("\"0\"", parts.headers["etag"]);
+        assert!(parts.headers.contains_key("last-modified"));
+        assert_eq!("", body);
+
+        let (parts, body) = get_with_headers(&file_handler, &[("If-None-Match", "\"1\"")]).await;
+
+        assert_eq!(200, parts.status);
+        assert_eq!("\"0\"", parts.headers["etag"]);
+        assert_eq!("\u{1}\u{2}\u{3}", body);
+    }
+
+    #[test(tokio::test)]
+    async fn it_honors_the_min_version() {
+        let file_repo = get_repo("get_min_version");
+        {
+            let mut repo = file_repo.lock().unwrap();
+
+            repo.save("keepass", "pdb.kdbx", &vec![1], None, &ADDRESS)
+                .unwrap();
+            repo.save("keepass", "pdb.kdbx", &vec![1, 2], Some(0), &ADDRESS)
+                .unwrap();
+        }
+
+        let file_handler = super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+
+        let (parts, _body) = get_with_headers(&file_handler, &[("X-Min-Version", "2")]).await;
+
+        assert_eq!(412, parts.status);
+        assert_eq!("\"1\"", parts.headers["etag"]);
+
+        for min_version in ["0", "1"] {
+            let (parts, body) =
+                get_with_headers(&file_handler, &[("X-Min-Version", min_version)]).await;
+
+            assert_eq!(200, parts.status);
+            assert_eq!("\u{1}\u{2}", body);
+        }
+
+        let request = hyper::Request::builder()
+            .uri("/files/keepass/pdb.kdbx")
+            .header("X-Min-Version", "latest")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let result = file_handler.handle(request).await;
+
+        assert!(matches!(
+            result,
+            Err(router::RouterError::HandlerError(400, _))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn it_honors_if_range() {
+        let file_repo = get_repo("if_range");
+        {
+            let mut repo = file_repo.lock().unwrap();
+
+            repo.save(
+                "keepass",
+                "pdb.kdbx",
+                "content of current file".as_bytes().to_owned().as_ref(),
+                None,
+                &ADDRESS,
+            )
+            .unwrap();
+        }
+
+        let file_handler = super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+
+        let (parts, body) = get_with_headers(
+            &file_handler,
+            &[("Range", "bytes=0-6"), ("If-Range", "\"0\"")],
+        )
+        .await;
+
+        assert_eq!(206, parts.status);
+        assert_eq!(
+            "bytes 0-6/23",
+            parts
+                .headers
+                .get("Content-Range")
+                .unwrap()
+                .to_str()
+                .unwrap()
+        );
+        assert_eq!("content", body);
+
+        let (parts, body) = get_with_headers(
+            &file_handler,
+            &[("Range", "bytes=0-6"), ("If-Range", "\"1\"")],
+        )
+        .await;
+
+        assert_eq!(200, parts.status);
+        assert!(!parts.headers.contains_key("Content-Range"));
+        assert_eq!("content of current file", body);
+    }
+
+    #[test(tokio::test)]
+    async fn it_spools_big_uploads() {
+        let file_repo = get_repo("spool");
+
+        let file_handler = super::PutFileHandler {
+            file_repo: file_repo.clone(),
+            matcher: crate::get_matcher(&hyper::Method::PUT),
+            spool_threshold_bytes: Some(16),
+            magic_rules: Vec::new(),
+            upload_permits: None,
+            max_body_bytes: 1024 * 1024,
+        };
+
+        for (file_name, content) in [
+            ("small.txt", String::from("small content")),
+            ("big.txt", "big content ".repeat(100)),
+        ] {
+            let (mut sender, body) = hyper::Body::channel();
+            let mut req = hyper::Request::builder()
+                .uri(format!("/files/spool/{}", file_name))
+                .method("PUT")
+                .body(body)
+                .unwrap();
+            req.extensions_mut()
+                .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+
+            let chunks: Vec<String> = content
+                .as_bytes()
+                .chunks(10)
+                .map(|c| String::from_utf8(c.to_vec()).unwrap())
+                .collect();
+            tokio::spawn(async move {
+                for chunk in chunks {
+                    sender.send_data(chunk.into()).await.unwrap();
+                }
+            });
+
+            let response = file_handler.handle(req).await.unwrap();
+            assert_eq!(201, response.status());
+
+            let data = file_repo
+                .lock()
+                .unwrap()
+                .get("spool", file_name, true)
+                .unwrap();
+            assert_eq!(content.as_bytes(), data.file.unwrap());
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_limits_the_size_of_the_uploads() {
+        let file_repo = get_repo("max_body");
+
+        let file_handler = super::PutFileHandler {
+            file_repo: file_repo.clone(),
+            matcher: crate::get_matcher(&hyper::Method::PUT),
+            spool_threshold_bytes: None,
+            magic_rules: Vec::new(),
+            upload_permits: None,
+            max_body_bytes: 16,
+        };
+
+        let put = |file_name: &str, content: &'static str, content_length: bool| {
+            let mut builder = hyper::Request::builder()
+                .uri(format!("/files/max_body/{}", file_name))
+                .method("PUT");
+            if content_length {
+                builder = builder.header("content-length", content.len());
+            }
+            let mut req = builder.body(hyper::Body::from(content)).unwrap();
+            req.extensions_mut()
+                .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+            file_handler.handle(req)
+        };
+
+        let response = put("small.txt", "small content", true).await.unwrap();
+
+        assert_eq!(201, response.status());
+
+        // refused on its declared length, and on its actual length when it lies about it
+        for content_length in [true, false] {
+            let error = put("big.txt", "content too big for the limit", content_length)
+                .await
+                .unwrap_err();
+
+            assert!(
+                matches!(error, router::RouterError::HandlerError(413, _)),
+                "with content-length {}: {:?}",
+                content_length,
+                error
+            );
+        }
+        assert!(file_repo
+            .lock()
+            .unwrap()
+            .get("max_body", "big.txt", false)
+            .is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn it_only_creates_the_files_with_if_none_match() {
+        let file_repo = get_repo("create_only");
+        file_repo
+            .lock()
+            .unwrap()
+            .save("keepass", "existing.kdbx", &vec![1], None, &ADDRESS)
+            .unwrap();
+
+        let put_handler = super::PutFileHandler {
+            file_repo: file_repo.clone(),
+            matcher: crate::get_matcher(&hyper::Method::PUT),
+            spool_threshold_bytes: None,
+            magic_rules: Vec::new(),
+            upload_permits: None,
+            max_body_bytes: 1024 * 1024,
+        };
+
+        for (file_name, expected_status) in [("new.kdbx", 201), ("existing.kdbx", 412)] {
+            let mut req = hyper::Request::builder()
+                .uri(format!("/files/keepass/{}", file_name))
+                .method("PUT")
+                .header("If-None-Match", "*")
+                .header("ETag", "\"0\"")
+                .body(hyper::Body::from("content"))
+                .unwrap();
+            req.extensions_mut()
+                .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+
+            let status = match put_handler.handle(req).await {
+                Ok(response) => response.status().as_u16(),
+                Err(router::RouterError::HandlerError(status, _)) => status,
+                Err(error) => panic!("Unexpected error {:?}", error),
+            };
+
+            assert_eq!(expected_status, status, "{}", file_name);
+        }
+
+        let repo = file_repo.lock().unwrap();
+        assert_eq!(
+            Some(vec![1]),
+            repo.get("keepass", "existing.kdbx", true).unwrap().file
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn it_returns_the_uploaded_content_type() {
+        let file_repo = get_repo("content_type");
+
+        let put_handler = super::PutFileHandler {
+            file_repo: file_repo.clone(),
+            matcher: crate::get_matcher(&hyper::Method::PUT),
+            spool_threshold_bytes: None,
+            magic_rules: Vec::new(),
+            upload_permits: None,
+            max_body_bytes: 1024 * 1024,
+        };
+        let get_handler = super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+
+        for (file_name, content_type, expected) in [
+            ("notes.txt", Some("text/markdown"), "text/markdown"),
+            ("image.png", None, "image/png"),
+            ("pdb.kdbx", None, "application/octet-stream"),
+        ] {
+            let mut builder = hyper::Request::builder()
+                .uri(format!("/files/content_type/{}", file_name))
+                .method("PUT");
+            if let Some(content_type) = content_type {
+                builder = builder.header("Content-Type", content_type);
+            }
+            let mut req = builder.body(hyper::Body::from("content")).unwrap();
+            req.extensions_mut()
+                .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+
+            assert_eq!(201, put_handler.handle(req).await.unwrap().status());
+
+            let req = hyper::Request::builder()
+                .uri(format!("/files/content_type/{}", file_name))
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap();
+            let response = get_handler.handle(req).await.unwrap();
+
+            assert_eq!(expected, response.headers().get("Content-Type").unwrap());
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_records_the_comment_in_the_history() {
+        let file_repo = get_repo("history_comment");
+
+        let put_handler = super::PutFileHandler {
+            file_repo: file_repo.clone(),
+            matcher: crate::get_matcher(&hyper::Method::PUT),
+            spool_threshold_bytes: None,
+            magic_rules: Vec::new(),
+            upload_permits: None,
+            max_body_bytes: 1024 * 1024,
+        };
+        let mut req = hyper::Request::builder()
+            .uri("/files/keepass/pdb.kdbx")
+            .method("PUT")
+            .header("User-Agent", "KeeWeb/1.18")
+            .header("X-Change-Comment", "Added the bank password")
+            .body(hyper::Body::from("content"))
+            .unwrap();
+        req.extensions_mut()
+            .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+
+        assert_eq!(201, put_handler.handle(req).await.unwrap().status());
+
+        let versions_handler = super::FileVersionsHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher("GET"),
+            timezone: None,
+        };
+        let req = hyper::Request::builder()
+            .uri("/file-versions/keepass/pdb.kdbx")
+            .method("GET")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let body = hyper::body::to_bytes(versions_handler.handle(req).await.unwrap().into_body())
+            .await
+            .unwrap();
+        let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!("KeeWeb/1.18", entries[0]["userAgent"]);
+        assert_eq!("Added the bank password", entries[0]["comment"]);
+    }
+
+    #[test(tokio::test)]
+    async fn it_rejects_doomed_uploads_before_reading_the_body() {
+        let file_repo = get_repo("expect_continue");
+        file_repo
+            .lock()
+            .unwrap()
+            .save("keepass", "pdb.kdbx", &vec![1], None, &ADDRESS)
+            .unwrap();
+
+        let file_handler = super::PutFileHandler {
+            file_repo,
+            matcher: crate::get_matcher(&hyper::Method::PUT),
+            spool_threshold_bytes: None,
+            magic_rules: Vec::new(),
+            upload_permits: None,
+            max_body_bytes: 1024 * 1024,
+        };
+
+        // the body is never sent, reading it would block until the timeout
+        let (_sender, body) = hyper::Body::channel();
+        let mut req = hyper::Request::builder()
+            .uri("/files/keepass/pdb.kdbx")
+            .method("PUT")
+            .header("Expect", "100-continue")
+            .header("Content-Length", "1000")
+            .header("ETag", "\"3\"")
+            .body(body)
+            .unwrap();
+        req.extensions_mut()
+            .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+
+        let error =
+            tokio::time::timeout(std::time::Duration::from_secs(1), file_handler.handle(req))
+                .await
+                .unwrap()
+                .unwrap_err();
+
+        assert!(matches!(error, router::RouterError::HandlerError(412, _)));
+    }
+
+    #[test(tokio::test)]
+    async fn it_limits_the_concurrent_uploads() {
+        let file_repo = get_repo("concurrent_uploads");
+        file_repo
+            .lock()
+            .unwrap()
+            .save("keepass", "pdb.kdbx", &vec![1], None, &ADDRESS)
+            .unwrap();
+        let permits = std::sync::Arc::new(tokio::sync::Semaphore::new(1));
+
+        let put_handler = std::sync::Arc::new(super::PutFileHandler {
+            file_repo: file_repo.clone(),
+            matcher: crate::get_matcher(&hyper::Method::PUT),
+            spool_threshold_bytes: None,
+            magic_rules: Vec::new(),
+            upload_permits: Some(permits.clone()),
+            max_body_bytes: 1024 * 1024,
+        });
+        let get_handler = super::GetFileHandler {
+            file_repo,
+            read_replica: None,
+            matcher: crate::get_matcher(&hyper::Method::GET),
+            coalescer: Default::default(),
+        };
+
+        let get_put_request = |body| {
+            let mut req = hyper::Request::builder()
+                .uri("/files/keepass/other.kdbx")
+                .method("PUT")
+                .body(body)
+                .unwrap();
+            req.extensions_mut()
+                .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+            req
+        };
+
+        // the body of the first upload never completes, so it keeps its permit
+        let (_sender, body) = hyper::Body::channel();
+        let handler = put_handler.clone();
+        let request = get_put_request(body);
+        tokio::spawn(async move { handler.handle(request).await });
+        while permits.available_permits() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let req = hyper::Request::builder()
+            .uri("/files/keepass/pdb.kdbx")
+            .method("GET")
+            .body(hyper::Body::empty())
+            .unwrap();
+        assert_eq!(200, get_handler.handle(req).await.unwrap().status());
+
+        let response = put_handler
+            .handle(get_put_request(hyper::Body::from("content")))
+            .await
+            .unwrap();
+        assert_eq!(503, response.status());
+        assert!(response.headers().contains_key("Retry-After"));
+    }
+
+    #[test(tokio::test)]
+    async fn it_validates_magic_bytes() {
+        let file_handler = super::PutFileHandler {
+            file_repo: get_repo("magic"),
+            matcher: crate::get_matcher(&hyper::Method::PUT),
+            spool_threshold_bytes: None,
+            magic_rules: vec![(String::from("kdbx"), vec![0x03, 0xd9, 0xa2, 0x9a])],
+            upload_permits: None,
+            max_body_bytes: 1024 * 1024,
+        };
+
+        for (file_name, content, expected_status) in [
+            ("valid.kdbx", vec![0x03, 0xd9, 0xa2, 0x9a, 0x67, 0xfb], 201),
+            ("invalid.KDBX", vec![0x50, 0x4b, 0x03, 0x04], 422),
+            ("unconfigured.txt", vec![0x50, 0x4b, 0x03, 0x04], 201),
+        ] {
+            let mut req = hyper::Request::builder()
+                .uri(format!("/files/magic/{}", file_name))
+                .method("PUT")
+                .body(hyper::Body::from(content))
+                .unwrap();
+            req.extensions_mut()
+                .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+
+            let status = match file_handler.handle(req).await {
+                Ok(response) => response.status().as_u16(),
+                Err(router::RouterError::HandlerError(status, _)) => status,
+                Err(e) => panic!("Unexpected error {:?}", e),
+            };
+
+            assert_eq!(expected_status, status, "for {}", file_name);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_isolates_the_tenants() {
+        let file_repo = get_repo("tenants");
+        let api_keys = std::sync::Arc::new(std::collections::HashMap::from([
+            (String::from("alice-key"), String::from("alice")),
+            (String::from("bob-key"), String::from("bob")),
+        ]));
+
+        let put_handler = super::TenantHandler {
+            handler: Box::from(super::PutFileHandler {
+                file_repo: file_repo.clone(),
+                matcher: crate::get_matcher(&hyper::Method::PUT),
+                spool_threshold_bytes: None,
+                magic_rules: Vec::new(),
+                upload_permits: None,
+                max_body_bytes: 1024 * 1024,
+            }),
+            api_keys: api_keys.clone(),
+        };
+        let get_handler = super::TenantHandler {
+            handler: Box::from(super::GetFileHandler {
+                file_repo: file_repo.clone(),
+                read_replica: None,
+                matcher: crate::get_matcher(&hyper::Method::GET),
+                coalescer: Default::default(),
+            }),
+            api_keys,
+        };
+
+        let request = |method: &str, key: Option<&str>, body: &'static str| {
+            let mut builder = hyper::Request::builder()
+                .uri("/files/keepass/pdb.kdbx")
+                .method(method);
+            if let Some(key) = key {
+                builder = builder.header("Authorization", format!("Bearer {}", key));
+            }
+            let mut req = builder.body(hyper::Body::from(body)).unwrap();
+            req.extensions_mut()
+                .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+            req
+        };
+
+        for (key, content) in [("alice-key", "alice content"), ("bob-key", "bob content")] {
+            let response = put_handler
+                .handle(request("PUT", Some(key), content))
+                .await
+                .unwrap();
+            assert_eq!(201, response.status());
+        }
+
+        for (key, content) in [("alice-key", "alice content"), ("bob-key", "bob content")] {
+            let response = get_handler
+                .handle(request("GET", Some(key), ""))
+                .await
+                .unwrap();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            assert_eq!(content, body);
+        }
+
+        let data = file_repo
+            .lock()
+            .unwrap()
+            .get("alice/keepass", "pdb.kdbx", true)
+            .unwrap();
+        assert_eq!("alice content".as_bytes(), data.file.unwrap());
+        assert!(file_repo
+            .lock()
+            .unwrap()
+            .get("keepass", "pdb.kdbx", false)
+            .is_err());
+
+        for key in [None, Some("unknown-key")] {
+            let result = get_handler.handle(request("GET", key, "")).await;
+            assert!(matches!(
+                result,
+                Err(router::RouterError::HandlerError(401, _))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_keeps_the_absolute_paths_under_the_root_of_the_tenant() {
+        let file_repo = get_repo("tenants_absolute_paths");
+        let api_keys = std::sync::Arc::new(std::collections::HashMap::from([
+            (String::from("alice-key"), String::from("alice")),
+            (String::from("bob-key"), String::from("bob")),
+        ]));
+
+        let put_handler = super::TenantHandler {
+            handler: Box::from(super::PutFileHandler {
+                file_repo: file_repo.clone(),
+                matcher: crate::get_matcher(&hyper::Method::PUT),
+                spool_threshold_bytes: None,
+                magic_rules: Vec::new(),
+                upload_permits: None,
+                max_body_bytes: 1024 * 1024,
+            }),
+            api_keys: api_keys.clone(),
+        };
+        let get_handler = super::TenantHandler {
+            handler: Box::from(super::GetFileHandler {
+                file_repo: file_repo.clone(),
+                read_replica: None,
+                matcher: crate::get_matcher(&hyper::Method::GET),
+                coalescer: Default::default(),
+            }),
+            api_keys,
+        };
+
+        let request = |method: &str, uri: &str, key: &str, body: &'static str| {
+            let mut req = hyper::Request::builder()
+                .uri(uri)
+                .method(method)
+                .header("Authorization", format!("Bearer {}", key))
+                .body(hyper::Body::from(body))
+                .unwrap();
+            req.extensions_mut()
+                .insert(std::net::SocketAddr::new(*ADDRESS, 8080));
+            req
+        };
+
+        let response = put_handler
+            .handle(request(
+                "PUT",
+                "/files//shared/x",
+                "alice-key",
+                "alice content",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(201, response.status());
+
+        let result = get_handler
+            .handle(request("GET", "/files//shared/x", "bob-key", ""))
+            .await;
+        assert!(matches!(
+            result,
+            Err(router::RouterError::HandlerError(404, _))
+        ));
+
+        let result = put_handler
+            .handle(request("PUT", "/files/../alice/shared/x", "bob-key", ""))
+            .await;
+        assert!(matches!(
+            result,
+            Err(router::RouterError::InvalidRequest(_))
+        ));
+
+        let data = file_repo
+            .lock()
+            .unwrap()
+            .get("alice/shared", "x", true)
+            .unwrap();
+        assert_eq!("alice content".as_bytes(), data.file.unwrap());
+    }
 }
@@ -1,3 +1,5 @@
+pub mod audit;
+pub mod configuration;
 pub mod db;
 pub mod handlers;
 pub mod log;
@@ -18,22 +20,38 @@ where
         .unwrap()
 }
 
-pub fn get_version_info_from_headers(
-    headers: &http::HeaderMap,
-) -> (Option<i32>, chrono::DateTime<chrono::Utc>) {
+/// Range of bytes requested by the client through the `range` header
+#[derive(Debug, PartialEq)]
+pub enum RequestedRange {
+    /// The whole content should be sent
+    Full,
+    /// Only the given bytes should be sent, bounds included
+    Partial(usize, usize),
+    /// The requested range is beyond the content
+    Unsatisfiable,
+}
+
+/// Decodes the version contained in an etag-like header, e.g. `"18"`
+pub fn get_version_from_header(headers: &http::HeaderMap, header: &str) -> Option<i32> {
     lazy_static::lazy_static! {
         static ref ETAG_REGEX: regex::Regex = regex::Regex::new(r#"\s*"(\d+)"\s*"#).unwrap();
     }
 
-    let etag = headers
-        .get("etag")
+    headers
+        .get(header)
         .and_then(|etag| etag.to_str().ok())
         .and_then(|h| {
             ETAG_REGEX
                 .captures(h)
                 .and_then(|m| m.get(1).map(|c| c.as_str()))
         })
-        .and_then(|etag| etag.parse().ok());
+        .and_then(|etag| etag.parse().ok())
+}
+
+pub fn get_version_info_from_headers(
+    headers: &http::HeaderMap,
+) -> (Option<i32>, chrono::DateTime<chrono::Utc>) {
+    let etag = get_version_from_header(headers, "etag");
 
     let timestamp = headers
         .get("last-modified")
@@ -48,9 +66,72 @@ pub fn get_version_info_from_headers(
     (etag, timestamp)
 }
 
+/// Decodes the `range` header for a content of the given length
+///
+/// Only single ranges are supported, other ranges result in the whole content being sent.
+/// If an `if-range` header is given, the range is only honored if it matches the current version
+pub fn get_requested_range(
+    headers: &http::HeaderMap,
+    version: i32,
+    length: usize,
+) -> RequestedRange {
+    lazy_static::lazy_static! {
+        static ref RANGE_REGEX: regex::Regex =
+            regex::Regex::new(r"^\s*bytes\s*=\s*(\d*)\s*-\s*(\d*)\s*$").unwrap();
+    }
+
+    if headers.contains_key("if-range") {
+        // only strong etags are valid validators, dates are not supported
+        let is_strong = headers
+            .get("if-range")
+            .and_then(|h| h.to_str().ok())
+            .map(|h| !h.trim_start().starts_with("W/"))
+            .unwrap_or(false);
+        if !is_strong || get_version_from_header(headers, "if-range") != Some(version) {
+            return RequestedRange::Full;
+        }
+    }
+
+    let captures = headers
+        .get("range")
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| RANGE_REGEX.captures(range));
+
+    let captures = match captures {
+        Some(captures) => captures,
+        None => return RequestedRange::Full,
+    };
+
+    let start: Option<usize> = captures[1].parse().ok();
+    let end: Option<usize> = captures[2].parse().ok();
+
+    match (start, end) {
+        (Some(start), end) => {
+            if start >= length {
+                RequestedRange::Unsatisfiable
+            } else {
+                let end = end.map_or(length - 1, |end| end.min(length - 1));
+                if end < start {
+                    RequestedRange::Full
+                } else {
+                    RequestedRange::Partial(start, end)
+                }
+            }
+        }
+        (None, Some(suffix)) => {
+            if suffix == 0 || length == 0 {
+                RequestedRange::Unsatisfiable
+            } else {
+                RequestedRange::Partial(length - suffix.min(length), length - 1)
+            }
+        }
+        (None, None) => RequestedRange::Full,
+    }
+}
+
 fn get_path_from_uri(uri: &http::Uri) -> Result<&str, router::RouterError> {
     lazy_static::lazy_static! {
-        static ref URI_REGEX: regex::Regex = regex::Regex::new(r"^/(files|file-versions)/(.+)").unwrap();
+        static ref URI_REGEX: regex::Regex = regex::Regex::new(r"^/(files|file-versions|files-meta)/(.+)").unwrap();
     }
     let matches = URI_REGEX.captures(uri.path());
     match matches {
@@ -62,8 +143,7 @@ fn get_path_from_uri(uri: &http::Uri) -> Result<&str, router::RouterError> {
 pub fn get_path_and_name_from_uri(
     uri: &http::Uri,
 ) -> Result<(String, String), router::RouterError> {
-    let full_path = get_path_from_uri(uri)?;
-    let full_path = std::path::PathBuf::from(full_path);
+    let full_path = get_relative_path(get_path_from_uri(uri)?)?;
     let file_path = full_path
         .parent()
         .unwrap_or(std::path::Path::new(""))
@@ -75,45 +155,376 @@ pub fn get_path_and_name_from_uri(
     Ok((file_path.into(), file_name.into()))
 }
 
-pub fn get_file_handlers(sqlite_path: &std::path::PathBuf) -> Vec<Box<dyn router::Handler>> {
+/// Keeps only the normal segments of the path so that it cannot escape the root of a tenant:
+/// the empty and `.` segments are dropped and the `..` ones refused with a 400
+fn get_relative_path(path: &str) -> Result<std::path::PathBuf, router::RouterError> {
+    std::path::Path::new(path)
+        .components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(segment) => Some(Ok(segment)),
+            std::path::Component::RootDir | std::path::Component::CurDir => None,
+            _ => Some(Err(router::InvalidRequest(String::from("Invalid path")))),
+        })
+        .collect()
+}
+
+/// Replaces the backslashes of the path, sent by some Windows clients, by forward slashes
+fn normalize_backslashes(uri: &http::Uri) -> Result<http::Uri, router::RouterError> {
+    let path = uri.path();
+    if !path.contains('\\') && !path.to_ascii_uppercase().contains("%5C") {
+        return Ok(uri.clone());
+    }
+    let path = path
+        .replace('\\', "/")
+        .replace("%5C", "/")
+        .replace("%5c", "/");
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(
+        path_and_query
+            .parse::<http::uri::PathAndQuery>()
+            .map_err(|_| router::InvalidRequest(String::from("Invalid path")))?,
+    );
+    http::Uri::from_parts(parts).map_err(|_| router::InvalidRequest(String::from("Invalid path")))
+}
+
+/// Refuses with a 400 the paths made of more than `max_path_depth` segments, file name included
+fn check_path_depth(uri: &http::Uri, max_path_depth: usize) -> Result<(), router::RouterError> {
+    let depth = std::path::Path::new(get_path_from_uri(uri)?)
+        .components()
+        .count();
+    if depth > max_path_depth {
+        return Err(router::InvalidRequest(format!(
+            "Path has {} segments, at most {} are allowed",
+            depth, max_path_depth
+        )));
+    }
+    Ok(())
+}
+
+/// Gets the path and name of the file, under the root of the tenant of the request if any
+fn get_tenant_path_and_name(
+    uri: &http::Uri,
+    extensions: &http::Extensions,
+) -> Result<(String, String), router::RouterError> {
+    let (file_path, file_name) = get_path_and_name_from_uri(uri)?;
+    match extensions.get::<handlers::TenantRoot>() {
+        Some(handlers::TenantRoot(root)) if file_path.is_empty() => {
+            Ok((root.to_owned(), file_name))
+        }
+        Some(handlers::TenantRoot(root)) => Ok((
+            std::path::Path::new(root)
+                .join(file_path)
+                .to_string_lossy()
+                .into(),
+            file_name,
+        )),
+        None => Ok((file_path, file_name)),
+    }
+}
+
+/// Gets the path, name and metadata key of the file, the key being the last segment of the uri
+fn get_tenant_path_name_and_key(
+    uri: &http::Uri,
+    extensions: &http::Extensions,
+) -> Result<(String, String, String), router::RouterError> {
+    let (full_path, key) = get_tenant_path_and_name(uri, extensions)?;
+    let full_path = std::path::PathBuf::from(full_path);
+    let file_path = full_path
+        .parent()
+        .unwrap_or(std::path::Path::new(""))
+        .to_string_lossy();
+    let file_name = full_path
+        .file_name()
+        .ok_or(router::InvalidRequest(String::from("Invalid url")))?
+        .to_string_lossy();
+    Ok((file_path.into(), file_name.into(), key))
+}
+
+/// Decodes an hexadecimal string such as `03d9a29a`
+fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(format!("Invalid hexadecimal string {}", hex))
+        })
+        .collect()
+}
+
+pub fn get_file_handlers(
+    configuration: &crate::configuration::FileConfiguration,
+) -> Vec<Box<dyn router::Handler>> {
     let file_repo = std::sync::Arc::new(std::sync::Mutex::new(
-        crate::db::FilesDB::new(&sqlite_path).unwrap(),
+        crate::db::FilesDB::open(&configuration.root_path, &configuration.sqlite)
+            .unwrap()
+            .with_max_total_bytes(configuration.max_total_bytes)
+            .with_delete_as_tombstone(configuration.delete_as_tombstone)
+            .with_max_retained_versions(configuration.max_retained_versions)
+            .with_audit_log(
+                configuration.audit_log.as_deref(),
+                configuration.audit_log_max_bytes,
+            )
+            .unwrap(),
     ));
-    ::log::info!("Initializing file repository in {:?}", &sqlite_path);
-    vec![
+    ::log::info!(
+        "Initializing file repository in {:?}",
+        &configuration.root_path
+    );
+    let read_replica = configuration
+        .read_replica_path
+        .as_deref()
+        .map(|read_replica_path| {
+            std::sync::Arc::new(std::sync::Mutex::new(
+                crate::db::ReadReplica::open(read_replica_path).unwrap(),
+            ))
+        });
+    let missing_version_status = if configuration.missing_version_as_bad_request {
+        400
+    } else {
+        428
+    };
+    let mut file_handlers: Vec<Box<dyn router::Handler>> = vec![
         Box::from(handlers::DeleteFileHandler {
             file_repo: file_repo.clone(),
             matcher: get_matcher(&hyper::Method::DELETE),
+            missing_version_status,
         }),
         Box::from(handlers::GetFileHandler {
             file_repo: file_repo.clone(),
-            matcher: get_matcher(&hyper::Method::GET),
-        }),
-        Box::from(handlers::GetFileHandler {
-            file_repo: file_repo.clone(),
-            matcher: get_matcher(&hyper::Method::HEAD),
+            read_replica: read_replica.clone(),
+            matcher: router::matcher::builder()
+                .regex_path("^/files/")
+                .with_methods(&[hyper::Method::GET, hyper::Method::HEAD])
+                .build()
+                .unwrap(),
+            coalescer: Default::default(),
         }),
         Box::from(handlers::MoveFileHandler {
             file_repo: file_repo.clone(),
             matcher: get_matcher("MOVE"),
+            missing_version_status,
+        }),
+        Box::from(handlers::SwapFileHandler {
+            file_repo: file_repo.clone(),
+            matcher: get_matcher("SWAP"),
         }),
         Box::from(handlers::PutFileHandler {
             file_repo: file_repo.clone(),
             matcher: get_matcher(&hyper::Method::PUT),
+            spool_threshold_bytes: configuration.spool_threshold_bytes,
+            magic_rules: configuration
+                .validate_magic
+                .iter()
+                .map(|rule| {
+                    (
+                        rule.extension.to_owned(),
+                        decode_hex(&rule.prefix)
+                            .expect("Invalid magic prefix in file configuration"),
+                    )
+                })
+                .collect(),
+            upload_permits: configuration
+                .max_concurrent_uploads
+                .map(|permits| std::sync::Arc::new(tokio::sync::Semaphore::new(permits))),
+            max_body_bytes: configuration.max_body_bytes,
         }),
         Box::from(handlers::FileVersionsHandler {
             file_repo: file_repo.clone(),
+            read_replica: read_replica.clone(),
+            timezone: configuration.history_timezone,
             matcher: router::matcher::builder()
                 .regex_path("^/file-versions/")
                 .with_method(&hyper::Method::GET)
                 .build()
                 .unwrap(),
         }),
-    ]
+        Box::from(handlers::FileMetadataHandler {
+            file_repo: file_repo.clone(),
+            read_replica: read_replica.clone(),
+            matcher: router::matcher::builder()
+                .regex_path("^/files-meta/")
+                .with_methods(&[
+                    hyper::Method::GET,
+                    hyper::Method::PUT,
+                    hyper::Method::DELETE,
+                ])
+                .build()
+                .unwrap(),
+        }),
+    ];
+    file_handlers = file_handlers
+        .into_iter()
+        .map(|handler| {
+            Box::from(handlers::PathDepthHandler {
+                handler,
+                max_path_depth: configuration.max_path_depth,
+            }) as Box<dyn router::Handler>
+        })
+        .collect();
+    if configuration.normalize_backslashes {
+        file_handlers = file_handlers
+            .into_iter()
+            .map(|handler| {
+                Box::from(handlers::NormalizeBackslashesHandler { handler })
+                    as Box<dyn router::Handler>
+            })
+            .collect();
+    }
+    if !configuration.api_keys.is_empty() {
+        let api_keys = std::sync::Arc::new(configuration.api_keys.clone());
+        file_handlers = file_handlers
+            .into_iter()
+            .map(|handler| {
+                Box::from(handlers::TenantHandler {
+                    handler,
+                    api_keys: api_keys.clone(),
+                }) as Box<dyn router::Handler>
+            })
+            .collect();
+    }
+    if configuration.maintenance_enabled {
+        let mut backup_handler: Box<dyn router::Handler> = Box::from(handlers::BackupHandler {
+            file_repo: file_repo.clone(),
+            matcher: router::matcher::builder()
+                .exact_path("/maintenance/backup")
+                .with_method(&hyper::Method::GET)
+                .build()
+                .unwrap(),
+        });
+        // the backup contains the files of every tenant
+        if !configuration.api_keys.is_empty() {
+            backup_handler = Box::from(handlers::AdminHandler {
+                handler: backup_handler,
+                admin_key: configuration.admin_key.clone(),
+            });
+        }
+        file_handlers.push(backup_handler);
+    }
+    file_handlers
 }
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn decode_hex() {
+        assert_eq!(
+            Ok(vec![0x03, 0xd9, 0xa2, 0x9a]),
+            super::decode_hex("03d9A29a")
+        );
+        assert!(super::decode_hex("03d").is_err());
+        assert!(super::decode_hex("zz").is_err());
+    }
+
+    #[tokio::test]
+    async fn it_names_the_handler_in_debug_routing() {
+        let root_path = std::path::PathBuf::from("target/test/files/debug_routing");
+        if root_path.exists() {
+            std::fs::remove_dir_all(&root_path).unwrap();
+        }
+        let configuration = crate::configuration::FileConfiguration {
+            root_path,
+            ..Default::default()
+        };
+        let mut router = router::Router::new();
+        router.add_handlers(super::get_file_handlers(&configuration));
+
+        let get_request = || {
+            hyper::Request::builder()
+                .uri("/files/keepass/pdb.kdbx")
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap()
+        };
+
+        let response = router.handle(get_request()).await.unwrap();
+
+        assert!(!response.headers().contains_key("x-handler"));
+
+        router.set_debug_routing(true);
+        let response = router.handle(get_request()).await.unwrap();
+
+        assert_eq!(404, response.status());
+        assert_eq!("GetFileHandler", response.headers()["x-handler"]);
+    }
+
+    #[tokio::test]
+    async fn it_requires_the_version_of_the_mutations() {
+        for (missing_version_as_bad_request, expected_status) in [(false, 428), (true, 400)] {
+            let root_path = std::path::PathBuf::from(format!(
+                "target/test/files/missing_version_{}",
+                missing_version_as_bad_request
+            ));
+            if root_path.exists() {
+                std::fs::remove_dir_all(&root_path).unwrap();
+            }
+            let configuration = crate::configuration::FileConfiguration {
+                root_path,
+                missing_version_as_bad_request,
+                ..Default::default()
+            };
+            let mut router = router::Router::new();
+            router.add_handlers(super::get_file_handlers(&configuration));
+
+            for method in ["DELETE", "MOVE"] {
+                let request = hyper::Request::builder()
+                    .uri("/files/keepass/pdb.kdbx")
+                    .method(method)
+                    .header("destination", "/files/keepass/moved.kdbx")
+                    .body(hyper::Body::empty())
+                    .unwrap();
+
+                let response = router.handle(request).await.unwrap();
+
+                assert_eq!(expected_status, response.status(), "{}", method);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_requires_the_admin_key_for_the_backup_with_tenants() {
+        let root_path = std::path::PathBuf::from("target/test/files/tenant_backup");
+        if root_path.exists() {
+            std::fs::remove_dir_all(&root_path).unwrap();
+        }
+        let configuration = crate::configuration::FileConfiguration {
+            root_path,
+            api_keys: std::collections::HashMap::from([(
+                String::from("alice-key"),
+                String::from("alice"),
+            )]),
+            admin_key: Some(String::from("admin-key")),
+            maintenance_enabled: true,
+            ..Default::default()
+        };
+        let mut router = router::Router::new();
+        router.add_handlers(super::get_file_handlers(&configuration));
+
+        for (key, expected_status) in [
+            (None, 401),
+            (Some("alice-key"), 401),
+            (Some("admin-key"), 200),
+        ] {
+            let mut request = hyper::Request::builder()
+                .uri("/maintenance/backup")
+                .method("GET");
+            if let Some(key) = key {
+                request = request.header("authorization", format!("Bearer {}", key));
+            }
+
+            let response = router
+                .handle(request.body(hyper::Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            assert_eq!(expected_status, response.status(), "{:?}", key);
+        }
+    }
+
     #[test]
     fn get_path_and_name_from_uri() {
         let uri = http::Uri::from_static("http://fakedomain/files/test/truc.txt");
@@ -127,6 +538,50 @@ mod tests {
 
         assert_eq!(String::from(""), path, "Wrong path");
         assert_eq!(String::from("testme"), file, "Wrong path");
+
+        let uri = http::Uri::from_static("http://fakedomain/files//shared/./truc.txt");
+        let (path, file) = super::get_path_and_name_from_uri(&uri).expect("Failed to decode");
+
+        assert_eq!(String::from("shared"), path, "Wrong path");
+        assert_eq!(String::from("truc.txt"), file, "Wrong path");
+
+        let uri = http::Uri::from_static("http://fakedomain/files/test/../../truc.txt");
+        assert!(matches!(
+            super::get_path_and_name_from_uri(&uri),
+            Err(router::RouterError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn normalize_backslashes() {
+        let backslashes =
+            http::Uri::from_static("http://fakedomain/files/keepass%5Csub%5cpdb.kdbx?a=%5C");
+        let slashes = http::Uri::from_static("http://fakedomain/files/keepass/sub/pdb.kdbx?a=%5C");
+
+        let normalized = super::normalize_backslashes(&backslashes).unwrap();
+
+        assert_eq!(slashes, normalized);
+        assert_eq!(
+            super::get_path_and_name_from_uri(&slashes).unwrap(),
+            super::get_path_and_name_from_uri(&normalized).unwrap()
+        );
+        assert_eq!(slashes, super::normalize_backslashes(&slashes).unwrap());
+    }
+
+    #[test]
+    fn check_path_depth() {
+        let below = http::Uri::from_static("http://fakedomain/files/a/b.txt");
+        let at = http::Uri::from_static("http://fakedomain/files/a/b/c.txt");
+        let above = http::Uri::from_static("http://fakedomain/files/a/b/c/d.txt");
+
+        assert_eq!(Ok(()), super::check_path_depth(&below, 3));
+        assert_eq!(Ok(()), super::check_path_depth(&at, 3));
+        assert_eq!(
+            Err(router::InvalidRequest(String::from(
+                "Path has 4 segments, at most 3 are allowed"
+            ))),
+            super::check_path_depth(&above, 3)
+        );
     }
 
     #[test]
@@ -184,4 +639,34 @@ mod tests {
 
         assert_eq!(None, version, "Wrong version decoded");
     }
+
+    #[test]
+    fn get_requested_range_with_if_range() {
+        let mut headers = http::HeaderMap::new();
+
+        headers.append("range", http::HeaderValue::from_static("bytes=2-5"));
+
+        assert_eq!(
+            super::RequestedRange::Partial(2, 5),
+            super::get_requested_range(&headers, 3, 10)
+        );
+
+        headers.append("if-range", http::HeaderValue::from_static("\"3\""));
+
+        assert_eq!(
+            super::RequestedRange::Partial(2, 5),
+            super::get_requested_range(&headers, 3, 10)
+        );
+        assert_eq!(
+            super::RequestedRange::Full,
+            super::get_requested_range(&headers, 4, 10)
+        );
+
+        headers.insert("if-range", http::HeaderValue::from_static("W/\"3\""));
+
+        assert_eq!(
+            super::RequestedRange::Full,
+            super::get_requested_range(&headers, 3, 10)
+        );
+    }
 }
@@ -27,9 +27,31 @@ pub enum FileLogEntryType {
         #[serde(rename = "pathFrom")]
         path_from: std::path::PathBuf,
     },
+    Swap {
+        version: u32,
+        hash: String,
+        #[serde(rename = "pathWith")]
+        path_with: std::path::PathBuf,
+    },
+    /// Deletion keeping an empty current version
+    Tombstone {
+        version: u32,
+    },
 }
 
 impl FileLogEntryType {
+    pub fn version(&self) -> u32 {
+        match self {
+            FileLogEntryType::Creation { version, .. } => *version,
+            FileLogEntryType::Deletion { version } => *version,
+            FileLogEntryType::Update { version, .. } => *version,
+            FileLogEntryType::MoveTo { version, .. } => *version,
+            FileLogEntryType::MoveFrom { version, .. } => *version,
+            FileLogEntryType::Swap { version, .. } => *version,
+            FileLogEntryType::Tombstone { version } => *version,
+        }
+    }
+
     pub fn new(
         entry_type: String,
         version: u32,
@@ -66,6 +88,18 @@ impl FileLogEntryType {
                     Err(String::from("No path given for a MoveTo entry"))
                 }
             }
+            "SWAP" => {
+                if let (Some(hash), Some(path)) = (hash, path) {
+                    Ok(FileLogEntryType::Swap {
+                        version,
+                        hash,
+                        path_with: std::path::PathBuf::from(path),
+                    })
+                } else {
+                    Err(String::from("Hash or path not given for a Swap entry"))
+                }
+            }
+            "TOMBSTONE" => Ok(FileLogEntryType::Tombstone { version }),
             "UPDATE" => {
                 if let Some(hash) = hash {
                     Ok(FileLogEntryType::Update { version, hash })
@@ -83,4 +117,10 @@ pub struct FileLogEntry {
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub address: std::net::IpAddr,
     pub entry: FileLogEntryType,
+    /// User-Agent of the client that made the change
+    #[serde(rename = "userAgent", default)]
+    pub user_agent: Option<String>,
+    /// Comment given by the client in a X-Change-Comment header
+    #[serde(default)]
+    pub comment: Option<String>,
 }
@@ -17,10 +17,32 @@ pub trait AVReceiverInterface: Sync + Send {
     async fn increment_volume(&self, increment: bool) -> i16;
 
     /// Gives the current volume and mute status of the receiver
-    async fn get_volume(&self) -> (i16, bool);
+    ///
+    /// The volume is `None` when the receiver reports `--`, i.e. it is turned all the way down
+    async fn get_volume(&self) -> (Option<i16>, bool);
 
     /// Sets the volume, taking a percentage in input, and returns the resulting volume
     async fn set_volume(&self, volume: i16) -> i16;
+
+    /// Gives the currently selected input, empty if the receiver could not be queried
+    async fn get_input(&self) -> String;
+
+    /// Selects the given input and returns the resulting one
+    async fn set_input(&self, input: String) -> String;
+}
+
+tokio::task_local! {
+    /// Headers of the incoming request on behalf of which the receiver is queried
+    static REQUEST_HEADERS: hyper::HeaderMap;
+}
+
+/// Runs the future with the headers of the incoming request, so that the receiver can forward
+/// the ones configured with [AVReceiverBuilder::with_forwarded_request_headers]
+pub async fn with_request_headers<F: std::future::Future>(
+    headers: hyper::HeaderMap,
+    future: F,
+) -> F::Output {
+    REQUEST_HEADERS.scope(headers, future).await
 }
 
 /// Builder for [AVReceiver](crate::avreceiver::AVReceiver)
@@ -30,6 +52,9 @@ pub struct AVReceiverBuilder {
     desired_input: String,
     min_volume: f32,
     max_volume: f32,
+    upstream_tally: Option<std::sync::Arc<router::debug::UpstreamTally>>,
+    headers: Vec<(String, String)>,
+    forwarded_request_headers: Vec<String>,
 }
 
 impl AVReceiverBuilder {
@@ -49,6 +74,27 @@ impl AVReceiverBuilder {
         self
     }
 
+    /// Counts the successes and failures of the commands sent to the receiver
+    pub fn with_upstream_tally(
+        mut self,
+        upstream_tally: std::sync::Arc<router::debug::UpstreamTally>,
+    ) -> AVReceiverBuilder {
+        self.upstream_tally = Some(upstream_tally);
+        self
+    }
+
+    /// Gives headers sent with every command, e.g. to authenticate to the receiver
+    pub fn with_headers(mut self, headers: Vec<(String, String)>) -> AVReceiverBuilder {
+        self.headers = headers;
+        self
+    }
+
+    /// Gives the names of the headers of the incoming requests that are sent with the commands
+    pub fn with_forwarded_request_headers(mut self, headers: Vec<String>) -> AVReceiverBuilder {
+        self.forwarded_request_headers = headers;
+        self
+    }
+
     /// Gives the minimum and maximum volume possible on the receiver
     #[allow(dead_code)]
     pub fn with_volume_range(mut self, min: f32, max: f32) -> AVReceiverBuilder {
@@ -65,31 +111,107 @@ impl AVReceiverBuilder {
         AVReceiver {
             scheme: self.scheme,
             authority: self.authority,
+            client: hyper::Client::new(),
             desired_input: self.desired_input,
             min_volume: self.min_volume,
             max_volume: self.max_volume,
+            upstream_tally: self.upstream_tally,
+            headers: self.headers,
+            forwarded_request_headers: self.forwarded_request_headers,
         }
     }
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug)]
 struct Value {
-    #[serde(rename = "value")]
     value: String,
 }
 
-#[derive(Debug, serde::Deserialize)]
+/// Status of the receiver
+///
+/// The firmwares send many other elements, possibly nested or repeated, so the deserialization
+/// only keeps the first occurrence of the elements we use and skips everything else.
+#[derive(Debug)]
 struct Item {
-    #[serde(rename = "Power")]
     power: Option<Value>,
-    #[serde(rename = "InputFuncSelect")]
     input_func_select: Option<Value>,
-    #[serde(rename = "MasterVolume")]
     master_volume: Option<Value>,
-    #[serde(rename = "Mute")]
     mute: Option<Value>,
 }
 
+struct ValueVisitor;
+
+struct ItemVisitor;
+
+impl<'de> serde::de::Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an element containing a value")
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut value = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "value" && value.is_none() {
+                value = Some(map.next_value()?);
+            } else {
+                map.next_value::<serde::de::IgnoredAny>()?;
+            }
+        }
+        Ok(Value {
+            value: value.unwrap_or_default(),
+        })
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for ItemVisitor {
+    type Value = Item;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a receiver status")
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Item, A::Error> {
+        let mut item = Item {
+            power: None,
+            input_func_select: None,
+            master_volume: None,
+            mute: None,
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            let field = match key.as_str() {
+                "Power" => &mut item.power,
+                "InputFuncSelect" => &mut item.input_func_select,
+                "MasterVolume" => &mut item.master_volume,
+                "Mute" => &mut item.mute,
+                _ => {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                    continue;
+                }
+            };
+            if field.is_none() {
+                *field = Some(map.next_value()?);
+            } else {
+                map.next_value::<serde::de::IgnoredAny>()?;
+            }
+        }
+        Ok(item)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Value {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_map(ValueVisitor)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Item {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Item, D::Error> {
+        deserializer.deserialize_map(ItemVisitor)
+    }
+}
+
 impl Item {
     pub fn is_powered_on(&self) -> bool {
         self.power
@@ -116,6 +238,14 @@ impl Item {
             .unwrap_or(receiver.min_volume)
     }
 
+    /// Volume in percent, `None` if the receiver reports `--`
+    pub fn get_volume_level(&self, receiver: &AVReceiver) -> Option<i16> {
+        match &self.master_volume {
+            Some(volume) if volume.value.trim() == "--" => None,
+            _ => Some(self.get_volume_percent(receiver)),
+        }
+    }
+
     pub fn get_volume_percent(&self, receiver: &AVReceiver) -> i16 {
         self.master_volume
             .as_ref()
@@ -134,9 +264,14 @@ static CMD_VOLUME: &str = "formiPhoneAppVolume.xml?1+";
 pub struct AVReceiver {
     scheme: String,
     authority: String,
+    /// Client of all the commands, so that its connections are reused
+    client: hyper::Client<hyper::client::HttpConnector>,
     desired_input: String,
     min_volume: f32,
     max_volume: f32,
+    upstream_tally: Option<std::sync::Arc<router::debug::UpstreamTally>>,
+    headers: Vec<(String, String)>,
+    forwarded_request_headers: Vec<String>,
 }
 
 impl AVReceiver {
@@ -148,6 +283,9 @@ impl AVReceiver {
             min_volume: -80.0,
             max_volume: -20.0,
             scheme: String::from("http"),
+            upstream_tally: None,
+            headers: Vec::new(),
+            forwarded_request_headers: Vec::new(),
         }
     }
 
@@ -167,14 +305,42 @@ impl AVReceiver {
             .build()
             .unwrap();
 
-        let request = hyper::Request::builder()
+        let mut request_builder = hyper::Request::builder()
             .method(hyper::Method::GET)
             .uri(uri)
-            .version(hyper::Version::HTTP_11)
+            .version(hyper::Version::HTTP_11);
+
+        for (name, value) in &self.headers {
+            request_builder = request_builder.header(name, value);
+        }
+        let forwarded_headers = REQUEST_HEADERS
+            .try_with(|request_headers| {
+                self.forwarded_request_headers
+                    .iter()
+                    .flat_map(|name| {
+                        request_headers
+                            .get_all(name)
+                            .iter()
+                            .map(move |value| (name.to_owned(), value.clone()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_else(|_| Vec::new());
+        for (name, value) in forwarded_headers {
+            request_builder = request_builder.header(name, value);
+        }
+
+        let request = request_builder
             .body(hyper::body::Body::empty())
-            .unwrap();
+            .map_err(|err| {
+                AVReceiver::error("Could not build the request for command", &cmd, err)
+            })?;
 
-        let mut response = hyper::Client::new().request(request).await.map_err(|err| {
+        let response = self.client.request(request).await;
+        if let Some(upstream_tally) = &self.upstream_tally {
+            upstream_tally.record(response.is_ok());
+        }
+        let mut response = response.map_err(|err| {
             AVReceiver::error("Error while querying receiver with command", &cmd, err)
         })?;
 
@@ -208,14 +374,7 @@ impl AVReceiver {
     }
 
     async fn set_source(&self) -> bool {
-        // no body in the response when setting source
-        let _ = self
-            .send_command_inner(format!("{}{}", CMD_SOURCE, self.desired_input), false)
-            .await;
-        match self.get_status().await {
-            Ok(res) => res.get_input() == self.desired_input,
-            Err(_) => false,
-        }
+        self.set_input(self.desired_input.to_owned()).await == self.desired_input
     }
 
     fn db_to_percent(&self, volume: &String) -> i16 {
@@ -309,11 +468,11 @@ impl AVReceiverInterface for AVReceiver {
             .unwrap_or(0)
     }
 
-    async fn get_volume(&self) -> (i16, bool) {
+    async fn get_volume(&self) -> (Option<i16>, bool) {
         self.get_status()
             .await
-            .map(|item| (item.get_volume_percent(&self), item.is_muted()))
-            .unwrap_or((0, false))
+            .map(|item| (item.get_volume_level(&self), item.is_muted()))
+            .unwrap_or((Some(0), false))
     }
 
     async fn set_volume(&self, volume: i16) -> i16 {
@@ -324,6 +483,21 @@ impl AVReceiverInterface for AVReceiver {
             .map(|item| item.get_volume_percent(&self))
             .unwrap_or(0)
     }
+
+    async fn get_input(&self) -> String {
+        self.get_status()
+            .await
+            .map(|item| item.get_input())
+            .unwrap_or_default()
+    }
+
+    async fn set_input(&self, input: String) -> String {
+        // no body in the response when setting source
+        let _ = self
+            .send_command_inner(format!("{}{}", CMD_SOURCE, input), false)
+            .await;
+        self.get_input().await
+    }
 }
 
 #[cfg(test)]
@@ -417,7 +591,28 @@ mod tests {
 
         let receiver = get_receiver(&mock_server);
 
-        assert_eq!((0, false), receiver.get_volume().await);
+        assert_eq!((None, false), receiver.get_volume().await);
+    }
+
+    #[test(tokio::test)]
+    async fn it_gives_the_minimum_volume_as_zero() {
+        let mock_server = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("GET"))
+            .and(wiremock::matchers::path(
+                "/goform/formMainZone_MainZoneXmlStatus.xml",
+            ))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200)
+                    .set_body_bytes(get_volume_response(String::from("-80.0"), false)),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let receiver = get_receiver(&mock_server);
+
+        assert_eq!((Some(0), false), receiver.get_volume().await);
     }
 
     #[test(tokio::test)]
@@ -439,7 +634,39 @@ mod tests {
         let receiver = get_receiver(&mock_server);
 
         assert!(receiver.is_powered_on().await);
-        assert_eq!((67, true), receiver.get_volume().await);
+        assert_eq!((Some(67), true), receiver.get_volume().await);
+    }
+
+    #[test(tokio::test)]
+    async fn it_ignores_the_unknown_elements() {
+        let mock_server = wiremock::MockServer::start().await;
+
+        let body = r#"<?xml version="1.0" encoding="utf-8" ?>
+<item>
+<FriendlyName><value>Living room</value></FriendlyName>
+<Power><value>ON</value></Power>
+<Zone><value>MainZone</value><value>Zone2</value></Zone>
+<RenameSource><value><value>CD</value></value><value><value>NET</value></value></RenameSource>
+<SourceList><Source index="1"><FuncName>CD</FuncName><Available>ON</Available></Source></SourceList>
+<InputFuncSelect><value>NET</value></InputFuncSelect>
+<MasterVolume><value>-40.0</value><unit>dB</unit></MasterVolume>
+<Mute><value>off</value></Mute>
+<Power><value>ON</value></Power>
+</item>"#;
+
+        wiremock::Mock::given(wiremock::matchers::method("GET"))
+            .and(wiremock::matchers::path(
+                "/goform/formMainZone_MainZoneXmlStatus.xml",
+            ))
+            .respond_with(wiremock::ResponseTemplate::new(200).set_body_bytes(body))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let receiver = get_receiver(&mock_server);
+
+        assert!(receiver.is_powered_on().await);
+        assert_eq!((Some(67), false), receiver.get_volume().await);
     }
 
     #[test_log::test(tokio::test)]
@@ -716,4 +943,40 @@ mod tests {
 
         assert_eq!(25, receiver.increment_volume(false).await);
     }
+
+    #[test(tokio::test)]
+    async fn it_sends_the_configured_headers() {
+        let mock_server = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("GET"))
+            .and(wiremock::matchers::header(
+                "authorization",
+                "Basic a2VuOmtlbg==",
+            ))
+            .and(wiremock::matchers::header("cookie", "session=42"))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200).set_body_bytes(get_power_response(true)),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let receiver = super::AVReceiver::builder()
+            .with_url(mock_server.uri())
+            .with_headers(vec![(
+                String::from("Authorization"),
+                String::from("Basic a2VuOmtlbg=="),
+            )])
+            .with_forwarded_request_headers(vec![String::from("cookie")])
+            .build();
+
+        let mut request_headers = hyper::HeaderMap::new();
+        request_headers.insert(
+            "cookie",
+            hyper::header::HeaderValue::from_static("session=42"),
+        );
+        request_headers.insert("x-other", hyper::header::HeaderValue::from_static("other"));
+
+        assert!(super::with_request_headers(request_headers, receiver.is_powered_on()).await);
+    }
 }
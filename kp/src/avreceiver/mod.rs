@@ -1,3 +1,4 @@
+pub use self::avreceiver::with_request_headers;
 pub use self::avreceiver::AVReceiverInterface;
 
 mod avreceiver;
@@ -7,11 +8,15 @@ pub use self::avreceiver::MockAVReceiver;
 
 pub fn get_avreceiver(
     configuration: &crate::configuration::AVReceiverConfiguration,
+    upstream_tally: std::sync::Arc<router::debug::UpstreamTally>,
 ) -> std::sync::Arc<dyn AVReceiverInterface> {
     std::sync::Arc::new(
         avreceiver::AVReceiver::builder()
             .with_url(configuration.target.to_owned())
             .with_desired_input(configuration.desired_input.to_owned())
+            .with_upstream_tally(upstream_tally)
+            .with_headers(configuration.forward_headers.to_owned())
+            .with_forwarded_request_headers(configuration.forward_request_headers.to_owned())
             .build(),
     )
 }
@@ -9,6 +9,13 @@ pub trait CECInterface: Sync + Send {
     /// Power on the given CEC devices. If [CECLogicalAddress::Broadcast] is given, then [LibcecConfiguration::wake_devices] is used
     fn power_on(&mut self, cec_logical_address: CECLogicalAddress) -> Result<(), CECError>;
 
+    /// Queries the power status of the given CEC device, [CECPowerStatus::Unknown] if it does not
+    /// answer
+    fn get_power_status(
+        &mut self,
+        cec_logical_address: CECLogicalAddress,
+    ) -> Result<CECPowerStatus, CECError>;
+
     /// Put in standby mode the given CEC devices. If [CECLogicalAddress::Broadcast] is given, then [LibcecConfiguration::power_off_devices] is used
     fn standby(&mut self, cec_logical_address: CECLogicalAddress) -> Result<(), CECError>;
 }
@@ -17,9 +24,13 @@ type LibcecConnectionT = *mut libc::c_void;
 
 pub struct LibcecConfigurationBuilder {
     client_version: Result<u32, CECError>,
+    osd_name: Option<String>,
     callbacks: &'static mut ICECCallbacks,
 }
 
+/// Maximum length in bytes of the OSD name, the last byte of the field being the terminator
+const OSD_NAME_MAX_LENGTH: usize = 12;
+
 pub struct CECConnection {
     connection: LibcecConnectionT,
     configuration: LibcecConfiguration,
@@ -110,6 +121,7 @@ impl LibcecConfigurationBuilder {
                 client_version: Err(CECError::InvalidConfiguration(
                     "No version given for CEC client version",
                 )),
+                osd_name: None,
                 callbacks: &mut ICECCALLBACKS_DEFAULT,
             }
         }
@@ -124,12 +136,47 @@ impl LibcecConfigurationBuilder {
         self
     }
 
+    /// Name shown by the other devices, e.g. in the input list of the TV
+    ///
+    /// It is truncated to 12 bytes if longer
+    pub fn with_osd_name<T>(mut self, osd_name: T) -> Self
+    where
+        T: std::convert::Into<String>,
+    {
+        let mut osd_name: String = osd_name.into();
+        if osd_name.len() > OSD_NAME_MAX_LENGTH {
+            let mut length = OSD_NAME_MAX_LENGTH;
+            while !osd_name.is_char_boundary(length) {
+                length -= 1;
+            }
+            log::warn!(
+                "CEC OSD name '{}' is longer than {} bytes, truncating it to '{}'",
+                osd_name,
+                OSD_NAME_MAX_LENGTH,
+                &osd_name[..length]
+            );
+            osd_name.truncate(length);
+        }
+        self.osd_name = Some(osd_name);
+        self
+    }
+
     pub fn build(self) -> Result<LibcecConfiguration, CECError> {
         unsafe {
             let mut configuration = std::mem::zeroed::<LibcecConfiguration>();
             libcec_clear_configuration(&mut configuration);
             configuration.client_version = self.client_version?;
             configuration.device_types.types[0] = CECDeviceType::RecordingDevice;
+            if let Some(osd_name) = &self.osd_name {
+                configuration.str_device_name = [0; 13];
+                for (target, byte) in configuration
+                    .str_device_name
+                    .iter_mut()
+                    .zip(osd_name.bytes())
+                {
+                    *target = byte as libc::c_char;
+                }
+            }
             configuration.callbacks = self.callbacks;
             Ok(configuration)
         }
@@ -151,15 +198,13 @@ impl LibcecConfigurationBuilder {
 }
 
 impl CECConnection {
-    pub fn new(configuration: LibcecConfiguration) -> CECConnection {
+    pub fn new(configuration: LibcecConfiguration) -> Result<CECConnection, CECError> {
         let mut connection = CECConnection {
             connection: std::ptr::null_mut(),
             configuration,
         };
-        if let Err(e) = connection.reinit() {
-            panic!("Failed to initialize the CEC connection: {:?}", e);
-        }
-        connection
+        connection.reinit()?;
+        Ok(connection)
     }
 
     fn reinit(&mut self) -> Result<(), CECError> {
@@ -243,6 +288,15 @@ impl CECInterface for CECConnection {
     fn standby(&mut self, cec_logical_address: CECLogicalAddress) -> Result<(), CECError> {
         self.exec(|s| unsafe { libcec_standby_devices(s.connection, cec_logical_address) })
     }
+
+    fn get_power_status(
+        &mut self,
+        cec_logical_address: CECLogicalAddress,
+    ) -> Result<CECPowerStatus, CECError> {
+        let status =
+            unsafe { libcec_get_device_power_status(self.connection, cec_logical_address) };
+        Ok(CECPowerStatus::from(status))
+    }
 }
 
 impl Drop for CECConnection {
@@ -250,3 +304,36 @@ impl Drop for CECConnection {
         self.drop_connection();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    fn get_osd_name(configuration: &super::LibcecConfiguration) -> Vec<u8> {
+        configuration
+            .str_device_name
+            .iter()
+            .map(|&byte| byte as u8)
+            .collect()
+    }
+
+    #[test]
+    fn it_sets_the_osd_name() {
+        let configuration = super::LibcecConfigurationBuilder::new()
+            .with_client_version("4.0.4")
+            .with_osd_name("Kodi")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            b"Kodi\0\0\0\0\0\0\0\0\0".to_vec(),
+            get_osd_name(&configuration)
+        );
+
+        let configuration = super::LibcecConfigurationBuilder::new()
+            .with_client_version("4.0.4")
+            .with_osd_name("Living room proxy")
+            .build()
+            .unwrap();
+
+        assert_eq!(b"Living room \0".to_vec(), get_osd_name(&configuration));
+    }
+}
@@ -2,6 +2,9 @@ pub struct CECFakeInterface {
     pub target: String,
 }
 
+/// Implementation doing nothing, used when the CEC connection could not be initialized
+pub struct CECUnavailableInterface {}
+
 /// Fake implementation for integration testing
 impl super::CECInterface for CECFakeInterface {
     fn power_on(
@@ -15,6 +18,17 @@ impl super::CECInterface for CECFakeInterface {
         Ok(())
     }
 
+    fn get_power_status(
+        &mut self,
+        cec_logical_address: super::CECLogicalAddress,
+    ) -> Result<super::CECPowerStatus, super::enums::CECError> {
+        log::info!(
+            "Received power status request for device {:?}",
+            cec_logical_address
+        );
+        Ok(super::CECPowerStatus::On)
+    }
+
     fn standby(
         &mut self,
         cec_logical_address: super::CECLogicalAddress,
@@ -32,3 +46,38 @@ impl super::CECInterface for CECFakeInterface {
         Ok(())
     }
 }
+
+impl super::CECInterface for CECUnavailableInterface {
+    fn power_on(
+        &mut self,
+        cec_logical_address: super::CECLogicalAddress,
+    ) -> Result<(), super::enums::CECError> {
+        log::warn!(
+            "CEC unavailable, ignoring power on request for device {:?}",
+            cec_logical_address
+        );
+        Ok(())
+    }
+
+    fn get_power_status(
+        &mut self,
+        cec_logical_address: super::CECLogicalAddress,
+    ) -> Result<super::CECPowerStatus, super::enums::CECError> {
+        log::warn!(
+            "CEC unavailable, ignoring power status request for device {:?}",
+            cec_logical_address
+        );
+        Ok(super::CECPowerStatus::Unknown)
+    }
+
+    fn standby(
+        &mut self,
+        cec_logical_address: super::CECLogicalAddress,
+    ) -> Result<(), super::enums::CECError> {
+        log::warn!(
+            "CEC unavailable, ignoring stand by request for device {:?}",
+            cec_logical_address
+        );
+        Ok(())
+    }
+}
@@ -7,6 +7,30 @@ pub enum CECError {
     OpenFailed,
 }
 
+impl std::convert::From<CECError> for router::RouterError {
+    fn from(error: CECError) -> Self {
+        let (status, kind, message) = match error {
+            CECError::AdapterNotFound => (503, "AdapterNotFound", "No CEC adapter found"),
+            CECError::CommandFailed => (502, "CommandFailed", "The CEC command failed"),
+            CECError::InitFailed => (503, "InitFailed", "Failed to initialize libcec"),
+            CECError::InvalidConfiguration(msg) => (500, "InvalidConfiguration", msg),
+            CECError::OpenFailed => (
+                503,
+                "OpenFailed",
+                "Failed to open the connection to the CEC adapter",
+            ),
+        };
+        router::JsonError(
+            status,
+            serde_json::json!({
+                "error": message,
+                "kind": kind
+            })
+            .to_string(),
+        )
+    }
+}
+
 // Contains the C bindings from https://github.com/Pulse-Eight/libcec
 #[repr(C)]
 #[allow(dead_code)]
@@ -43,7 +67,7 @@ pub enum CECMenuState {
 
 #[repr(C)]
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CECPowerStatus {
     On = 0x00,
     Standby = 0x01,
@@ -52,6 +76,18 @@ pub enum CECPowerStatus {
     Unknown = 0x99,
 }
 
+impl std::convert::From<libc::c_int> for CECPowerStatus {
+    fn from(status: libc::c_int) -> Self {
+        match status {
+            0x00 => CECPowerStatus::On,
+            0x01 => CECPowerStatus::Standby,
+            0x02 => CECPowerStatus::InTransitionStandbyToOn,
+            0x03 => CECPowerStatus::InTransitionOnToStandby,
+            _ => CECPowerStatus::Unknown,
+        }
+    }
+}
+
 #[repr(C)]
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -29,6 +29,11 @@ extern "C" {
         connection: LibcecConnectionT,
         cec_logical_address: CECLogicalAddress,
     ) -> libc::c_int;
+    /// Returns a [CECPowerStatus] as an integer, since libcec may answer values outside the enum
+    pub fn libcec_get_device_power_status(
+        connection: LibcecConnectionT,
+        cec_logical_address: CECLogicalAddress,
+    ) -> libc::c_int;
     /// Broadcast a message that notifies connected CEC capable devices that this device is no longer the active source.
     pub fn libcec_set_inactive_view(connection: LibcecConnectionT) -> libc::c_int;
     pub fn libcec_clear_configuration(configuration: *mut LibcecConfiguration);
@@ -1,5 +1,6 @@
 pub use cec::CECInterface;
 pub use enums::CECLogicalAddress;
+pub use enums::CECPowerStatus;
 
 mod cec;
 mod cec_fake;
@@ -9,6 +10,8 @@ mod structs;
 
 #[cfg(test)]
 pub use self::cec::MockCECInterface;
+#[cfg(test)]
+pub use enums::CECError;
 
 pub fn get_cec_connection(
     configuration: &crate::configuration::CECConfiguration,
@@ -19,12 +22,50 @@ pub fn get_cec_connection(
             target: target.to_owned(),
         }))
     } else {
-        let configuration = cec::LibcecConfigurationBuilder::new()
-            .with_client_version(&configuration.cec_version)
-            .build()
-            .expect("Invalid CEC configuration");
-        std::sync::Arc::new(std::sync::Mutex::new(cec::CECConnection::new(
-            configuration,
-        )))
+        connect_or_fallback(|| {
+            let mut builder = cec::LibcecConfigurationBuilder::new()
+                .with_client_version(&configuration.cec_version);
+            if let Some(osd_name) = &configuration.osd_name {
+                builder = builder.with_osd_name(osd_name);
+            }
+            let configuration = builder.build().expect("Invalid CEC configuration");
+            cec::CECConnection::new(configuration)
+        })
+    }
+}
+
+/// Falls back to an interface answering every command with a no-op when the connection fails
+fn connect_or_fallback<C, F>(connect: F) -> std::sync::Arc<std::sync::Mutex<dyn cec::CECInterface>>
+where
+    C: cec::CECInterface + 'static,
+    F: FnOnce() -> Result<C, enums::CECError>,
+{
+    match connect() {
+        Ok(connection) => std::sync::Arc::new(std::sync::Mutex::new(connection)),
+        Err(error) => {
+            log::warn!(
+                "Failed to initialize the CEC connection, CEC is unavailable: {:?}",
+                error
+            );
+            std::sync::Arc::new(std::sync::Mutex::new(cec_fake::CECUnavailableInterface {}))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn it_falls_back_when_the_cec_initialization_fails() {
+        let cec_interface = super::connect_or_fallback(|| {
+            Err::<super::MockCECInterface, _>(super::CECError::InitFailed)
+        });
+        let mut cec_interface = cec_interface.lock().unwrap();
+
+        assert!(cec_interface.power_on(super::CECLogicalAddress::TV).is_ok());
+        assert!(cec_interface.standby(super::CECLogicalAddress::TV).is_ok());
+        assert!(matches!(
+            cec_interface.get_power_status(super::CECLogicalAddress::TV),
+            Ok(super::CECPowerStatus::Unknown)
+        ));
     }
 }
@@ -1,3 +1,5 @@
+pub use files::configuration::FileConfiguration;
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct CECConfiguration {
     #[serde(rename = "cecVersion", default = "cec_default_version")]
@@ -5,18 +7,79 @@ pub struct CECConfiguration {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "fakeTarget", default)]
     pub fake_target: Option<String>,
-}
-
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct FileConfiguration {
-    #[serde(rename = "rootPath", default = "file_default_root_path")]
-    pub root_path: std::path::PathBuf,
+    /// Name shown by the TV for the proxy, at most 12 bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "osdName", default)]
+    pub osd_name: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct JRPCConfiguration {
+    /// Methods sent in the Allow header of the answers to OPTIONS /jsonrpc, never forwarded
+    #[serde(rename = "allowedMethods", default = "jrpc_default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(rename = "circuitBreaker", default)]
+    pub circuit_breaker: CircuitBreakerConfiguration,
+    /// Overloaded methods that are forwarded to kodi as is, e.g. `System.Shutdown` when headless
+    #[serde(
+        rename = "disabledOverloaders",
+        skip_serializing_if = "Vec::is_empty",
+        default
+    )]
+    pub disabled_overloaders: Vec<String>,
+    /// Answers GET /jsonrpc with the list of the overloaded methods instead of forwarding it
+    #[serde(rename = "describeOnGet", default)]
+    pub describe_on_get: bool,
+    /// Makes /health ping kodi and report whether it answered within this delay
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "healthPingTimeoutMs", default)]
+    pub health_ping_timeout_ms: Option<u64>,
+    /// Requests declaring a bigger `Content-Length` are refused with a 413 before being read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "maxRequestBytes", default)]
+    pub max_request_bytes: Option<u64>,
+    /// Answers JSONRPC.Ping with `{"pong": true, "proxyVersion": ...}` instead of forwarding it
+    #[serde(rename = "pingWithVersion", default)]
+    pub ping_with_version: bool,
+    /// Methods renamed before being handled, e.g. to map non standard names to kodi's methods
+    #[serde(
+        rename = "methodAliases",
+        skip_serializing_if = "std::collections::HashMap::is_empty",
+        default
+    )]
+    pub method_aliases: std::collections::HashMap<String, String>,
+    /// Header and value the requests must contain, e.g. `["X-Proxy-Secret", "..."]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "requireHeader", default)]
+    pub require_header: Option<(String, String)>,
+    /// Refuses the requests that do not contain `"jsonrpc": "2.0"` with a -32600 error
+    #[serde(rename = "requireVersion", default)]
+    pub require_version: bool,
     #[serde(default = "jrpc_default_target")]
     pub target: String,
+    /// Fails the forwarded queries with a 502 when kodi does not answer within this delay
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "upstreamTimeoutMs", default)]
+    pub upstream_timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct CircuitBreakerConfiguration {
+    /// Number of consecutive forwarding failures opening the breaker
+    #[serde(
+        rename = "failureThreshold",
+        default = "breaker_default_failure_threshold"
+    )]
+    pub failure_threshold: u32,
+    /// Delay within which the failures must happen to be counted as consecutive
+    #[serde(rename = "windowSeconds", default = "breaker_default_window_seconds")]
+    pub window_seconds: u64,
+    /// Delay during which the forwards are rejected once the breaker is open
+    #[serde(
+        rename = "cooldownSeconds",
+        default = "breaker_default_cooldown_seconds"
+    )]
+    pub cooldown_seconds: u64,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -37,12 +100,119 @@ pub struct AVReceiverConfiguration {
     pub desired_input: String,
     #[serde(default = "av_default_target")]
     pub target: String,
+    /// Headers sent with every command, e.g. `[["Authorization", "Basic ..."]]`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(rename = "forwardHeaders", default)]
+    pub forward_headers: Vec<(String, String)>,
+    /// Names of the headers of the incoming requests sent along with the commands they trigger
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(rename = "forwardRequestHeaders", default)]
+    pub forward_request_headers: Vec<String>,
+    /// Makes /avreceiver/volume send a null volume, instead of 0, when the receiver reports `--`
+    #[serde(rename = "distinctOffVolume", default)]
+    pub distinct_off_volume: bool,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct ServerConfiguration {
     #[serde(default = "server_default_host")]
     pub host: String,
+    /// Connections are closed after this duration, regardless of their activity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "maxConnectionLifetimeSecs", default)]
+    pub max_connection_lifetime_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub redirects: Vec<RedirectRule>,
+    /// Value of the Server header of all the responses, the header is removed if not set or empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "serverHeader", default)]
+    pub server_header: Option<String>,
+    /// Routes the paths with a trailing slash as if it was not there
+    #[serde(rename = "canonicalizePaths", default)]
+    pub canonicalize_paths: bool,
+    /// Only logs the requests taking longer than this, instead of logging all the requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "slowRequestThresholdMs", default)]
+    pub slow_request_threshold_ms: Option<u64>,
+    /// Adds an X-Handler header with the name of the handler that served the request
+    #[serde(rename = "debugRouting", default)]
+    pub debug_routing: bool,
+    /// Sends a Server-Timing trailer with the handling time, only over HTTP/2
+    #[serde(rename = "sendTimingTrailer", default)]
+    pub send_timing_trailer: bool,
+    /// Makes /exit answer with an empty 204, as it used to, instead of a json document
+    #[serde(rename = "exitEmptyResponse", default)]
+    pub exit_empty_response: bool,
+    /// Delay between the response of /exit and the shutdown, so that the response is flushed
+    #[serde(rename = "exitDelayMs", default = "server_default_exit_delay_ms")]
+    pub exit_delay_ms: u64,
+    /// Below this many free bytes on the filesystem of the file repository, /health reports a
+    /// degraded status and a warning is logged
+    #[serde(
+        rename = "healthMinFreeBytes",
+        default = "server_default_health_min_free_bytes"
+    )]
+    pub health_min_free_bytes: u64,
+    /// Time after which the handlers that do not give their own timeout are abandoned with a 504
+    #[serde(
+        rename = "handlerTimeoutMs",
+        default = "server_default_handler_timeout_ms"
+    )]
+    pub handler_timeout_ms: u64,
+    /// Lets the pages served from other origins, e.g. a web frontend, call the proxy
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub cors: Option<CorsConfiguration>,
+    /// Sends a Retry-After header with this delay in the 502, 503 and 504 errors of the proxy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "retryAfterSecs", default)]
+    pub retry_after_secs: Option<u64>,
+    /// Longer request URIs, path and query, are refused with a 414
+    #[serde(rename = "maxUriLength", default = "server_default_max_uri_length")]
+    pub max_uri_length: usize,
+    /// Serves HTTPS with the given certificate instead of plain HTTP
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tls: Option<TlsConfiguration>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct CorsConfiguration {
+    /// Origins allowed to call the proxy, e.g. `https://ui.example.com`, `*` allowing any origin
+    #[serde(rename = "allowedOrigins")]
+    pub allowed_origins: Vec<String>,
+    #[serde(rename = "allowedMethods", default = "cors_default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(rename = "allowedHeaders", default)]
+    pub allowed_headers: Vec<String>,
+    /// Time during which the browsers can reuse the answer of a preflight request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "maxAgeSecs", default)]
+    pub max_age_secs: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct TlsConfiguration {
+    /// PEM file containing the certificate chain, the certificate of the server first
+    #[serde(rename = "certPath")]
+    pub cert_path: std::path::PathBuf,
+    /// PEM file containing the private key of the certificate
+    #[serde(rename = "keyPath")]
+    pub key_path: std::path::PathBuf,
+    /// Oldest version of TLS accepted, `1.2` or `1.3`
+    #[serde(rename = "minVersion", default = "tls_default_min_version")]
+    #[serde(deserialize_with = "deserialize_tls_version")]
+    #[serde(serialize_with = "serialize_tls_version")]
+    pub min_version: router::TlsVersion,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct RedirectRule {
+    /// Exact path of the requests to redirect
+    pub source: String,
+    /// Value of the location header sent back
+    pub target: String,
+    /// One of 301, 302, 307 or 308
+    #[serde(default = "redirect_default_status")]
+    pub status: u16,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -61,27 +231,125 @@ pub struct ProxyConfiguration {
     pub server: ServerConfiguration,
 }
 
+/// Settings holding secrets, as paths of the serialized configuration, sent as `***` by [Redacted]
+const SECRET_FIELDS: &[&str] = &[
+    "file.adminKey",
+    "file.apiKeys",
+    "jrpc.requireHeader",
+    "receiver.forwardHeaders",
+];
+
+/// Serializes the configuration with its secret settings replaced by `***`
+pub struct Redacted<'a>(pub &'a ProxyConfiguration);
+
+impl serde::Serialize for Redacted<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut value = serde_json::to_value(self.0).map_err(serde::ser::Error::custom)?;
+        for path in SECRET_FIELDS {
+            if let Some(secret) = value.pointer_mut(&format!("/{}", path.replace('.', "/"))) {
+                *secret = serde_json::Value::from("***");
+            }
+        }
+        serde::Serialize::serialize(&value, serializer)
+    }
+}
+
+impl ProxyConfiguration {
+    /// Describes the settings changed between this configuration and `other`, one line per
+    /// setting as `path.of.the.field: old -> new`, the values of the secret settings being
+    /// replaced by `***`
+    pub fn diff(&self, other: &ProxyConfiguration) -> Vec<String> {
+        let mut changes = Vec::new();
+        diff_values(
+            "",
+            &serde_json::to_value(self).unwrap(),
+            &serde_json::to_value(other).unwrap(),
+            &mut changes,
+        );
+        changes
+    }
+}
+
+/// Recursively compares the fields of two json objects, the missing fields being null
+fn diff_values(
+    path: &str,
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+    changes: &mut Vec<String>,
+) {
+    if SECRET_FIELDS.contains(&path) {
+        if old != new {
+            changes.push(format!("{}: {} -> {}", path, mask(old), mask(new)));
+        }
+        return;
+    }
+    match (old, new) {
+        (serde_json::Value::Object(old_fields), serde_json::Value::Object(new_fields)) => {
+            let keys: std::collections::BTreeSet<&String> =
+                old_fields.keys().chain(new_fields.keys()).collect();
+            for key in keys {
+                let field_path = if path.is_empty() {
+                    key.to_owned()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_values(
+                    &field_path,
+                    old_fields.get(key).unwrap_or(&serde_json::Value::Null),
+                    new_fields.get(key).unwrap_or(&serde_json::Value::Null),
+                    changes,
+                );
+            }
+        }
+        _ if old != new => changes.push(format!("{}: {} -> {}", path, old, new)),
+        _ => (),
+    }
+}
+
+/// Replaces a secret value by `***`, unless it is not set
+fn mask(value: &serde_json::Value) -> serde_json::Value {
+    if value.is_null() {
+        serde_json::Value::Null
+    } else {
+        serde_json::Value::from("***")
+    }
+}
+
 impl std::default::Default for CECConfiguration {
     fn default() -> Self {
         CECConfiguration {
             cec_version: cec_default_version(),
             fake_target: None,
+            osd_name: None,
         }
     }
 }
 
-impl std::default::Default for FileConfiguration {
+impl std::default::Default for JRPCConfiguration {
     fn default() -> Self {
-        FileConfiguration {
-            root_path: file_default_root_path(),
+        JRPCConfiguration {
+            allowed_methods: jrpc_default_allowed_methods(),
+            circuit_breaker: CircuitBreakerConfiguration::default(),
+            disabled_overloaders: Vec::new(),
+            describe_on_get: false,
+            health_ping_timeout_ms: None,
+            max_request_bytes: None,
+            method_aliases: std::collections::HashMap::new(),
+            ping_with_version: false,
+            require_header: None,
+            require_version: false,
+            target: jrpc_default_target(),
+            upstream_timeout_ms: None,
         }
     }
 }
 
-impl std::default::Default for JRPCConfiguration {
+impl std::default::Default for CircuitBreakerConfiguration {
     fn default() -> Self {
-        JRPCConfiguration {
-            target: jrpc_default_target(),
+        CircuitBreakerConfiguration {
+            failure_threshold: breaker_default_failure_threshold(),
+            window_seconds: breaker_default_window_seconds(),
+            cooldown_seconds: breaker_default_cooldown_seconds(),
         }
     }
 }
@@ -101,6 +369,9 @@ impl std::default::Default for AVReceiverConfiguration {
         AVReceiverConfiguration {
             desired_input: av_default_input(),
             target: av_default_target(),
+            forward_headers: Vec::new(),
+            forward_request_headers: Vec::new(),
+            distinct_off_volume: false,
         }
     }
 }
@@ -109,21 +380,69 @@ impl std::default::Default for ServerConfiguration {
     fn default() -> Self {
         ServerConfiguration {
             host: server_default_host(),
+            max_connection_lifetime_secs: None,
+            redirects: Vec::new(),
+            server_header: None,
+            canonicalize_paths: false,
+            slow_request_threshold_ms: None,
+            debug_routing: false,
+            send_timing_trailer: false,
+            exit_empty_response: false,
+            exit_delay_ms: server_default_exit_delay_ms(),
+            health_min_free_bytes: server_default_health_min_free_bytes(),
+            handler_timeout_ms: server_default_handler_timeout_ms(),
+            cors: None,
+            retry_after_secs: None,
+            max_uri_length: server_default_max_uri_length(),
+            tls: None,
         }
     }
 }
+
+/// Reads and parses the configuration file
+pub fn read_configuration(path: &str) -> Result<ProxyConfiguration, String> {
+    let configuration =
+        std::fs::read(path).map_err(|e| format!("Configuration file not found: {}", e))?;
+    let configuration = String::from_utf8(configuration)
+        .map_err(|e| format!("Could not decode the configuration file: {}", e))?;
+    serde_json::from_str(configuration.as_str())
+        .map_err(|e| format!("Invalid configuration file: {}", e))
+}
+
+fn cors_default_allowed_methods() -> Vec<String> {
+    ["GET", "HEAD", "PUT", "POST", "DELETE", "MOVE"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
 fn cec_default_version() -> String {
     String::from("4.0.4")
 }
 
-fn file_default_root_path() -> std::path::PathBuf {
-    std::path::PathBuf::from("test/path")
+fn jrpc_default_allowed_methods() -> Vec<String> {
+    ["POST", "GET", "OPTIONS"]
+        .into_iter()
+        .map(String::from)
+        .collect()
 }
 
 fn jrpc_default_target() -> String {
     String::from("http://localhost:8081/jsonrpc")
 }
 
+fn breaker_default_failure_threshold() -> u32 {
+    5
+}
+
+fn breaker_default_window_seconds() -> u64 {
+    30
+}
+
+fn breaker_default_cooldown_seconds() -> u64 {
+    10
+}
+
 fn logging_default_enabled() -> bool {
     true
 }
@@ -144,6 +463,30 @@ fn server_default_host() -> String {
     String::from("127.0.0.1:8079")
 }
 
+fn server_default_exit_delay_ms() -> u64 {
+    100
+}
+
+fn server_default_health_min_free_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn server_default_handler_timeout_ms() -> u64 {
+    10_000
+}
+
+fn server_default_max_uri_length() -> usize {
+    8192
+}
+
+fn tls_default_min_version() -> router::TlsVersion {
+    router::TlsVersion::Tls12
+}
+
+fn redirect_default_status() -> u16 {
+    302
+}
+
 fn deserialize_level<'de, D>(deserializer: D) -> Result<log::LevelFilter, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -163,6 +506,21 @@ where
     }
 }
 
+fn deserialize_tls_version<'de, D>(deserializer: D) -> Result<router::TlsVersion, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: String = serde::Deserialize::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+fn serialize_tls_version<S>(version: &router::TlsVersion, s: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    s.serialize_str(&version.to_string())
+}
+
 pub fn serialize_level<S>(level: &log::LevelFilter, s: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
@@ -198,4 +556,95 @@ mod test {
             assert_eq!(json, ser_json);
         }
     }
+
+    #[test]
+    fn it_decodes_the_tls_min_version() {
+        let tls = r#"{"certPath":"cert.pem","keyPath":"key.pem"}"#;
+        let configuration: TlsConfiguration = serde_json::from_str(tls).unwrap();
+
+        assert_eq!(router::TlsVersion::Tls12, configuration.min_version);
+
+        let tls = r#"{"certPath":"cert.pem","keyPath":"key.pem","minVersion":"1.3"}"#;
+        let configuration: TlsConfiguration = serde_json::from_str(tls).unwrap();
+
+        assert_eq!(router::TlsVersion::Tls13, configuration.min_version);
+        assert_eq!(tls, serde_json::to_string(&configuration).unwrap());
+
+        let tls = r#"{"certPath":"cert.pem","keyPath":"key.pem","minVersion":"1.1"}"#;
+
+        assert!(serde_json::from_str::<TlsConfiguration>(tls).is_err());
+    }
+
+    #[test]
+    fn it_describes_the_changed_fields() {
+        let current: ProxyConfiguration = serde_json::from_str(
+            r#"{"server":{"host":"127.0.0.1:8079"},"logging":{"level":"WARN"}}"#,
+        )
+        .unwrap();
+        let new: ProxyConfiguration = serde_json::from_str(
+            r#"{"server":{"host":"127.0.0.1:8080","serverHeader":"kp"},"logging":{"level":"WARN"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                r#"server.host: "127.0.0.1:8079" -> "127.0.0.1:8080""#,
+                r#"server.serverHeader: null -> "kp""#,
+            ],
+            current.diff(&new)
+        );
+        assert!(current.diff(&current).is_empty());
+    }
+
+    #[test]
+    fn it_redacts_the_secrets() {
+        let configuration: ProxyConfiguration = serde_json::from_str(
+            r#"{"jrpc":{"requireHeader":["X-Proxy-Secret","s3cr3t"]},"server":{"serverHeader":"kp"}}"#,
+        )
+        .unwrap();
+
+        let redacted = serde_json::to_value(Redacted(&configuration)).unwrap();
+
+        assert_eq!(serde_json::json!("***"), redacted["jrpc"]["requireHeader"]);
+        assert_eq!(serde_json::json!("kp"), redacted["server"]["serverHeader"]);
+        assert!(redacted["file"].get("apiKeys").is_none());
+        assert!(!redacted.to_string().contains("s3cr3t"));
+    }
+
+    #[test]
+    fn it_redacts_the_secrets_in_the_changes() {
+        let current: ProxyConfiguration =
+            serde_json::from_str(r#"{"jrpc":{"requireHeader":["X-Proxy-Secret","old-s3cr3t"]}}"#)
+                .unwrap();
+        let new: ProxyConfiguration = serde_json::from_str(
+            r#"{"jrpc":{"requireHeader":["X-Proxy-Secret","new-s3cr3t"]},"file":{"apiKeys":{"k3y":"tenant"}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                r#"file.apiKeys: null -> "***""#,
+                r#"jrpc.requireHeader: "***" -> "***""#,
+            ],
+            current.diff(&new)
+        );
+    }
+
+    #[test]
+    fn it_describes_nested_and_list_changes() {
+        let current: ProxyConfiguration =
+            serde_json::from_str(r#"{"jrpc":{"circuitBreaker":{"failureThreshold":5}}}"#).unwrap();
+        let new: ProxyConfiguration = serde_json::from_str(
+            r#"{"jrpc":{"circuitBreaker":{"failureThreshold":3},"disabledOverloaders":["System.Shutdown"]}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "jrpc.circuitBreaker.failureThreshold: 5 -> 3",
+                r#"jrpc.disabledOverloaders: null -> ["System.Shutdown"]"#,
+            ],
+            current.diff(&new)
+        );
+    }
 }
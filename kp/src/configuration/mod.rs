@@ -1,3 +1,5 @@
 pub mod configuration;
+mod reload;
 
 pub use self::configuration::*;
+pub use self::reload::*;
@@ -0,0 +1,286 @@
+/// Settings that can be applied without restarting the server
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReloadableSettings {
+    pub log_level: log::LevelFilter,
+}
+
+/// Settings applied by a reload, as paths of the serialized configuration
+///
+/// The log level is applied by [ConfigurationReloader] itself, the other ones through the
+/// [router::LiveSettings] given to [ConfigurationReloader::set_router_settings]
+const RELOADABLE_FIELDS: &[&str] = &["logging", "server.cors", "server.handlerTimeoutMs"];
+
+/// Re-reads the configuration file and applies the settings that can be changed on the fly
+///
+/// The other settings keep the value they had at startup, and a warning is logged if they changed
+/// since they require a restart. Among the timeouts, only the default one of the handlers is
+/// reloaded, e.g. the upstream timeout of jrpc requires a restart.
+pub struct ConfigurationReloader {
+    path: String,
+    /// Configuration the server is running with, i.e. the one it started with plus the settings
+    /// applied by the reloads
+    configuration: std::sync::RwLock<super::ProxyConfiguration>,
+    settings: std::sync::Arc<std::sync::RwLock<ReloadableSettings>>,
+    router_settings: std::sync::OnceLock<std::sync::Arc<std::sync::RwLock<router::LiveSettings>>>,
+}
+
+impl ReloadableSettings {
+    pub fn from_configuration(configuration: &super::ProxyConfiguration) -> ReloadableSettings {
+        ReloadableSettings {
+            log_level: if configuration.logging.enabled {
+                configuration.logging.level
+            } else {
+                log::LevelFilter::Off
+            },
+        }
+    }
+}
+
+/// Settings of the router coming from the server configuration
+pub fn get_router_settings(
+    configuration: &super::ServerConfiguration,
+) -> Result<router::LiveSettings, String> {
+    let cors = configuration
+        .cors
+        .as_ref()
+        .map(|cors| {
+            let allowed_methods = cors
+                .allowed_methods
+                .iter()
+                .map(|method| {
+                    hyper::Method::from_bytes(method.as_bytes())
+                        .map_err(|_| format!("Incorrect method in CORS configuration: {}", method))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok::<_, String>(std::sync::Arc::new(router::CorsConfig {
+                allowed_origins: cors.allowed_origins.clone(),
+                allowed_methods,
+                allowed_headers: cors.allowed_headers.clone(),
+                max_age: cors.max_age_secs.map(std::time::Duration::from_secs),
+            }))
+        })
+        .transpose()?;
+    Ok(router::LiveSettings {
+        default_timeout: std::time::Duration::from_millis(configuration.handler_timeout_ms),
+        cors,
+    })
+}
+
+/// Copy of `running` with the reloadable settings of `new`
+fn with_reloadable_settings(
+    running: &super::ProxyConfiguration,
+    new: &super::ProxyConfiguration,
+) -> Result<super::ProxyConfiguration, String> {
+    let mut merged = serde_json::to_value(running).unwrap();
+    let new = serde_json::to_value(new).unwrap();
+    for path in RELOADABLE_FIELDS {
+        let (parent, key) = path.rsplit_once('.').unwrap_or(("", path));
+        let parent_pointer = if parent.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", parent.replace('.', "/"))
+        };
+        // the unset optional settings are not serialized, so the field may be missing on one side
+        if let Some(serde_json::Value::Object(fields)) = merged.pointer_mut(&parent_pointer) {
+            match new.pointer(&format!("/{}", path.replace('.', "/"))) {
+                Some(value) => fields.insert(key.to_owned(), value.clone()),
+                None => fields.remove(key),
+            };
+        }
+    }
+    serde_json::from_value(merged).map_err(|e| format!("Invalid configuration: {}", e))
+}
+
+impl ConfigurationReloader {
+    pub fn new(path: &str, configuration: super::ProxyConfiguration) -> ConfigurationReloader {
+        let settings = ReloadableSettings::from_configuration(&configuration);
+        ConfigurationReloader {
+            path: path.to_owned(),
+            configuration: std::sync::RwLock::new(configuration),
+            settings: std::sync::Arc::new(std::sync::RwLock::new(settings)),
+            router_settings: std::sync::OnceLock::new(),
+        }
+    }
+
+    pub fn settings(&self) -> std::sync::Arc<std::sync::RwLock<ReloadableSettings>> {
+        self.settings.clone()
+    }
+
+    /// Gives the settings of the router updated by the reloads, only the first call is taken into
+    /// account
+    pub fn set_router_settings(
+        &self,
+        router_settings: std::sync::Arc<std::sync::RwLock<router::LiveSettings>>,
+    ) {
+        if self.router_settings.set(router_settings).is_err() {
+            log::warn!("Router settings already given to the configuration reloader");
+        }
+    }
+
+    /// Reloads the configuration file, returning the sections that require a restart to be applied
+    ///
+    /// Nothing is applied if the new configuration is invalid
+    pub fn reload(&self) -> Result<Vec<&'static str>, String> {
+        log::info!("Reloading configuration from {}", &self.path);
+        let new_configuration = super::read_configuration(&self.path)?;
+
+        let applied_configuration = {
+            let running = self.configuration.read().unwrap();
+            for change in running.diff(&new_configuration) {
+                log::info!("Configuration changed: {}", change);
+            }
+            with_reloadable_settings(&running, &new_configuration)?
+        };
+        let router_settings = get_router_settings(&applied_configuration.server)?;
+
+        let restart_sections = get_restart_sections(&applied_configuration, &new_configuration);
+        for section in &restart_sections {
+            log::warn!(
+                "Configuration of '{}' changed, a restart is required to apply it",
+                section
+            );
+        }
+
+        let settings = ReloadableSettings::from_configuration(&applied_configuration);
+        log::set_max_level(settings.log_level);
+        log::info!("Log level set to {:?}", settings.log_level);
+        *self.settings.write().unwrap() = settings;
+        if let Some(live_settings) = self.router_settings.get() {
+            *live_settings.write().unwrap() = router_settings;
+        }
+        *self.configuration.write().unwrap() = applied_configuration;
+
+        Ok(restart_sections)
+    }
+
+    /// Describes the differences between the configuration file and the running configuration,
+    /// without applying them
+    pub fn diff_on_disk(&self) -> Result<Vec<String>, String> {
+        let on_disk = super::read_configuration(&self.path)?;
+        Ok(self.configuration.read().unwrap().diff(&on_disk))
+    }
+
+    /// Running configuration, with its secrets redacted
+    pub fn redacted_configuration(&self) -> serde_json::Value {
+        serde_json::to_value(super::Redacted(&self.configuration.read().unwrap())).unwrap()
+    }
+}
+
+/// Sections of the new configuration whose changes were not applied
+fn get_restart_sections(
+    applied_configuration: &super::ProxyConfiguration,
+    new_configuration: &super::ProxyConfiguration,
+) -> Vec<&'static str> {
+    let applied = serde_json::to_value(applied_configuration).unwrap();
+    let new = serde_json::to_value(new_configuration).unwrap();
+    ["cec", "file", "jrpc", "receiver", "server"]
+        .into_iter()
+        .filter(|section| applied[section] != new[section])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    const TEST_PATH: &str = "target/test/configuration";
+
+    fn write_configuration(name: &str, json: &str) -> String {
+        std::fs::create_dir_all(TEST_PATH).unwrap();
+        let path = format!("{}/{}", TEST_PATH, name);
+        std::fs::write(&path, json).unwrap();
+        path
+    }
+
+    #[test]
+    fn it_reloads_the_log_level() {
+        let path = write_configuration(
+            "reload_level.json",
+            r#"{"logging":{"level":"WARN"},"server":{"host":"127.0.0.1:8079"}}"#,
+        );
+        let reloader = super::ConfigurationReloader::new(
+            &path,
+            super::super::read_configuration(&path).unwrap(),
+        );
+        let settings = reloader.settings();
+
+        assert_eq!(log::LevelFilter::Warn, settings.read().unwrap().log_level);
+
+        write_configuration(
+            "reload_level.json",
+            r#"{"logging":{"level":"DEBUG"},"server":{"host":"127.0.0.1:8079"}}"#,
+        );
+        let restart_sections = reloader.reload().unwrap();
+
+        assert_eq!(log::LevelFilter::Debug, settings.read().unwrap().log_level);
+        assert!(restart_sections.is_empty());
+    }
+
+    #[test]
+    fn it_flags_settings_requiring_a_restart() {
+        let path = write_configuration(
+            "reload_host.json",
+            r#"{"server":{"host":"127.0.0.1:8079"}}"#,
+        );
+        let reloader = super::ConfigurationReloader::new(
+            &path,
+            super::super::read_configuration(&path).unwrap(),
+        );
+
+        write_configuration(
+            "reload_host.json",
+            r#"{"server":{"host":"127.0.0.1:8080"}}"#,
+        );
+
+        assert_eq!(vec!["server"], reloader.reload().unwrap());
+        assert_eq!(
+            serde_json::json!("127.0.0.1:8079"),
+            reloader.redacted_configuration()["server"]["host"]
+        );
+        assert_eq!(
+            vec![r#"server.host: "127.0.0.1:8079" -> "127.0.0.1:8080""#],
+            reloader.diff_on_disk().unwrap()
+        );
+    }
+
+    #[test]
+    fn it_reloads_the_router_settings() {
+        let path = write_configuration(
+            "reload_router.json",
+            r#"{"server":{"host":"127.0.0.1:8079"}}"#,
+        );
+        let reloader = super::ConfigurationReloader::new(
+            &path,
+            super::super::read_configuration(&path).unwrap(),
+        );
+        let router_settings =
+            std::sync::Arc::new(std::sync::RwLock::new(router::LiveSettings::default()));
+        reloader.set_router_settings(router_settings.clone());
+
+        write_configuration(
+            "reload_router.json",
+            r#"{"server":{"host":"127.0.0.1:8079","handlerTimeoutMs":2000,"cors":{"allowedOrigins":["*"],"allowedMethods":["GET"]}}}"#,
+        );
+
+        assert!(reloader.reload().unwrap().is_empty());
+        assert!(reloader.diff_on_disk().unwrap().is_empty());
+        let live_settings = router_settings.read().unwrap();
+        assert_eq!(
+            std::time::Duration::from_secs(2),
+            live_settings.default_timeout
+        );
+        let cors = live_settings.cors.as_ref().unwrap();
+        assert_eq!(vec![hyper::Method::GET], cors.allowed_methods);
+        drop(live_settings);
+
+        write_configuration(
+            "reload_router.json",
+            r#"{"server":{"host":"127.0.0.1:8079","cors":{"allowedOrigins":["*"],"allowedMethods":["NOT A METHOD"]}}}"#,
+        );
+
+        assert!(reloader.reload().is_err());
+        assert!(router_settings.read().unwrap().cors.is_some());
+        assert_eq!(
+            serde_json::json!(["GET"]),
+            reloader.redacted_configuration()["server"]["cors"]["allowedMethods"]
+        );
+    }
+}
@@ -1,6 +1,8 @@
 pub struct AVReceiverVolumeHandler {
     pub receiver: std::sync::Arc<dyn crate::avreceiver::AVReceiverInterface>,
     pub matcher: Box<dyn router::matcher::Matcher>,
+    /// Sends a null volume, instead of 0, when the receiver is turned all the way down
+    pub distinct_off_volume: bool,
 }
 
 pub struct AVReceiverPowerHandler {
@@ -18,7 +20,16 @@ impl router::Handler for AVReceiverVolumeHandler {
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
-        let (volume, is_mute) = self.handle_volume_request(request.uri()).await?;
+        let (volume, is_mute) = crate::avreceiver::with_request_headers(
+            request.headers().clone(),
+            self.handle_volume_request(request.uri()),
+        )
+        .await?;
+
+        let volume = match volume {
+            None if !self.distinct_off_volume => Some(0),
+            volume => volume,
+        };
 
         let body = serde_json::json!({
             "data": {
@@ -34,8 +45,8 @@ impl router::Handler for AVReceiverVolumeHandler {
             .unwrap())
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(10)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(10))
     }
 }
 
@@ -43,7 +54,7 @@ impl AVReceiverVolumeHandler {
     async fn handle_volume_request(
         &self,
         uri: &http::uri::Uri,
-    ) -> Result<(i16, bool), router::RouterError> {
+    ) -> Result<(Option<i16>, bool), router::RouterError> {
         let mut query: std::collections::HashMap<std::borrow::Cow<str>, std::borrow::Cow<str>> =
             form_urlencoded::parse(uri.query().unwrap_or("").as_bytes()).collect();
 
@@ -122,10 +133,18 @@ impl router::Handler for AVReceiverPowerHandler {
                     "Accepted values for power are 'on', 'off'",
                 )));
             }
-            self.receiver.set_power(power == "on").await;
+            crate::avreceiver::with_request_headers(
+                request.headers().clone(),
+                self.receiver.set_power(power == "on"),
+            )
+            .await;
         }
 
-        let power = self.receiver.is_powered_on().await;
+        let power = crate::avreceiver::with_request_headers(
+            request.headers().clone(),
+            self.receiver.is_powered_on(),
+        )
+        .await;
 
         let body = serde_json::json!({
             "data": {
@@ -140,8 +159,8 @@ impl router::Handler for AVReceiverPowerHandler {
             .unwrap())
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(10)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(10))
     }
 }
 
@@ -169,12 +188,13 @@ mod tests {
         receiver_mock
             .expect_get_volume()
             .times(2)
-            .returning(|| (25, false));
+            .returning(|| (Some(25), false));
 
         let receiver_mock = std::sync::Arc::new(receiver_mock);
         let handler = super::AVReceiverVolumeHandler {
             receiver: receiver_mock.clone(),
             matcher: crate::handlers::avreceiver::get_matcher("volume"),
+            distinct_off_volume: false,
         };
 
         let request = hyper::Request::builder()
@@ -194,6 +214,42 @@ mod tests {
         handler.handle(request).await.unwrap();
     }
 
+    #[test(tokio::test)]
+    async fn it_distinguishes_the_turned_down_volume() {
+        let mut receiver_mock = crate::avreceiver::MockAVReceiver::new();
+
+        receiver_mock
+            .expect_get_volume()
+            .times(2)
+            .returning(|| (None, false));
+
+        let receiver_mock = std::sync::Arc::new(receiver_mock);
+
+        for (distinct_off_volume, expected) in [
+            (true, serde_json::Value::Null),
+            (false, serde_json::json!(0)),
+        ] {
+            let handler = super::AVReceiverVolumeHandler {
+                receiver: receiver_mock.clone(),
+                matcher: crate::handlers::avreceiver::get_matcher("volume"),
+                distinct_off_volume,
+            };
+
+            let request = hyper::Request::builder()
+                .uri("/avreceiver/volume")
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap();
+
+            let body = hyper::body::to_bytes(handler.handle(request).await.unwrap().into_body())
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(expected, body["data"]["volume"]);
+        }
+    }
+
     #[test(tokio::test)]
     async fn it_allows_powering() {
         let mut receiver_mock = crate::avreceiver::MockAVReceiver::new();
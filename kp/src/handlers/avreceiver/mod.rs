@@ -10,11 +10,13 @@ fn get_matcher(path: &str) -> Box<dyn router::matcher::Matcher> {
 
 pub fn get_handlers(
     receiver: std::sync::Arc<dyn crate::avreceiver::AVReceiverInterface>,
+    configuration: &crate::configuration::AVReceiverConfiguration,
 ) -> Vec<Box<dyn router::Handler>> {
     vec![
         Box::from(handlers::AVReceiverVolumeHandler {
             receiver: receiver.clone(),
             matcher: get_matcher("volume"),
+            distinct_off_volume: configuration.distinct_off_volume,
         }),
         Box::from(handlers::AVReceiverPowerHandler {
             receiver: receiver.clone(),
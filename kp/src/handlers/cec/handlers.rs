@@ -1,15 +1,37 @@
 use std::str::FromStr;
 
 pub struct CECPowerOn {
+    /// If set, the handler always targets this address and ignores the query parameters
+    pub address: Option<crate::cec::CECLogicalAddress>,
     pub connection: std::sync::Arc<std::sync::Mutex<dyn crate::cec::CECInterface>>,
     pub matcher: Box<dyn router::matcher::Matcher>,
 }
 
 pub struct CECStandby {
+    /// If set, the handler always targets this address and ignores the query parameters
+    pub address: Option<crate::cec::CECLogicalAddress>,
     pub connection: std::sync::Arc<std::sync::Mutex<dyn crate::cec::CECInterface>>,
     pub matcher: Box<dyn router::matcher::Matcher>,
 }
 
+/// Gets the targeted address from the `address` (or legacy `device`) query parameter, defaulting
+/// to broadcast
+fn get_address(
+    request: &hyper::Request<hyper::Body>,
+    address: &Option<crate::cec::CECLogicalAddress>,
+) -> Result<crate::cec::CECLogicalAddress, router::RouterError> {
+    if let Some(address) = address {
+        return Ok(*address);
+    }
+    form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes())
+        .find(|(param, _)| param == "address" || param == "device")
+        .map(|(param, value)| {
+            crate::cec::CECLogicalAddress::from_str(&value)
+                .map_err(|_| router::InvalidRequest(format!("Invalid {} parameter", param)))
+        })
+        .unwrap_or(Ok(crate::cec::CECLogicalAddress::Broadcast))
+}
+
 // TODO reduce copy paste
 #[async_trait::async_trait]
 impl router::Handler for CECPowerOn {
@@ -21,11 +43,7 @@ impl router::Handler for CECPowerOn {
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
-        let address = form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes())
-            .find(|(param, _)| param == "device")
-            .map(|(_, value)| crate::cec::CECLogicalAddress::from_str(&value))
-            .unwrap_or(Ok(crate::cec::CECLogicalAddress::Broadcast))
-            .map_err(|_| router::InvalidRequest(String::from("Invalid device parameter")))?;
+        let address = get_address(&request, &self.address)?;
 
         self.connection
             .lock()
@@ -35,8 +53,7 @@ impl router::Handler for CECPowerOn {
                     String::from("Failed to acquire lock on CEC connection"),
                 )
             })?
-            .power_on(address)
-            .map_err(|e| router::HandlerError(500, format!("Failed to turn on device: {:?}", e)))?;
+            .power_on(address)?;
 
         Ok(hyper::Response::builder()
             .status(204)
@@ -44,8 +61,8 @@ impl router::Handler for CECPowerOn {
             .unwrap())
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(5)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(5))
     }
 }
 
@@ -59,11 +76,7 @@ impl router::Handler for CECStandby {
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
-        let address = form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes())
-            .find(|(param, _)| param == "device")
-            .map(|(_, value)| crate::cec::CECLogicalAddress::from_str(&value))
-            .unwrap_or(Ok(crate::cec::CECLogicalAddress::Broadcast))
-            .map_err(|_| router::InvalidRequest(String::from("Invalid device parameter")))?;
+        let address = get_address(&request, &self.address)?;
 
         self.connection
             .lock()
@@ -73,10 +86,7 @@ impl router::Handler for CECStandby {
                     String::from("Failed to acquire lock on CEC connection"),
                 )
             })?
-            .standby(address)
-            .map_err(|e| {
-                router::HandlerError(500, format!("Failed put device in standby: {:?}", e))
-            })?;
+            .standby(address)?;
 
         Ok(hyper::Response::builder()
             .status(204)
@@ -84,7 +94,138 @@ impl router::Handler for CECStandby {
             .unwrap())
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(5)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(5))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use router::Handler;
+    use test_log::test;
+
+    fn get_handler(error: crate::cec::CECError) -> super::CECPowerOn {
+        let mut mock_cec = crate::cec::MockCECInterface::new();
+        mock_cec
+            .expect_power_on()
+            .times(1)
+            .returning(move |_| Err(error));
+        super::CECPowerOn {
+            address: None,
+            connection: std::sync::Arc::new(std::sync::Mutex::new(mock_cec)),
+            matcher: crate::handlers::cec::get_matcher("power-on"),
+        }
+    }
+
+    fn get_request() -> hyper::Request<hyper::Body> {
+        get_request_with_uri("/cec/power-on")
+    }
+
+    fn get_request_with_uri(uri: &str) -> hyper::Request<hyper::Body> {
+        hyper::Request::builder()
+            .uri(uri)
+            .method("GET")
+            .body(hyper::Body::empty())
+            .unwrap()
+    }
+
+    #[test(tokio::test)]
+    async fn it_maps_cec_errors() {
+        let cases = [
+            (
+                crate::cec::CECError::AdapterNotFound,
+                503,
+                "AdapterNotFound",
+            ),
+            (crate::cec::CECError::CommandFailed, 502, "CommandFailed"),
+            (crate::cec::CECError::InitFailed, 503, "InitFailed"),
+            (
+                crate::cec::CECError::InvalidConfiguration("Invalid CEC version"),
+                500,
+                "InvalidConfiguration",
+            ),
+            (crate::cec::CECError::OpenFailed, 503, "OpenFailed"),
+        ];
+
+        for (error, expected_status, expected_kind) in cases {
+            let handler = get_handler(error);
+
+            let error = handler.handle(get_request()).await.unwrap_err();
+
+            match error {
+                router::RouterError::JsonError(status, body) => {
+                    assert_eq!(expected_status, status);
+                    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+                    assert_eq!(expected_kind, body["kind"]);
+                    assert!(body["error"].is_string());
+                }
+                _ => panic!("Wrong type of error"),
+            }
+        }
+    }
+
+    fn get_standby_handler(
+        address: Option<crate::cec::CECLogicalAddress>,
+        expected_address: crate::cec::CECLogicalAddress,
+    ) -> super::CECStandby {
+        let mut mock_cec = crate::cec::MockCECInterface::new();
+        mock_cec
+            .expect_standby()
+            .with(mockall::predicate::eq(expected_address))
+            .times(1)
+            .returning(|_| Ok(()));
+        super::CECStandby {
+            address,
+            connection: std::sync::Arc::new(std::sync::Mutex::new(mock_cec)),
+            matcher: crate::handlers::cec::get_matcher("standby"),
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_broadcasts_on_the_all_endpoint() {
+        let handler = get_standby_handler(
+            Some(crate::cec::CECLogicalAddress::Broadcast),
+            crate::cec::CECLogicalAddress::Broadcast,
+        );
+
+        let response = handler
+            .handle(get_request_with_uri("/cec/standby-all?address=TV"))
+            .await
+            .unwrap();
+
+        assert_eq!(204, response.status());
+    }
+
+    #[test(tokio::test)]
+    async fn it_targets_the_requested_address() {
+        let handler = get_standby_handler(None, crate::cec::CECLogicalAddress::AudioSystem);
+
+        let response = handler
+            .handle(get_request_with_uri("/cec/standby?address=AUDIO_SYSTEM"))
+            .await
+            .unwrap();
+
+        assert_eq!(204, response.status());
+    }
+
+    #[test(tokio::test)]
+    async fn it_rejects_invalid_addresses() {
+        let handler = super::CECStandby {
+            address: None,
+            connection: std::sync::Arc::new(std::sync::Mutex::new(
+                crate::cec::MockCECInterface::new(),
+            )),
+            matcher: crate::handlers::cec::get_matcher("standby"),
+        };
+
+        let error = handler
+            .handle(get_request_with_uri("/cec/standby?address=KITCHEN"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            router::InvalidRequest(String::from("Invalid address parameter")),
+            error
+        );
     }
 }
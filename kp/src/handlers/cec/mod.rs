@@ -13,12 +13,24 @@ pub fn get_cec_handlers(
 ) -> Vec<Box<dyn router::Handler>> {
     vec![
         Box::from(handlers::CECPowerOn {
+            address: None,
             connection: cec_interface.clone(),
             matcher: get_matcher("power-on"),
         }),
+        Box::from(handlers::CECPowerOn {
+            address: Some(crate::cec::CECLogicalAddress::Broadcast),
+            connection: cec_interface.clone(),
+            matcher: get_matcher("power-on-all"),
+        }),
         Box::from(handlers::CECStandby {
+            address: None,
             connection: cec_interface.clone(),
             matcher: get_matcher("standby"),
         }),
+        Box::from(handlers::CECStandby {
+            address: Some(crate::cec::CECLogicalAddress::Broadcast),
+            connection: cec_interface.clone(),
+            matcher: get_matcher("standby-all"),
+        }),
     ]
 }
@@ -0,0 +1,174 @@
+/// Sends the running configuration, with its secrets redacted
+pub struct ConfigHandler {
+    pub reloader: std::sync::Arc<crate::configuration::ConfigurationReloader>,
+    pub matcher: Box<dyn router::matcher::Matcher>,
+}
+
+#[async_trait::async_trait]
+impl router::Handler for ConfigHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        &self.matcher
+    }
+
+    async fn handle(
+        &self,
+        _request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        Ok(hyper::Response::builder()
+            .status(200)
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(
+                self.reloader.redacted_configuration().to_string(),
+            ))
+            .unwrap())
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
+    }
+}
+
+/// Sends the differences between the configuration file and the running configuration, i.e. what
+/// a reload, or a restart for the settings that cannot be reloaded, would change
+pub struct ConfigDiffHandler {
+    pub reloader: std::sync::Arc<crate::configuration::ConfigurationReloader>,
+    pub matcher: Box<dyn router::matcher::Matcher>,
+}
+
+#[async_trait::async_trait]
+impl router::Handler for ConfigDiffHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        &self.matcher
+    }
+
+    async fn handle(
+        &self,
+        _request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        let changes = self
+            .reloader
+            .diff_on_disk()
+            .map_err(|error| router::HandlerError(500, error))?;
+
+        Ok(hyper::Response::builder()
+            .status(200)
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(
+                serde_json::json!({ "changes": changes }).to_string(),
+            ))
+            .unwrap())
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    const TEST_PATH: &str = "target/test/configuration_handlers";
+
+    #[test(tokio::test)]
+    async fn it_sends_the_redacted_configuration() {
+        std::fs::create_dir_all(TEST_PATH).unwrap();
+        let path = format!("{}/config.json", TEST_PATH);
+        std::fs::write(
+            &path,
+            r#"{"file":{"apiKeys":{"k3y":"tenant"}},"server":{"host":"127.0.0.1:8079"}}"#,
+        )
+        .unwrap();
+        let reloader = crate::configuration::ConfigurationReloader::new(
+            &path,
+            crate::configuration::read_configuration(&path).unwrap(),
+        );
+
+        let handler = super::super::get_config_handler(
+            std::sync::Arc::new(reloader),
+            Some(String::from("admin-key")),
+        );
+        let request = hyper::Request::builder()
+            .uri("/maintenance/config")
+            .header("authorization", "Bearer admin-key")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        let body = hyper::body::to_bytes(handler.handle(request).await.unwrap().into_body())
+            .await
+            .unwrap();
+        let configuration: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(
+            serde_json::json!("127.0.0.1:8079"),
+            configuration["server"]["host"]
+        );
+        assert_eq!(serde_json::json!("***"), configuration["file"]["apiKeys"]);
+    }
+
+    #[test(tokio::test)]
+    async fn it_sends_the_pending_changes() {
+        std::fs::create_dir_all(TEST_PATH).unwrap();
+        let path = format!("{}/config_diff.json", TEST_PATH);
+        std::fs::write(&path, r#"{"server":{"host":"127.0.0.1:8079"}}"#).unwrap();
+        let reloader = crate::configuration::ConfigurationReloader::new(
+            &path,
+            crate::configuration::read_configuration(&path).unwrap(),
+        );
+        std::fs::write(&path, r#"{"server":{"host":"127.0.0.1:8080"}}"#).unwrap();
+
+        let handler = super::super::get_config_diff_handler(
+            std::sync::Arc::new(reloader),
+            Some(String::from("admin-key")),
+        );
+        let request = hyper::Request::builder()
+            .uri("/maintenance/config-diff")
+            .header("authorization", "Bearer admin-key")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        let body = hyper::body::to_bytes(handler.handle(request).await.unwrap().into_body())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            serde_json::json!({ "changes": [r#"server.host: "127.0.0.1:8079" -> "127.0.0.1:8080""#] }),
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn it_requires_the_admin_key() {
+        std::fs::create_dir_all(TEST_PATH).unwrap();
+        let path = format!("{}/config_admin.json", TEST_PATH);
+        std::fs::write(&path, r#"{"server":{"host":"127.0.0.1:8079"}}"#).unwrap();
+        let reloader = std::sync::Arc::new(crate::configuration::ConfigurationReloader::new(
+            &path,
+            crate::configuration::read_configuration(&path).unwrap(),
+        ));
+
+        for admin_key in [None, Some(String::from("admin-key"))] {
+            let handlers = [
+                super::super::get_config_handler(reloader.clone(), admin_key.clone()),
+                super::super::get_config_diff_handler(reloader.clone(), admin_key.clone()),
+            ];
+            for handler in handlers {
+                for key in [None, Some("wrong-key")] {
+                    let mut request = hyper::Request::builder().uri("/maintenance/config");
+                    if let Some(key) = key {
+                        request = request.header("authorization", format!("Bearer {}", key));
+                    }
+
+                    let result = handler
+                        .handle(request.body(hyper::Body::empty()).unwrap())
+                        .await;
+
+                    assert!(matches!(
+                        result,
+                        Err(router::RouterError::HandlerError(401, _))
+                    ));
+                }
+            }
+        }
+    }
+}
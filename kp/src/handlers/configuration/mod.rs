@@ -0,0 +1,47 @@
+mod handlers;
+
+/// The configuration may hold secrets, even redacted, so it is only sent for the admin key of the
+/// file configuration, whether tenants are configured or not
+fn admin_handler<H: router::Handler + 'static>(
+    admin_key: Option<String>,
+    handler: H,
+) -> Box<dyn router::Handler> {
+    Box::from(files::handlers::AdminHandler {
+        handler: Box::from(handler),
+        admin_key,
+    })
+}
+
+pub fn get_config_handler(
+    reloader: std::sync::Arc<crate::configuration::ConfigurationReloader>,
+    admin_key: Option<String>,
+) -> Box<dyn router::Handler> {
+    admin_handler(
+        admin_key,
+        handlers::ConfigHandler {
+            reloader,
+            matcher: router::matcher::builder()
+                .exact_path(String::from("/maintenance/config"))
+                .with_method(&hyper::Method::GET)
+                .build()
+                .unwrap(),
+        },
+    )
+}
+
+pub fn get_config_diff_handler(
+    reloader: std::sync::Arc<crate::configuration::ConfigurationReloader>,
+    admin_key: Option<String>,
+) -> Box<dyn router::Handler> {
+    admin_handler(
+        admin_key,
+        handlers::ConfigDiffHandler {
+            reloader,
+            matcher: router::matcher::builder()
+                .exact_path(String::from("/maintenance/config-diff"))
+                .with_method(&hyper::Method::GET)
+                .build()
+                .unwrap(),
+        },
+    )
+}
@@ -0,0 +1,187 @@
+/// Answers the liveness probes, optionally checking that kodi can be reached
+pub struct HealthHandler {
+    pub kodi: Option<Box<crate::handlers::jsonrpc::JsonrpcHandler>>,
+    pub disk: Option<DiskCheck>,
+    pub matcher: Box<dyn router::matcher::Matcher>,
+}
+
+/// Free space on the filesystem containing a path, the health being degraded below a threshold
+pub struct DiskCheck {
+    pub path: std::path::PathBuf,
+    pub min_free_bytes: u64,
+    /// Gives the free bytes of the filesystem of the path, `None` if it cannot be known
+    pub free_bytes: fn(&std::path::Path) -> Option<u64>,
+}
+
+impl DiskCheck {
+    pub fn new(path: std::path::PathBuf, min_free_bytes: u64) -> DiskCheck {
+        DiskCheck {
+            path,
+            min_free_bytes,
+            free_bytes: get_free_bytes,
+        }
+    }
+
+    /// Gives the free bytes and whether they are above the threshold
+    fn evaluate(&self) -> (Option<u64>, bool) {
+        let free_bytes = (self.free_bytes)(&self.path);
+        let healthy = match free_bytes {
+            Some(free_bytes) if free_bytes < self.min_free_bytes => {
+                log::warn!(
+                    "Only {} bytes left on the filesystem of {:?}",
+                    free_bytes,
+                    self.path
+                );
+                false
+            }
+            Some(_) => true,
+            None => {
+                log::warn!("Could not get the free space of {:?}", self.path);
+                false
+            }
+        };
+        (free_bytes, healthy)
+    }
+}
+
+/// Bytes available to unprivileged users on the filesystem containing the path
+fn get_free_bytes(path: &std::path::Path) -> Option<u64> {
+    let path =
+        std::ffi::CString::new(std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str())).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[async_trait::async_trait]
+impl router::Handler for HealthHandler {
+    fn get_matcher(&self) -> &Box<dyn router::matcher::Matcher> {
+        &self.matcher
+    }
+
+    async fn handle(
+        &self,
+        _request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
+        let mut health = serde_json::json!({ "status": "ok" });
+        if let Some(kodi) = &self.kodi {
+            health["kodi"] = serde_json::Value::from(kodi.ping_upstream().await);
+        }
+        if let Some(disk) = &self.disk {
+            let (free_bytes, healthy) = disk.evaluate();
+            health["freeBytes"] = serde_json::Value::from(free_bytes);
+            if !healthy {
+                health["status"] = serde_json::Value::from("degraded");
+            }
+        }
+
+        Ok(hyper::Response::builder()
+            .status(200)
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(health.to_string()))
+            .unwrap())
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(5))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    async fn get_health(handler: Box<dyn router::Handler>) -> serde_json::Value {
+        let request = hyper::Request::builder()
+            .uri("/health")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        let body = hyper::body::to_bytes(handler.handle(request).await.unwrap().into_body())
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test(tokio::test)]
+    async fn it_reports_whether_kodi_is_reachable() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200)
+                    .set_body_bytes(r#"{"id":1,"jsonrpc":"2.0","result":"pong"}"#),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let reachable = crate::configuration::JRPCConfiguration {
+            health_ping_timeout_ms: Some(500),
+            target: mock_server.uri(),
+            ..Default::default()
+        };
+        let unreachable = crate::configuration::JRPCConfiguration {
+            health_ping_timeout_ms: Some(500),
+            target: String::from("http://127.0.0.1:1"),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serde_json::json!({ "status": "ok", "kodi": true }),
+            get_health(super::super::get_health_handler(
+                crate::handlers::jsonrpc::get_upstream_pinger(&reachable),
+                None
+            ))
+            .await
+        );
+        assert_eq!(
+            serde_json::json!({ "status": "ok", "kodi": false }),
+            get_health(super::super::get_health_handler(
+                crate::handlers::jsonrpc::get_upstream_pinger(&unreachable),
+                None
+            ))
+            .await
+        );
+        assert_eq!(
+            serde_json::json!({ "status": "ok" }),
+            get_health(super::super::get_health_handler(None, None)).await
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn it_degrades_the_health_below_the_free_space_threshold() {
+        let get_disk_check = |free_bytes: fn(&std::path::Path) -> Option<u64>| super::DiskCheck {
+            path: std::path::PathBuf::from("/"),
+            min_free_bytes: 1000,
+            free_bytes,
+        };
+
+        assert_eq!(
+            serde_json::json!({ "status": "ok", "freeBytes": 1000 }),
+            get_health(super::super::get_health_handler(
+                None,
+                Some(get_disk_check(|_| Some(1000)))
+            ))
+            .await
+        );
+        assert_eq!(
+            serde_json::json!({ "status": "degraded", "freeBytes": 999 }),
+            get_health(super::super::get_health_handler(
+                None,
+                Some(get_disk_check(|_| Some(999)))
+            ))
+            .await
+        );
+        assert_eq!(
+            serde_json::json!({ "status": "degraded", "freeBytes": null }),
+            get_health(super::super::get_health_handler(
+                None,
+                Some(get_disk_check(|_| None))
+            ))
+            .await
+        );
+        assert!(super::get_free_bytes(std::path::Path::new("/")).is_some());
+    }
+}
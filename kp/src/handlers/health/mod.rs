@@ -0,0 +1,18 @@
+mod handlers;
+
+pub use self::handlers::DiskCheck;
+
+pub fn get_health_handler(
+    kodi: Option<Box<crate::handlers::jsonrpc::JsonrpcHandler>>,
+    disk: Option<DiskCheck>,
+) -> Box<dyn router::Handler> {
+    Box::from(handlers::HealthHandler {
+        kodi,
+        disk,
+        matcher: router::matcher::builder()
+            .exact_path(String::from("/health"))
+            .with_method(&hyper::Method::GET)
+            .build()
+            .unwrap(),
+    })
+}
@@ -0,0 +1,110 @@
+/// Circuit breaker protecting the upstream jsonrpc server
+///
+/// After `failure_threshold` consecutive failures within `window`, the breaker opens and rejects
+/// the forwards for `cooldown`. It then lets the requests through again (half-open): the first
+/// outcome either closes it or opens it for another cooldown.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    window: std::time::Duration,
+    cooldown: std::time::Duration,
+    state: std::sync::Mutex<BreakerState>,
+}
+
+enum BreakerState {
+    Closed {
+        failures: u32,
+        first_failure: Option<std::time::Instant>,
+    },
+    Open {
+        until: std::time::Instant,
+    },
+    HalfOpen,
+}
+
+impl CircuitBreaker {
+    pub fn new(
+        failure_threshold: u32,
+        window: std::time::Duration,
+        cooldown: std::time::Duration,
+    ) -> CircuitBreaker {
+        CircuitBreaker {
+            failure_threshold,
+            window,
+            cooldown,
+            state: std::sync::Mutex::new(BreakerState::Closed {
+                failures: 0,
+                first_failure: None,
+            }),
+        }
+    }
+
+    /// Returns an error if the breaker is open and the forward must not be attempted
+    pub fn check(&self) -> Result<(), router::RouterError> {
+        let mut state = self.state.lock().unwrap();
+        if let BreakerState::Open { until } = *state {
+            if std::time::Instant::now() < until {
+                return Err(router::HandlerError(
+                    503,
+                    String::from("Jsonrpc server unavailable"),
+                ));
+            }
+            log::info!("Jsonrpc circuit breaker half-open");
+            *state = BreakerState::HalfOpen;
+        }
+        Ok(())
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let BreakerState::HalfOpen = *state {
+            log::info!("Jsonrpc circuit breaker closed");
+        }
+        *state = BreakerState::Closed {
+            failures: 0,
+            first_failure: None,
+        };
+    }
+
+    pub fn record_failure(&self) {
+        let now = std::time::Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let (failures, first_failure) = match *state {
+            BreakerState::Closed {
+                failures,
+                first_failure: Some(first_failure),
+            } if now.duration_since(first_failure) <= self.window => (failures + 1, first_failure),
+            BreakerState::Closed { .. } => (1, now),
+            BreakerState::Open { .. } => return,
+            BreakerState::HalfOpen => (self.failure_threshold, now),
+        };
+        if failures >= self.failure_threshold {
+            log::warn!("Jsonrpc circuit breaker opened after {} failures", failures);
+            *state = BreakerState::Open {
+                until: now + self.cooldown,
+            };
+        } else {
+            *state = BreakerState::Closed {
+                failures,
+                first_failure: Some(first_failure),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn it_only_counts_failures_within_the_window() {
+        let breaker = super::CircuitBreaker::new(
+            2,
+            std::time::Duration::from_millis(0),
+            std::time::Duration::from_secs(60),
+        );
+
+        breaker.record_failure();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        breaker.record_failure();
+
+        assert!(breaker.check().is_ok());
+    }
+}
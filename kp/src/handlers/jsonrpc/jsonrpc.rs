@@ -10,14 +10,29 @@ pub trait JsonrpcOverloader: Sync + Send {
         json_request: JRPCQuery,
         handler: &JsonrpcHandler,
     ) -> Result<JRPCResponse, router::RouterError>;
+    /// Whether the overloaded method takes parameters, the queries without any are then refused
+    /// with a -32602 error before reaching the overloader
+    fn requires_params(&self) -> bool {
+        true
+    }
 }
 
 /// Builder for [JsonrpcHandler](crate::jsonrpc::JsonrpcHandler)
 pub struct JsonrpcHandlerBuilder {
+    allowed_methods: Vec<hyper::Method>,
     authority: String,
+    circuit_breaker: Option<super::circuitbreaker::CircuitBreaker>,
+    describe_on_get: bool,
+    method_aliases: std::collections::HashMap<String, String>,
+    max_request_bytes: Option<u64>,
     scheme: String,
     overloaders: std::collections::HashMap<String, Box<dyn JsonrpcOverloader>>,
     path: String,
+    ping_timeout: std::time::Duration,
+    require_version: bool,
+    required_header: Option<(hyper::header::HeaderName, hyper::header::HeaderValue)>,
+    upstream_tally: Option<std::sync::Arc<router::debug::UpstreamTally>>,
+    upstream_timeout: Option<std::time::Duration>,
 }
 
 /// Sub router dedicated to jsonrpc queries
@@ -26,10 +41,22 @@ pub struct JsonrpcHandlerBuilder {
 /// one is registered to the method, otherwise forwards the query to the actual jsonrpc server
 pub struct JsonrpcHandler {
     scheme: String,
+    allowed_methods: Vec<hyper::Method>,
     authority: String,
+    circuit_breaker: Option<super::circuitbreaker::CircuitBreaker>,
+    /// Client of all the queries to the jsonrpc server, so that its connections are reused
+    client: hyper::Client<hyper::client::HttpConnector>,
+    describe_on_get: bool,
     matcher: Box<dyn router::matcher::Matcher>,
+    max_request_bytes: Option<u64>,
+    method_aliases: std::collections::HashMap<String, String>,
     overloaders: std::collections::HashMap<String, Box<dyn JsonrpcOverloader>>,
     path: String,
+    ping_timeout: std::time::Duration,
+    require_version: bool,
+    required_header: Option<(hyper::header::HeaderName, hyper::header::HeaderValue)>,
+    upstream_tally: Option<std::sync::Arc<router::debug::UpstreamTally>>,
+    upstream_timeout: Option<std::time::Duration>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -43,10 +70,27 @@ pub struct JRPCQuery {
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct JRPCResponse {
     jsonrpc: Option<String>,
+    /// Absent from the error responses, `null` in the successful responses without a result
+    #[serde(skip_serializing_if = "Option::is_none")]
     result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    error: Option<JRPCError>,
     id: Option<i32>,
 }
 
+/// Error object of a [JRPCResponse]
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct JRPCError {
+    code: i32,
+    message: String,
+}
+
+impl JRPCError {
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+    pub const INVALID_PARAMS: i32 = -32602;
+    pub const INTERNAL_ERROR: i32 = -32603;
+}
+
 impl JRPCQuery {
     pub fn params(&self) -> Option<&serde_json::Value> {
         self.params.as_ref()
@@ -71,14 +115,51 @@ impl JRPCResponse {
     pub fn new(result: Option<serde_json::Value>, id: Option<i32>) -> JRPCResponse {
         JRPCResponse {
             jsonrpc: Some(String::from("2.0")),
-            result,
+            result: Some(result.unwrap_or(serde_json::Value::Null)),
+            error: None,
             id,
         }
     }
 
+    /// Response telling that the query failed, with one of the codes of [JRPCError]
+    pub fn error(code: i32, message: &str, id: Option<i32>) -> JRPCResponse {
+        JRPCResponse {
+            jsonrpc: Some(String::from("2.0")),
+            result: None,
+            error: Some(JRPCError {
+                code,
+                message: message.to_owned(),
+            }),
+            id,
+        }
+    }
+
+    /// Converts the failure of an overloader or a forward, the invalid requests being attributed
+    /// to their parameters
+    pub fn from_router_error(error: &router::RouterError, id: Option<i32>) -> JRPCResponse {
+        log::info!("Sending jsonrpc error for {:?}", error);
+        match error {
+            router::InvalidRequest(message) => {
+                JRPCResponse::error(JRPCError::INVALID_PARAMS, message, id)
+            }
+            router::ForwardingError(message) | router::HandlerError(_, message) => {
+                JRPCResponse::error(JRPCError::INTERNAL_ERROR, message, id)
+            }
+            error => JRPCResponse::error(JRPCError::INTERNAL_ERROR, &format!("{:?}", error), id),
+        }
+    }
+
     pub fn result(&self) -> &Option<serde_json::Value> {
         &self.result
     }
+
+    fn into_http_response(self) -> hyper::Response<hyper::Body> {
+        hyper::Response::builder()
+            .status(200)
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(serde_json::to_string(&self).unwrap()))
+            .unwrap()
+    }
 }
 
 impl JsonrpcHandlerBuilder {
@@ -95,6 +176,111 @@ impl JsonrpcHandlerBuilder {
         self
     }
 
+    /// Short-circuits the forwards with a 503 for `cooldown` after `failure_threshold`
+    /// consecutive failures within `window`
+    pub fn with_circuit_breaker(
+        mut self,
+        failure_threshold: u32,
+        window: std::time::Duration,
+        cooldown: std::time::Duration,
+    ) -> JsonrpcHandlerBuilder {
+        self.circuit_breaker = Some(super::circuitbreaker::CircuitBreaker::new(
+            failure_threshold,
+            window,
+            cooldown,
+        ));
+        self
+    }
+
+    /// Counts the successes and failures of the forwards
+    pub fn with_upstream_tally(
+        mut self,
+        upstream_tally: std::sync::Arc<router::debug::UpstreamTally>,
+    ) -> JsonrpcHandlerBuilder {
+        self.upstream_tally = Some(upstream_tally);
+        self
+    }
+
+    /// Renames the given methods before looking for an overloader or forwarding them
+    pub fn with_method_aliases(
+        mut self,
+        method_aliases: &std::collections::HashMap<String, String>,
+    ) -> JsonrpcHandlerBuilder {
+        self.method_aliases.extend(
+            method_aliases
+                .iter()
+                .map(|(alias, method)| (alias.to_owned(), method.to_owned())),
+        );
+        self
+    }
+
+    /// Refuses the requests with a 401 unless they contain the given header with the given value
+    ///
+    /// The header is removed before forwarding the requests
+    pub fn with_required_header(
+        mut self,
+        required_header: Option<(String, String)>,
+    ) -> JsonrpcHandlerBuilder {
+        self.required_header = required_header.map(|(name, value)| {
+            (
+                hyper::header::HeaderName::from_bytes(name.as_bytes())
+                    .expect("Invalid name for the required jsonrpc header"),
+                hyper::header::HeaderValue::from_str(&value)
+                    .expect("Invalid value for the required jsonrpc header"),
+            )
+        });
+        self
+    }
+
+    /// Maximum time [ping_upstream](crate::jsonrpc::JsonrpcHandler::ping_upstream()) waits for
+    /// the jsonrpc server
+    pub fn with_ping_timeout(mut self, ping_timeout: std::time::Duration) -> JsonrpcHandlerBuilder {
+        self.ping_timeout = ping_timeout;
+        self
+    }
+
+    /// Maximum time [forward](crate::jsonrpc::JsonrpcHandler::forward()) waits for the jsonrpc
+    /// server before failing with a forwarding error
+    pub fn with_upstream_timeout(
+        mut self,
+        upstream_timeout: std::time::Duration,
+    ) -> JsonrpcHandlerBuilder {
+        self.upstream_timeout = Some(upstream_timeout);
+        self
+    }
+
+    /// Refuses the requests that do not contain `"jsonrpc": "2.0"` with an Invalid Request error
+    pub fn with_require_version(mut self, require_version: bool) -> JsonrpcHandlerBuilder {
+        self.require_version = require_version;
+        self
+    }
+
+    /// Refuses with a 413 the requests declaring a `Content-Length` bigger than this, before
+    /// reading their body
+    pub fn with_max_request_bytes(
+        mut self,
+        max_request_bytes: Option<u64>,
+    ) -> JsonrpcHandlerBuilder {
+        self.max_request_bytes = max_request_bytes;
+        self
+    }
+
+    /// Answers the GET requests with a json document listing the overloaded methods instead of
+    /// forwarding them
+    pub fn with_describe_on_get(mut self, describe_on_get: bool) -> JsonrpcHandlerBuilder {
+        self.describe_on_get = describe_on_get;
+        self
+    }
+
+    /// Methods sent in the Allow header of the answers to OPTIONS, which are never forwarded
+    pub fn with_allowed_methods(
+        mut self,
+        allowed_methods: Vec<hyper::Method>,
+    ) -> JsonrpcHandlerBuilder {
+        self.allowed_methods = allowed_methods;
+        self
+    }
+
     /// Adds an overloader
     pub fn add_overloader(
         mut self,
@@ -109,13 +295,24 @@ impl JsonrpcHandlerBuilder {
     pub fn build(self) -> Box<JsonrpcHandler> {
         Box::from(JsonrpcHandler {
             scheme: self.scheme,
+            allowed_methods: self.allowed_methods,
             authority: self.authority,
+            circuit_breaker: self.circuit_breaker,
+            client: hyper::Client::new(),
+            describe_on_get: self.describe_on_get,
             matcher: router::matcher::builder()
                 .exact_path(&self.path)
                 .build()
                 .unwrap(),
+            max_request_bytes: self.max_request_bytes,
+            method_aliases: self.method_aliases,
             overloaders: self.overloaders,
             path: self.path,
+            ping_timeout: self.ping_timeout,
+            require_version: self.require_version,
+            required_header: self.required_header,
+            upstream_tally: self.upstream_tally,
+            upstream_timeout: self.upstream_timeout,
         })
     }
 }
@@ -123,10 +320,24 @@ impl JsonrpcHandlerBuilder {
 impl JsonrpcHandler {
     pub fn builder() -> JsonrpcHandlerBuilder {
         JsonrpcHandlerBuilder {
+            allowed_methods: vec![
+                hyper::Method::POST,
+                hyper::Method::GET,
+                hyper::Method::OPTIONS,
+            ],
             authority: String::from("127.0.0.1:8080"),
+            circuit_breaker: None,
+            describe_on_get: false,
+            max_request_bytes: None,
+            method_aliases: std::collections::HashMap::new(),
             scheme: String::from("http"),
             overloaders: std::collections::HashMap::new(),
             path: String::from("/jsonrpc"),
+            ping_timeout: std::time::Duration::from_secs(2),
+            require_version: false,
+            required_header: None,
+            upstream_tally: None,
+            upstream_timeout: None,
         }
     }
 
@@ -145,9 +356,17 @@ impl JsonrpcHandler {
 
         log::trace!("Sending {:?}", &body);
 
+        // tags the logs of the forward so that they can be correlated with the client request
+        let request_id = parts
+            .headers
+            .get("x-request-id")
+            .and_then(|request_id| request_id.to_str().ok())
+            .unwrap_or("-")
+            .to_owned();
+
         let mut request_builder = hyper::Request::builder()
             .method(parts.method)
-            .uri(uri)
+            .uri(uri.clone())
             .version(parts.version);
 
         let headers = request_builder.headers_mut().unwrap();
@@ -161,10 +380,55 @@ impl JsonrpcHandler {
                 JsonrpcHandler::f_err("Error while building the forwarding jsonrpc request", &err)
             })?;
 
-        hyper::Client::new()
-            .request(request)
-            .await
-            .map_err(|err| JsonrpcHandler::f_err("Error while forwarding jsonrpc request", &err))
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.check()?;
+        }
+
+        let start = std::time::Instant::now();
+        let request = async {
+            self.client.request(request).await.map_err(|err| {
+                JsonrpcHandler::f_err("Error while forwarding jsonrpc request", &err)
+            })
+        };
+        let response = match self.upstream_timeout {
+            Some(timeout) => async_std::future::timeout(timeout, request)
+                .await
+                .unwrap_or_else(|_| {
+                    log::warn!("Jsonrpc server did not answer within {:?}", timeout);
+                    Err(router::ForwardingError(String::from("upstream timed out")))
+                }),
+            None => request.await,
+        };
+
+        match &response {
+            Ok(response) => log::debug!(
+                "[{}] Forwarded to {}: {} in {}ms",
+                request_id,
+                uri,
+                response.status().as_u16(),
+                start.elapsed().as_millis()
+            ),
+            Err(_) => log::debug!(
+                "[{}] Forwarding to {} failed in {}ms",
+                request_id,
+                uri,
+                start.elapsed().as_millis()
+            ),
+        }
+
+        let success = matches!(&response, Ok(response) if !response.status().is_server_error());
+        if let Some(upstream_tally) = &self.upstream_tally {
+            upstream_tally.record(success);
+        }
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            if success {
+                circuit_breaker.record_success();
+            } else {
+                circuit_breaker.record_failure();
+            }
+        }
+
+        response
     }
 
     pub async fn forward_jrpc(
@@ -190,6 +454,43 @@ impl JsonrpcHandler {
         Ok(json)
     }
 
+    /// Checks that the jsonrpc server answers a JSONRPC.Ping within the ping timeout
+    ///
+    /// The ping bypasses the circuit breaker and is not counted in the upstream tally.
+    pub async fn ping_upstream(&self) -> bool {
+        let uri = hyper::Uri::builder()
+            .scheme(self.scheme.as_str())
+            .authority(self.authority.as_str())
+            .path_and_query(self.path.as_str())
+            .build()
+            .unwrap();
+        let query = JRPCQuery::new(String::from("JSONRPC.Ping"), None, Some(1));
+        let request = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(serde_json::to_string(&query).unwrap()))
+            .unwrap();
+
+        let ping = async {
+            let response = self.client.request(request).await.ok()?;
+            if !response.status().is_success() {
+                return None;
+            }
+            let body = hyper::body::to_bytes(response.into_body()).await.ok()?;
+            serde_json::from_slice::<JRPCResponse>(&body).ok()
+        };
+
+        match tokio::time::timeout(self.ping_timeout, ping).await {
+            Ok(Some(response)) => response.result.is_some(),
+            Ok(None) => false,
+            Err(_) => {
+                log::warn!("Jsonrpc server did not answer the ping in time");
+                false
+            }
+        }
+    }
+
     fn f_err<T: std::fmt::Display>(msg: &str, err: &T) -> router::RouterError {
         let msg = format!("{}: [{}]", msg, err);
         log::warn!("{}", msg);
@@ -200,6 +501,22 @@ impl JsonrpcHandler {
         let msg = format!("{}: [{}]", msg, err);
         router::InvalidRequest(msg)
     }
+
+    fn allow_header(&self) -> String {
+        self.allowed_methods
+            .iter()
+            .map(hyper::Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Answer sent to the notifications, which get no jsonrpc response
+    fn notification_response() -> hyper::Response<hyper::Body> {
+        hyper::Response::builder()
+            .status(204)
+            .body(hyper::Body::empty())
+            .unwrap()
+    }
 }
 
 #[async_trait::async_trait]
@@ -208,11 +525,65 @@ impl router::Handler for JsonrpcHandler {
         &self.matcher
     }
 
+    fn allowed_methods(&self) -> Option<&[hyper::Method]> {
+        Some(&self.allowed_methods)
+    }
+
     async fn handle(
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, router::RouterError> {
-        let (parts, body) = request.into_parts();
+        let (mut parts, body) = request.into_parts();
+
+        // the preflight requests of the allowed origins are answered by the router, with the
+        // methods below in the Allow header; the other OPTIONS are not forwarded either
+        if parts.method == hyper::Method::OPTIONS {
+            return Ok(hyper::Response::builder()
+                .status(204)
+                .header(hyper::header::ALLOW, self.allow_header())
+                .body(hyper::Body::empty())
+                .unwrap());
+        }
+
+        if let Some((name, value)) = &self.required_header {
+            if parts.headers.get(name) != Some(value) {
+                return Err(router::HandlerError(
+                    401,
+                    String::from("Missing or invalid secret header"),
+                ));
+            }
+            parts.headers.remove(name);
+        }
+
+        if self.describe_on_get && parts.method == hyper::Method::GET {
+            let mut methods: Vec<&String> = self.overloaders.keys().collect();
+            methods.sort();
+            return Ok(hyper::Response::builder()
+                .status(200)
+                .header("content-type", "application/json")
+                .body(hyper::Body::from(
+                    serde_json::json!({ "service": "jsonrpc", "methods": methods }).to_string(),
+                ))
+                .unwrap());
+        }
+
+        if let Some(max_request_bytes) = self.max_request_bytes {
+            let content_length = parts
+                .headers
+                .get(hyper::header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok());
+            if let Some(content_length) = content_length.filter(|&l| l > max_request_bytes) {
+                return Err(router::HandlerError(
+                    413,
+                    format!(
+                        "Jsonrpc request of {} bytes is bigger than the {} bytes allowed",
+                        content_length, max_request_bytes
+                    ),
+                ));
+            }
+        }
+
         let body = hyper::body::to_bytes(body)
             .await
             .map_err(|e| JsonrpcHandler::h_err("Could not read body of jsonrpc request", &e))?;
@@ -220,35 +591,91 @@ impl router::Handler for JsonrpcHandler {
         let body_str = String::from_utf8(body.to_vec())
             .map_err(|e| JsonrpcHandler::h_err("Jsonrpc request body is not valid utf-8", &e))?;
 
-        if &parts.method == hyper::Method::POST {
-            let json: JRPCQuery = serde_json::from_str(body_str.as_str())
+        let mut body = body;
+        // the errors are only sent as jsonrpc error objects to the jsonrpc queries
+        let is_query = &parts.method == hyper::Method::POST;
+        let mut id = None;
+        // the notifications do not expect any response, so theirs is dropped
+        let mut is_notification = false;
+
+        if is_query {
+            let mut json: JRPCQuery = serde_json::from_str(body_str.as_str())
                 .map_err(|e| JsonrpcHandler::h_err("Jsonrpc request body is not valid json", &e))?;
 
+            if self.require_version && json.jsonrpc.as_deref() != Some("2.0") {
+                return Err(router::JsonError(
+                    400,
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "error": { "code": -32600, "message": "Invalid Request" },
+                        "id": json.id(),
+                    })
+                    .to_string(),
+                ));
+            }
+
+            id = json.id();
+            is_notification = id.is_none();
+
+            if let Some(method) = self.method_aliases.get(json.method()) {
+                log::info!("Renaming method '{}' to '{}'", json.method(), method);
+                json.method = method.to_owned();
+                body = hyper::body::Bytes::from(serde_json::to_string(&json).unwrap());
+            }
+
             if let Some(overloader) = self.overloaders.get(json.method()) {
                 log::info!("Overloading method '{}'", json.method());
-                if json.params().is_none() {
-                    return Err(JsonrpcHandler::h_err(
-                        "Jsonrpc request did not contain any parameter",
-                        json.method(),
-                    ));
+                if overloader.requires_params() && json.params().is_none() {
+                    if is_notification {
+                        log::warn!("Overloaded notification did not contain any parameter");
+                        return Ok(JsonrpcHandler::notification_response());
+                    }
+                    return Ok(JRPCResponse::from_router_error(
+                        &JsonrpcHandler::h_err(
+                            "Jsonrpc request did not contain any parameter",
+                            json.method(),
+                        ),
+                        id,
+                    )
+                    .into_http_response());
                 }
                 // TODO improve this with better error handling
                 // TODO improve deserialization
-                return overloader.handle(parts, json, self).await.map(|response| {
-                    hyper::Response::builder()
-                        .status(200)
-                        .header("content-type", "application/json")
-                        .body(hyper::Body::from(serde_json::to_string(&response).unwrap()))
-                        .unwrap()
-                });
+                // the overloaders may query the receiver, which can forward some of the headers
+                let headers = parts.headers.clone();
+                let response = crate::avreceiver::with_request_headers(
+                    headers,
+                    overloader.handle(parts, json, self),
+                )
+                .await;
+                if is_notification {
+                    if let Err(error) = response {
+                        log::warn!("Overloaded notification failed: {:?}", error);
+                    }
+                    return Ok(JsonrpcHandler::notification_response());
+                }
+                let response =
+                    response.unwrap_or_else(|error| JRPCResponse::from_router_error(&error, id));
+                return Ok(response.into_http_response());
             }
         }
         // when in doubt, forward
-        self.forward(parts, body).await
-    }
-
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(10)
+        match self.forward(parts, body).await {
+            Ok(_) if is_notification => Ok(JsonrpcHandler::notification_response()),
+            Err(router::ForwardingError(message)) if is_notification => {
+                log::warn!("Forwarded notification failed: {}", message);
+                Ok(JsonrpcHandler::notification_response())
+            }
+            Ok(response) if is_query && response.status() == hyper::StatusCode::NOT_FOUND => Ok(
+                JRPCResponse::error(JRPCError::METHOD_NOT_FOUND, "Method not found", id)
+                    .into_http_response(),
+            ),
+            // the circuit breaker keeps its 503 so that the clients back off
+            Err(error @ router::ForwardingError(_)) if is_query => {
+                Ok(JRPCResponse::from_router_error(&error, id).into_http_response())
+            }
+            response => response,
+        }
     }
 }
 
@@ -260,6 +687,22 @@ mod tests {
 
     struct MockOverloader {}
 
+    struct FailingOverloader {}
+
+    #[async_trait::async_trait]
+    impl JsonrpcOverloader for FailingOverloader {
+        async fn handle(
+            &self,
+            _parts: hyper::http::request::Parts,
+            _body: crate::handlers::jsonrpc::JRPCQuery,
+            _handler: &super::JsonrpcHandler,
+        ) -> Result<super::JRPCResponse, router::RouterError> {
+            Err(router::InvalidRequest(String::from(
+                "Invalid volume parameter",
+            )))
+        }
+    }
+
     #[async_trait::async_trait]
     impl JsonrpcOverloader for MockOverloader {
         async fn handle(
@@ -289,7 +732,7 @@ mod tests {
         let req = hyper::Request::builder()
             .uri("/jsonrpc")
             .method("POST")
-            .body(hyper::Body::from(r#"{"method":"Not.Found"}"#))
+            .body(hyper::Body::from(r#"{"method":"Not.Found","id":1}"#))
             .unwrap();
 
         let (parts, body) = jrpc.handle(req).await.unwrap().into_parts();
@@ -301,6 +744,188 @@ mod tests {
         assert_eq!("a post body", body);
     }
 
+    #[test(tokio::test)]
+    async fn it_sends_the_errors_as_jsonrpc_error_objects() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(wiremock::ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .add_overloader("Application.SetVolume", Box::new(FailingOverloader {}))
+            .build();
+
+        let get_body = |body: &'static str| {
+            let jrpc = &jrpc;
+            async move {
+                let req = hyper::Request::builder()
+                    .uri("/jsonrpc")
+                    .method("POST")
+                    .body(hyper::Body::from(body))
+                    .unwrap();
+                let (parts, body) = jrpc.handle(req).await.unwrap().into_parts();
+                assert_eq!(200, parts.status);
+                serde_json::from_slice::<serde_json::Value>(
+                    &hyper::body::to_bytes(body).await.unwrap(),
+                )
+                .unwrap()
+            }
+        };
+
+        assert_eq!(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32602, "message": "Invalid volume parameter" },
+                "id": 7,
+            }),
+            get_body(r#"{"method":"Application.SetVolume","params":{"volume":"loud"},"id":7}"#)
+                .await
+        );
+        assert_eq!(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32601, "message": "Method not found" },
+                "id": 8,
+            }),
+            get_body(r#"{"method":"Not.Found","id":8}"#).await
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn it_reuses_its_client_for_the_forwards() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::path("/jsonrpc"))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200)
+                    .set_body_bytes(r#"{"jsonrpc":"2.0","result":"OK","id":1}"#),
+            )
+            .expect(5)
+            .mount(&mock_server)
+            .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .build();
+
+        for _ in 0..5 {
+            let req = hyper::Request::builder()
+                .uri("/jsonrpc")
+                .method("POST")
+                .body(hyper::Body::from(r#"{"method":"A.Method","id":1}"#))
+                .unwrap();
+            let (parts, body) = jrpc.handle(req).await.unwrap().into_parts();
+
+            assert_eq!(200, parts.status);
+            assert_eq!(
+                r#"{"jsonrpc":"2.0","result":"OK","id":1}"#,
+                hyper::body::to_bytes(body).await.unwrap()
+            );
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_times_out_the_slow_upstreams() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::path("/jsonrpc"))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200)
+                    .set_delay(std::time::Duration::from_millis(500))
+                    .set_body_bytes(r#"{"jsonrpc":"2.0","result":"OK","id":1}"#),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .with_upstream_timeout(std::time::Duration::from_millis(100))
+            .build();
+
+        let (parts, _) = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("POST")
+            .body(())
+            .unwrap()
+            .into_parts();
+        let body = hyper::body::Bytes::from(r#"{"method":"A.Method","id":1}"#);
+
+        let start = std::time::Instant::now();
+        let error = jrpc.forward(parts, body).await.unwrap_err();
+
+        assert_eq!(
+            router::ForwardingError(String::from("upstream timed out")),
+            error
+        );
+        assert!(start.elapsed() < std::time::Duration::from_millis(400));
+    }
+
+    #[test(tokio::test)]
+    async fn it_answers_options_without_forwarding() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::any())
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let mut router = router::Router::new();
+        router
+            .set_cors(Some(router::CorsConfig {
+                allowed_origins: vec![String::from("https://ui.example.com")],
+                allowed_methods: vec![hyper::Method::POST],
+                allowed_headers: vec![String::from("content-type")],
+                max_age: None,
+            }))
+            .add_handler(
+                crate::handlers::jsonrpc::JsonrpcHandler::builder()
+                    .with_url(&mock_server.uri())
+                    .with_required_header(Some((String::from("x-secret"), String::from("s3cr3t"))))
+                    .with_allowed_methods(vec![hyper::Method::POST, hyper::Method::OPTIONS])
+                    .build(),
+            );
+
+        let preflight = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("OPTIONS")
+            .header("origin", "https://ui.example.com")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type, x-secret")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        let response = router.handle(preflight).await.unwrap();
+        let headers = response.headers();
+
+        assert_eq!(204, response.status());
+        assert_eq!("POST, OPTIONS", headers["allow"]);
+        assert_eq!("POST", headers["access-control-allow-methods"]);
+        assert_eq!("content-type", headers["access-control-allow-headers"]);
+        assert_eq!(
+            "https://ui.example.com",
+            headers["access-control-allow-origin"]
+        );
+
+        let options = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("OPTIONS")
+            .body(hyper::Body::empty())
+            .unwrap();
+
+        let response = router.handle(options).await.unwrap();
+        let headers = response.headers();
+
+        assert_eq!(204, response.status());
+        assert_eq!("POST, OPTIONS", headers["allow"]);
+        assert!(!headers.contains_key("access-control-allow-headers"));
+    }
+
     #[test(tokio::test)]
     async fn it_returns_errors() {
         let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder().build();
@@ -331,7 +956,7 @@ mod tests {
             .uri("/jsonrpc")
             .method("POST")
             .body(hyper::Body::from(
-                r#"{"method":"A.Method","params":{"akey":"a value"}}"#,
+                r#"{"method":"A.Method","params":{"akey":"a value"},"id":1}"#,
             ))
             .unwrap();
 
@@ -383,4 +1008,340 @@ mod tests {
 
         assert_eq!(serde_json::json!({"res":"a result"}), res);
     }
+
+    #[test(tokio::test)]
+    async fn it_opens_the_circuit_breaker() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(wiremock::ResponseTemplate::new(500))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .with_circuit_breaker(
+                2,
+                std::time::Duration::from_secs(60),
+                std::time::Duration::from_millis(200),
+            )
+            .build();
+
+        let get_request = || {
+            hyper::Request::builder()
+                .uri("/jsonrpc")
+                .method("POST")
+                .body(hyper::Body::from(r#"{"method":"Not.Found","id":1}"#))
+                .unwrap()
+        };
+
+        for _ in 0..2 {
+            assert_eq!(500, jrpc.handle(get_request()).await.unwrap().status());
+        }
+
+        let error = jrpc.handle(get_request()).await.unwrap_err();
+        assert_eq!(
+            router::HandlerError(503, String::from("Jsonrpc server unavailable")),
+            error
+        );
+
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+
+        for _ in 0..2 {
+            assert_eq!(200, jrpc.handle(get_request()).await.unwrap().status());
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_renames_aliased_methods() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::body_string(
+                r#"{"jsonrpc":"2.0","method":"Player.GetActivePlayers","params":null,"id":1}"#,
+            ))
+            .respond_with(wiremock::ResponseTemplate::new(200).set_body_bytes("forwarded"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .with_method_aliases(&std::collections::HashMap::from([
+                (
+                    String::from("Custom.Volume"),
+                    String::from("Application.SetVolume"),
+                ),
+                (
+                    String::from("Custom.Players"),
+                    String::from("Player.GetActivePlayers"),
+                ),
+            ]))
+            .add_overloader("Application.SetVolume", Box::from(MockOverloader {}))
+            .build();
+
+        let req = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("POST")
+            .body(hyper::Body::from(
+                r#"{"jsonrpc":"2.0","method":"Custom.Volume","params":{"volume":12},"id":1}"#,
+            ))
+            .unwrap();
+
+        let body = jrpc.handle(req).await.unwrap().into_body();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+
+        assert_eq!(r#"{"jsonrpc":"2.0","result":null,"id":1}"#, body);
+
+        let req = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("POST")
+            .body(hyper::Body::from(
+                r#"{"jsonrpc":"2.0","method":"Custom.Players","id":1}"#,
+            ))
+            .unwrap();
+
+        let body = jrpc.handle(req).await.unwrap().into_body();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+
+        assert_eq!("forwarded", body);
+    }
+
+    #[test(tokio::test)]
+    async fn it_requires_the_secret_header() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(wiremock::ResponseTemplate::new(200).set_body_bytes("a post body"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .with_required_header(Some((
+                String::from("X-Proxy-Secret"),
+                String::from("s3cr3t"),
+            )))
+            .add_overloader("A.Method", Box::from(MockOverloader {}))
+            .build();
+
+        for (method, secret, expected_status) in [
+            ("Not.Found", Some("s3cr3t"), 200),
+            ("A.Method", Some("s3cr3t"), 200),
+            ("Not.Found", None, 401),
+            ("A.Method", None, 401),
+            ("Not.Found", Some("wrong"), 401),
+            ("A.Method", Some("wrong"), 401),
+        ] {
+            let mut builder = hyper::Request::builder().uri("/jsonrpc").method("POST");
+            if let Some(secret) = secret {
+                builder = builder.header("x-proxy-secret", secret);
+            }
+            let req = builder
+                .body(hyper::Body::from(format!(
+                    r#"{{"method":"{}","params":{{}},"id":1}}"#,
+                    method
+                )))
+                .unwrap();
+
+            let status = match jrpc.handle(req).await {
+                Ok(response) => response.status().as_u16(),
+                Err(router::RouterError::HandlerError(status, _)) => status,
+                Err(e) => panic!("Unexpected error {:?}", e),
+            };
+
+            assert_eq!(expected_status, status, "for {} with {:?}", method, secret);
+        }
+
+        let requests = mock_server.received_requests().await.unwrap();
+        assert!(!requests[0]
+            .headers
+            .iter()
+            .any(|(name, _)| name.as_str() == "x-proxy-secret"));
+    }
+
+    #[test(tokio::test)]
+    async fn it_pings_a_reachable_upstream() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::body_partial_json(serde_json::json!({
+            "method": "JSONRPC.Ping"
+        })))
+        .respond_with(
+            wiremock::ResponseTemplate::new(200)
+                .set_body_bytes(r#"{"id":1,"jsonrpc":"2.0","result":"pong"}"#),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .build();
+
+        assert!(jrpc.ping_upstream().await);
+    }
+
+    #[test(tokio::test)]
+    async fn it_does_not_wait_for_an_unreachable_upstream() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200)
+                    .set_body_bytes(r#"{"id":1,"jsonrpc":"2.0","result":"pong"}"#)
+                    .set_delay(std::time::Duration::from_secs(5)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .with_ping_timeout(std::time::Duration::from_millis(100))
+            .build();
+
+        let start = std::time::Instant::now();
+
+        assert!(!jrpc.ping_upstream().await);
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+
+        let unreachable = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&String::from("http://127.0.0.1:1"))
+            .build();
+
+        assert!(!unreachable.ping_upstream().await);
+    }
+
+    #[test(tokio::test)]
+    async fn it_requires_the_jsonrpc_version() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200)
+                    .set_body_bytes(r#"{"id":3,"jsonrpc":"2.0","result":"pong"}"#),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .with_require_version(true)
+            .build();
+
+        let get_request = |body: &'static str| {
+            hyper::Request::builder()
+                .uri("/jsonrpc")
+                .method("POST")
+                .body(hyper::Body::from(body))
+                .unwrap()
+        };
+
+        let response = jrpc
+            .handle(get_request(
+                r#"{"jsonrpc":"2.0","method":"JSONRPC.Ping","id":3}"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+
+        let error = jrpc
+            .handle(get_request(r#"{"method":"JSONRPC.Ping","id":4}"#))
+            .await
+            .unwrap_err();
+
+        match error {
+            router::RouterError::JsonError(400, body) => assert_eq!(
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32600, "message": "Invalid Request" },
+                    "id": 4,
+                }),
+                serde_json::from_str::<serde_json::Value>(&body).unwrap()
+            ),
+            error => panic!("Unexpected error {:?}", error),
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn it_refuses_the_requests_declaring_a_too_big_body() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .with_max_request_bytes(Some(1024))
+            .build();
+
+        // the body is never sent, so the handler cannot have read it
+        let (_sender, body) = hyper::Body::channel();
+        let req = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("POST")
+            .header("content-length", "1048576")
+            .body(body)
+            .unwrap();
+
+        let error = tokio::time::timeout(std::time::Duration::from_secs(1), jrpc.handle(req))
+            .await
+            .expect("the request should be refused without reading the body")
+            .unwrap_err();
+
+        assert!(matches!(error, router::RouterError::HandlerError(413, _)));
+    }
+
+    #[test(tokio::test)]
+    async fn it_describes_the_overloaders_on_get() {
+        let jrpc = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_describe_on_get(true)
+            .add_overloader("Player.Open", Box::new(MockOverloader {}))
+            .add_overloader("Application.SetVolume", Box::new(MockOverloader {}))
+            .build();
+
+        let req = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("GET")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let body = hyper::body::to_bytes(jrpc.handle(req).await.unwrap().into_body())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            serde_json::json!({
+                "service": "jsonrpc",
+                "methods": ["Application.SetVolume", "Player.Open"],
+            }),
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        );
+
+        let req = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("POST")
+            .body(hyper::Body::from(
+                r#"{"method":"Player.Open","params":{},"id":1}"#,
+            ))
+            .unwrap();
+        let body = hyper::body::to_bytes(jrpc.handle(req).await.unwrap().into_body())
+            .await
+            .unwrap();
+
+        assert_eq!(r#"{"jsonrpc":"2.0","result":null,"id":1}"#, body);
+    }
 }
@@ -1,38 +1,224 @@
-use self::jsonrpc::{JRPCQuery, JRPCResponse, JsonrpcHandler, JsonrpcOverloader};
+pub use self::jsonrpc::JsonrpcHandler;
+use self::jsonrpc::{JRPCQuery, JRPCResponse, JsonrpcOverloader};
+use self::pingoverloaders::*;
 use self::poweroverloaders::*;
+use self::settingsoverloaders::*;
 use self::volumeoverloaders::*;
 
+mod circuitbreaker;
 mod jsonrpc;
+mod pingoverloaders;
 mod poweroverloaders;
+mod settingsoverloaders;
 mod volumeoverloaders;
 
+/// Builds the jsonrpc handler, with all the known overloaders except the disabled ones
+///
+/// Returns an error if the configuration disables a method that is not overloaded.
 pub fn get_jrpc_handler(
     configuration: &crate::configuration::JRPCConfiguration,
     avreceiver: std::sync::Arc<dyn crate::avreceiver::AVReceiverInterface>,
     cec_interface: std::sync::Arc<std::sync::Mutex<dyn crate::cec::CECInterface>>,
-) -> Box<dyn router::Handler> {
-    let mut builder = jsonrpc::JsonrpcHandler::builder()
-        .with_url(&configuration.target)
-        .add_overloader(
+    upstream_tally: std::sync::Arc<router::debug::UpstreamTally>,
+) -> Result<Box<dyn router::Handler>, String> {
+    let mut overloaders: Vec<(&str, Box<dyn JsonrpcOverloader>)> = vec![
+        (
             "Application.SetVolume",
             JRPCSetVolume::new(avreceiver.clone()),
-        )
-        .add_overloader("Application.SetMute", JRPCSetMute::new(avreceiver.clone()))
-        .add_overloader(
+        ),
+        ("Application.SetMute", JRPCSetMute::new(avreceiver.clone())),
+        (
             "Application.GetProperties",
-            JRPCGetProperties::new(avreceiver.clone()),
-        )
-        .add_overloader("System.GetProperties", JRPCGetSystemProperties::new());
+            JRPCGetProperties::new(avreceiver.clone(), cec_interface.clone()),
+        ),
+        ("System.GetProperties", JRPCGetSystemProperties::new()),
+        (
+            "Settings.GetSettingValue",
+            JRPCGetSettingValue::new(avreceiver.clone()),
+        ),
+        (
+            "Settings.SetSettingValue",
+            JRPCSetSettingValue::new(avreceiver.clone()),
+        ),
+    ];
     for method in [
         "Application.Quit",
         "System.Hibernate",
         "System.Shutdown",
         "System.Suspend",
     ] {
-        builder = builder.add_overloader(
+        overloaders.push((
             method,
             JRPCShutdown::new(avreceiver.clone(), cec_interface.clone()),
+        ));
+    }
+
+    if configuration.ping_with_version {
+        overloaders.push(("JSONRPC.Ping", JRPCPing::new()));
+    }
+
+    for method in &configuration.disabled_overloaders {
+        if !overloaders.iter().any(|(name, _)| name == method) {
+            return Err(format!("Unknown overloader {} cannot be disabled", method));
+        }
+    }
+
+    let allowed_methods = configuration
+        .allowed_methods
+        .iter()
+        .map(|method| {
+            hyper::Method::from_bytes(method.as_bytes())
+                .map_err(|_| format!("Incorrect allowed method in jrpc configuration: {}", method))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut builder = jsonrpc::JsonrpcHandler::builder()
+        .with_url(&configuration.target)
+        .with_allowed_methods(allowed_methods)
+        .with_upstream_tally(upstream_tally)
+        .with_method_aliases(&configuration.method_aliases)
+        .with_required_header(configuration.require_header.to_owned())
+        .with_require_version(configuration.require_version)
+        .with_max_request_bytes(configuration.max_request_bytes)
+        .with_describe_on_get(configuration.describe_on_get)
+        .with_circuit_breaker(
+            configuration.circuit_breaker.failure_threshold,
+            std::time::Duration::from_secs(configuration.circuit_breaker.window_seconds),
+            std::time::Duration::from_secs(configuration.circuit_breaker.cooldown_seconds),
         );
+    if let Some(timeout) = configuration.upstream_timeout_ms {
+        builder = builder.with_upstream_timeout(std::time::Duration::from_millis(timeout));
+    }
+    for (method, overloader) in overloaders {
+        if configuration
+            .disabled_overloaders
+            .iter()
+            .any(|disabled| disabled == method)
+        {
+            log::info!("Overloader of {} disabled, it will be forwarded", method);
+        } else {
+            builder = builder.add_overloader(method, overloader);
+        }
+    }
+    Ok(builder.build())
+}
+
+/// Builds the handler used by /health to ping kodi, if it is configured
+pub fn get_upstream_pinger(
+    configuration: &crate::configuration::JRPCConfiguration,
+) -> Option<Box<JsonrpcHandler>> {
+    configuration.health_ping_timeout_ms.map(|timeout| {
+        JsonrpcHandler::builder()
+            .with_url(&configuration.target)
+            .with_ping_timeout(std::time::Duration::from_millis(timeout))
+            .build()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    fn get_handler(
+        configuration: &crate::configuration::JRPCConfiguration,
+        avreceiver: crate::avreceiver::MockAVReceiver,
+    ) -> Result<Box<dyn router::Handler>, String> {
+        let cec_interface: std::sync::Arc<std::sync::Mutex<dyn crate::cec::CECInterface>> =
+            std::sync::Arc::new(std::sync::Mutex::new(crate::cec::MockCECInterface::new()));
+        super::get_jrpc_handler(
+            configuration,
+            std::sync::Arc::new(avreceiver),
+            cec_interface,
+            std::sync::Arc::new(router::debug::UpstreamTally::default()),
+        )
+    }
+
+    #[test(tokio::test)]
+    async fn it_forwards_the_disabled_overloaders() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::body_partial_json(serde_json::json!({
+            "method": "Application.SetVolume"
+        })))
+        .respond_with(
+            wiremock::ResponseTemplate::new(200)
+                .set_body_bytes(r#"{"id":1,"jsonrpc":"2.0","result":50}"#),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let configuration = crate::configuration::JRPCConfiguration {
+            disabled_overloaders: vec![String::from("Application.SetVolume")],
+            target: mock_server.uri(),
+            ..Default::default()
+        };
+        // the mock receiver has no expectation and panics if the overloader is called
+        let handler =
+            get_handler(&configuration, crate::avreceiver::MockAVReceiver::new()).unwrap();
+
+        let request = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("POST")
+            .body(hyper::Body::from(
+                r#"{"id":1,"jsonrpc":"2.0","method":"Application.SetVolume","params":{"volume":50}}"#,
+            ))
+            .unwrap();
+        let response = handler.handle(request).await.unwrap();
+
+        assert_eq!(200, response.status());
+    }
+
+    #[test(tokio::test)]
+    async fn it_answers_the_notifications_with_no_content() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::body_partial_json(serde_json::json!({
+            "method": "Player.Stop"
+        })))
+        .respond_with(
+            wiremock::ResponseTemplate::new(200)
+                .set_body_bytes(r#"{"jsonrpc":"2.0","result":"OK"}"#),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let configuration = crate::configuration::JRPCConfiguration {
+            target: mock_server.uri(),
+            ..Default::default()
+        };
+        let mut avreceiver = crate::avreceiver::MockAVReceiver::new();
+        avreceiver
+            .expect_set_mute()
+            .with(mockall::predicate::eq(true))
+            .times(1)
+            .returning(|_| true);
+        let handler = get_handler(&configuration, avreceiver).unwrap();
+
+        for body in [
+            r#"{"jsonrpc":"2.0","method":"Application.SetMute","params":{"mute":true}}"#,
+            r#"{"jsonrpc":"2.0","method":"Player.Stop","params":{"playerid":1}}"#,
+        ] {
+            let request = hyper::Request::builder()
+                .uri("/jsonrpc")
+                .method("POST")
+                .body(hyper::Body::from(body))
+                .unwrap();
+            let (parts, body) = handler.handle(request).await.unwrap().into_parts();
+
+            assert_eq!(204, parts.status);
+            assert!(hyper::body::to_bytes(body).await.unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn it_refuses_to_disable_unknown_overloaders() {
+        let configuration = crate::configuration::JRPCConfiguration {
+            disabled_overloaders: vec![String::from("Application.Unknown")],
+            ..Default::default()
+        };
+
+        assert!(get_handler(&configuration, crate::avreceiver::MockAVReceiver::new()).is_err());
     }
-    builder.build()
 }
@@ -0,0 +1,103 @@
+/// Answers JSONRPC.Ping locally with the version of the proxy, so that the monitoring sees the
+/// proxy itself through the jsonrpc entry point
+pub struct JRPCPing {}
+
+impl JRPCPing {
+    pub fn new() -> Box<dyn crate::handlers::jsonrpc::JsonrpcOverloader> {
+        Box::new(JRPCPing {})
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::handlers::jsonrpc::JsonrpcOverloader for JRPCPing {
+    async fn handle(
+        &self,
+        _parts: hyper::http::request::Parts,
+        json_request: super::jsonrpc::JRPCQuery,
+        _handler: &super::jsonrpc::JsonrpcHandler,
+    ) -> Result<super::jsonrpc::JRPCResponse, router::RouterError> {
+        Ok(crate::handlers::jsonrpc::JRPCResponse::new(
+            Some(serde_json::json!({
+                "pong": true,
+                "proxyVersion": env!("CARGO_PKG_VERSION"),
+            })),
+            json_request.id(),
+        ))
+    }
+
+    fn requires_params(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    #[test(tokio::test)]
+    async fn it_answers_the_ping_with_the_proxy_version() {
+        // no upstream is reachable, the ping must not be forwarded
+        let jrpc_handler = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&String::from("http://127.0.0.1:1/jsonrpc"))
+            .build();
+        let (parts, _) = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        let result = super::JRPCPing::new()
+            .handle(
+                parts,
+                crate::handlers::jsonrpc::JRPCQuery::new(
+                    String::from("JSONRPC.Ping"),
+                    None,
+                    Some(7),
+                ),
+                jrpc_handler.as_ref(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "result": { "pong": true, "proxyVersion": env!("CARGO_PKG_VERSION") },
+                "id": 7
+            }),
+            serde_json::to_value(&result).unwrap()
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn it_answers_the_ping_without_params() {
+        let jrpc_handler = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&String::from("http://127.0.0.1:1/jsonrpc"))
+            .add_overloader("JSONRPC.Ping", super::JRPCPing::new())
+            .build();
+        let request = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("POST")
+            .body(hyper::Body::from(
+                r#"{"jsonrpc":"2.0","method":"JSONRPC.Ping","id":1}"#,
+            ))
+            .unwrap();
+
+        let (parts, body) = router::Handler::handle(jrpc_handler.as_ref(), request)
+            .await
+            .unwrap()
+            .into_parts();
+        let body: serde_json::Value =
+            serde_json::from_slice(&hyper::body::to_bytes(body).await.unwrap()).unwrap();
+
+        assert_eq!(200, parts.status);
+        assert_eq!(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "result": { "pong": true, "proxyVersion": env!("CARGO_PKG_VERSION") },
+                "id": 1
+            }),
+            body
+        );
+    }
+}
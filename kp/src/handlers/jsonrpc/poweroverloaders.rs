@@ -1,3 +1,8 @@
+/// Answers System.GetProperties locally: only rebooting is advertised as possible since the other
+/// power actions are overloaded to switch off the receiver and the TV
+///
+/// The answer only depends on the requested properties and is never forwarded, so it does not
+/// need any caching.
 pub struct JRPCGetSystemProperties {}
 
 pub struct JRPCShutdown {
@@ -167,4 +172,34 @@ mod test {
             .await
             .unwrap();
     }
+
+    #[rstest::rstest]
+    #[test(tokio::test)]
+    async fn it_never_forwards_system_properties() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::any())
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let jrpc_handler = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .build();
+
+        let jrpc = super::JRPCGetSystemProperties::new();
+
+        for _ in 0..2 {
+            let request = crate::handlers::jsonrpc::JRPCQuery::new(
+                String::from("System.GetProperties"),
+                Some(serde_json::json!({ "properties": ["canshutdown"] })),
+                Some(42),
+            );
+
+            jrpc.handle(parts(), request, jrpc_handler.as_ref())
+                .await
+                .unwrap();
+        }
+    }
 }
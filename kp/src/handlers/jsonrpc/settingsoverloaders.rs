@@ -0,0 +1,241 @@
+use crate::avreceiver::AVReceiverInterface;
+
+/// Id of the synthetic setting giving the input of the receiver
+static SETTING_RECEIVER_INPUT: &str = "proxy.receiver.input";
+/// Id of the synthetic setting giving the power status of the receiver
+static SETTING_RECEIVER_POWER: &str = "proxy.receiver.power";
+
+/// Answers Settings.GetSettingValue for the synthetic `proxy.*` settings, the other settings are
+/// forwarded to Kodi
+pub struct JRPCGetSettingValue {
+    receiver: std::sync::Arc<dyn AVReceiverInterface>,
+}
+
+/// Answers Settings.SetSettingValue for the writable synthetic `proxy.*` settings, the other
+/// settings are forwarded to Kodi
+pub struct JRPCSetSettingValue {
+    receiver: std::sync::Arc<dyn AVReceiverInterface>,
+}
+
+fn get_param<'a>(
+    json_request: &'a crate::handlers::jsonrpc::JRPCQuery,
+    name: &str,
+) -> Option<&'a serde_json::Value> {
+    match json_request.params() {
+        Some(serde_json::Value::Object(params)) => params.get(name),
+        _ => None,
+    }
+}
+
+fn get_setting(
+    json_request: &crate::handlers::jsonrpc::JRPCQuery,
+) -> Result<String, router::RouterError> {
+    match get_param(json_request, "setting") {
+        Some(serde_json::Value::String(setting)) => Ok(setting.to_owned()),
+        _ => Err(router::InvalidRequest(String::from(
+            "Invalid setting parameter",
+        ))),
+    }
+}
+
+impl JRPCGetSettingValue {
+    pub fn new(
+        receiver: std::sync::Arc<dyn AVReceiverInterface>,
+    ) -> Box<dyn crate::handlers::jsonrpc::JsonrpcOverloader> {
+        Box::new(JRPCGetSettingValue { receiver })
+    }
+}
+
+impl JRPCSetSettingValue {
+    pub fn new(
+        receiver: std::sync::Arc<dyn AVReceiverInterface>,
+    ) -> Box<dyn crate::handlers::jsonrpc::JsonrpcOverloader> {
+        Box::new(JRPCSetSettingValue { receiver })
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::handlers::jsonrpc::JsonrpcOverloader for JRPCGetSettingValue {
+    async fn handle(
+        &self,
+        parts: hyper::http::request::Parts,
+        json_request: crate::handlers::jsonrpc::JRPCQuery,
+        handler: &crate::handlers::jsonrpc::JsonrpcHandler,
+    ) -> Result<crate::handlers::jsonrpc::JRPCResponse, router::RouterError> {
+        let setting = get_setting(&json_request)?;
+        let value = if setting == SETTING_RECEIVER_INPUT {
+            serde_json::Value::from(self.receiver.get_input().await)
+        } else if setting == SETTING_RECEIVER_POWER {
+            serde_json::Value::from(self.receiver.is_powered_on().await)
+        } else {
+            return handler.forward_jrpc(parts, json_request).await;
+        };
+        Ok(crate::handlers::jsonrpc::JRPCResponse::new(
+            Some(serde_json::json!({ "value": value })),
+            json_request.id(),
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::handlers::jsonrpc::JsonrpcOverloader for JRPCSetSettingValue {
+    async fn handle(
+        &self,
+        parts: hyper::http::request::Parts,
+        json_request: crate::handlers::jsonrpc::JRPCQuery,
+        handler: &crate::handlers::jsonrpc::JsonrpcHandler,
+    ) -> Result<crate::handlers::jsonrpc::JRPCResponse, router::RouterError> {
+        let setting = get_setting(&json_request)?;
+        if setting == SETTING_RECEIVER_INPUT {
+            let input = match get_param(&json_request, "value") {
+                Some(serde_json::Value::String(input)) if !input.is_empty() => input.to_owned(),
+                _ => {
+                    return Err(router::InvalidRequest(String::from(
+                        "Invalid value parameter",
+                    )))
+                }
+            };
+            let result = self.receiver.set_input(input.to_owned()).await;
+            Ok(crate::handlers::jsonrpc::JRPCResponse::new(
+                Some(serde_json::Value::from(result == input)),
+                json_request.id(),
+            ))
+        } else if setting == SETTING_RECEIVER_POWER {
+            Err(router::InvalidRequest(format!(
+                "Setting {} is read-only",
+                setting
+            )))
+        } else {
+            handler.forward_jrpc(parts, json_request).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    #[rstest::fixture]
+    fn parts() -> http::request::Parts {
+        let (parts, _) = hyper::Request::builder()
+            .uri("https://localhost:8080/jsonrpc")
+            .body(hyper::Body::empty())
+            .unwrap()
+            .into_parts();
+        parts
+    }
+
+    fn get_query(method: &str, params: serde_json::Value) -> crate::handlers::jsonrpc::JRPCQuery {
+        crate::handlers::jsonrpc::JRPCQuery::new(String::from(method), Some(params), Some(42))
+    }
+
+    #[rstest::rstest]
+    #[test(tokio::test)]
+    async fn it_reads_the_receiver_input(parts: http::request::Parts) {
+        let jrpc_handler = crate::handlers::jsonrpc::JsonrpcHandler::builder().build();
+
+        let mut mock_receiver = crate::avreceiver::MockAVReceiver::new();
+        mock_receiver
+            .expect_get_input()
+            .times(1)
+            .returning(|| String::from("AUXB"));
+
+        let jrpc = super::JRPCGetSettingValue::new(std::sync::Arc::new(mock_receiver));
+
+        let result = jrpc
+            .handle(
+                parts,
+                get_query(
+                    "Settings.GetSettingValue",
+                    serde_json::json!({ "setting": "proxy.receiver.input" }),
+                ),
+                jrpc_handler.as_ref(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            &Some(serde_json::json!({ "value": "AUXB" })),
+            result.result()
+        );
+    }
+
+    #[rstest::rstest]
+    #[test(tokio::test)]
+    async fn it_writes_the_receiver_input(parts: http::request::Parts) {
+        let jrpc_handler = crate::handlers::jsonrpc::JsonrpcHandler::builder().build();
+
+        let mut mock_receiver = crate::avreceiver::MockAVReceiver::new();
+        mock_receiver
+            .expect_set_input()
+            .with(mockall::predicate::eq(String::from("NET")))
+            .times(1)
+            .returning(|input| input);
+
+        let jrpc = super::JRPCSetSettingValue::new(std::sync::Arc::new(mock_receiver));
+
+        let result = jrpc
+            .handle(
+                parts,
+                get_query(
+                    "Settings.SetSettingValue",
+                    serde_json::json!({ "setting": "proxy.receiver.input", "value": "NET" }),
+                ),
+                jrpc_handler.as_ref(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(&Some(serde_json::json!(true)), result.result());
+    }
+
+    #[test(tokio::test)]
+    async fn it_forwards_unknown_settings() {
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::body_partial_json(serde_json::json!({
+            "params": { "setting": "audiooutput.volumesteps" }
+        })))
+        .respond_with(
+            wiremock::ResponseTemplate::new(200)
+                .set_body_bytes(r#"{"id":42,"jsonrpc":"2.0","result":{"value":90}}"#),
+        )
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+        let jrpc_handler = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .build();
+
+        let mock_receiver = std::sync::Arc::new(crate::avreceiver::MockAVReceiver::new());
+        let get_jrpc = super::JRPCGetSettingValue::new(mock_receiver.clone());
+        let set_jrpc = super::JRPCSetSettingValue::new(mock_receiver);
+
+        let result = get_jrpc
+            .handle(
+                parts(),
+                get_query(
+                    "Settings.GetSettingValue",
+                    serde_json::json!({ "setting": "audiooutput.volumesteps" }),
+                ),
+                jrpc_handler.as_ref(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(&Some(serde_json::json!({ "value": 90 })), result.result());
+
+        set_jrpc
+            .handle(
+                parts(),
+                get_query(
+                    "Settings.SetSettingValue",
+                    serde_json::json!({ "setting": "audiooutput.volumesteps", "value": 50 }),
+                ),
+                jrpc_handler.as_ref(),
+            )
+            .await
+            .unwrap();
+    }
+}
@@ -8,8 +8,12 @@ pub struct JRPCSetMute {
     receiver: std::sync::Arc<dyn AVReceiverInterface>,
 }
 
+/// Answers Application.GetProperties, with the volume properties given by the receiver and a
+/// `power` property giving the power state of the TV through CEC. The other properties are
+/// forwarded to Kodi
 pub struct JRPCGetProperties {
     receiver: std::sync::Arc<dyn AVReceiverInterface>,
+    cec_interface: std::sync::Arc<std::sync::Mutex<dyn crate::cec::CECInterface>>,
 }
 
 impl JRPCSetVolume {
@@ -145,14 +149,41 @@ impl crate::handlers::jsonrpc::JsonrpcOverloader for JRPCSetMute {
 impl JRPCGetProperties {
     pub fn new(
         receiver: std::sync::Arc<dyn AVReceiverInterface>,
+        cec_interface: std::sync::Arc<std::sync::Mutex<dyn crate::cec::CECInterface>>,
     ) -> Box<dyn crate::handlers::jsonrpc::JsonrpcOverloader> {
-        Box::new(JRPCGetProperties { receiver })
+        Box::new(JRPCGetProperties {
+            receiver,
+            cec_interface,
+        })
     }
 
     fn is_volume_property(param: &String) -> bool {
         return param == "muted" || param == "volume";
     }
 
+    /// Whether the TV is on or switching on, null if its state cannot be known
+    async fn get_power_property(&self) -> serde_json::Value {
+        let interface = self.cec_interface.clone();
+        let status = tokio::task::spawn_blocking(move || {
+            interface
+                .lock()
+                .ok()
+                .map(|mut e| e.get_power_status(crate::cec::CECLogicalAddress::TV))
+        })
+        .await;
+        match status {
+            Ok(Some(Ok(crate::cec::CECPowerStatus::On)))
+            | Ok(Some(Ok(crate::cec::CECPowerStatus::InTransitionStandbyToOn))) => {
+                serde_json::Value::from(true)
+            }
+            Ok(Some(Ok(crate::cec::CECPowerStatus::Standby)))
+            | Ok(Some(Ok(crate::cec::CECPowerStatus::InTransitionOnToStandby))) => {
+                serde_json::Value::from(false)
+            }
+            _ => serde_json::Value::Null,
+        }
+    }
+
     async fn get_volume_properties(
         &self,
         volume_properties: &Vec<String>,
@@ -164,7 +195,11 @@ impl JRPCGetProperties {
             let mut res = serde_json::Map::<String, serde_json::Value>::new();
             for param in volume_properties {
                 if param == "volume" {
-                    res.insert(param.to_owned(), serde_json::Value::from(volume));
+                    // kodi expects a number, so the turned down volume is sent as 0
+                    res.insert(
+                        param.to_owned(),
+                        serde_json::Value::from(volume.unwrap_or(0)),
+                    );
                 } else if param == "muted" {
                     res.insert(param.to_owned(), serde_json::Value::from(mute));
                 }
@@ -214,11 +249,14 @@ impl crate::handlers::jsonrpc::JsonrpcOverloader for JRPCGetProperties {
             if let Some(serde_json::Value::Array(properties)) = params.get("properties") {
                 let mut volume_properties = Vec::<String>::new();
                 let mut other_properties = Vec::<String>::new();
+                let mut with_power = false;
 
                 for param in properties {
                     match param {
                         serde_json::Value::String(param) => {
-                            if JRPCGetProperties::is_volume_property(param) {
+                            if param == "power" {
+                                with_power = true;
+                            } else if JRPCGetProperties::is_volume_property(param) {
                                 volume_properties.push(param.to_owned());
                             } else {
                                 other_properties.push(param.to_owned());
@@ -228,18 +266,29 @@ impl crate::handlers::jsonrpc::JsonrpcOverloader for JRPCGetProperties {
                     }
                 }
 
-                let (volume_props, other_props) = futures::join!(
+                let (volume_props, other_props, power) = futures::join!(
                     self.get_volume_properties(&volume_properties),
                     JRPCGetProperties::get_other_properties(
                         parts,
                         &json_request,
                         handler,
                         other_properties
-                    )
+                    ),
+                    async {
+                        if with_power {
+                            Some(self.get_power_property().await)
+                        } else {
+                            None
+                        }
+                    }
                 );
 
                 let mut other_props = other_props?;
 
+                if let Some(power) = power {
+                    other_props.insert(String::from("power"), power);
+                }
+
                 if let Some(properties) = volume_props {
                     for (key, value) in properties {
                         other_props.insert(key, value);
@@ -392,7 +441,9 @@ mod tests {
             .with(mockall::predicate::eq(false))
             .times(2)
             .returning(|_| false);
-        mock.expect_get_volume().times(1).returning(|| (40, true));
+        mock.expect_get_volume()
+            .times(1)
+            .returning(|| (Some(40), true));
         let mock = std::sync::Arc::new(mock);
         let jrpc = super::JRPCSetMute::new(mock);
 
@@ -448,7 +499,7 @@ mod tests {
         mock_receiver
             .expect_get_volume()
             .times(1)
-            .returning(|| (42, false));
+            .returning(|| (Some(42), false));
         let mock_receiver = std::sync::Arc::new(mock_receiver);
 
         let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
@@ -470,7 +521,11 @@ mod tests {
             .with_url(&mock_server.uri())
             .build();
 
-        let jrpc = super::JRPCGetProperties::new(mock_receiver);
+        let mock_cec = crate::cec::MockCECInterface::new();
+        let jrpc = super::JRPCGetProperties::new(
+            mock_receiver,
+            std::sync::Arc::new(std::sync::Mutex::new(mock_cec)),
+        );
 
         let parts = get_parts();
 
@@ -498,4 +553,51 @@ mod tests {
             result
         );
     }
+
+    #[test(tokio::test)]
+    async fn it_merges_the_tv_power_in_properties() {
+        let mut mock_cec = crate::cec::MockCECInterface::new();
+        mock_cec
+            .expect_get_power_status()
+            .with(mockall::predicate::eq(crate::cec::CECLogicalAddress::TV))
+            .times(1)
+            .returning(|_| Ok(crate::cec::CECPowerStatus::Standby));
+
+        let mock_server: wiremock::MockServer = wiremock::MockServer::start().await;
+
+        wiremock::Mock::given(wiremock::matchers::body_partial_json(serde_json::json!({
+            "params": { "properties": ["name"] }
+        })))
+        .respond_with(
+            wiremock::ResponseTemplate::new(200).set_body_bytes(r#"{"result":{"name":"Kodi"}}"#),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let jrpc_handler = crate::handlers::jsonrpc::JsonrpcHandler::builder()
+            .with_url(&mock_server.uri())
+            .build();
+
+        let jrpc = super::JRPCGetProperties::new(
+            std::sync::Arc::new(crate::avreceiver::MockAVReceiver::new()),
+            std::sync::Arc::new(std::sync::Mutex::new(mock_cec)),
+        );
+
+        let request = crate::handlers::jsonrpc::JRPCQuery::new(
+            String::from("Application.GetProperties"),
+            Some(serde_json::json!({ "properties": ["power", "name"] })),
+            Some(42),
+        );
+
+        let result = jrpc
+            .handle(get_parts(), request, jrpc_handler.as_ref())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            &Some(serde_json::json!({ "power": false, "name": "Kodi" })),
+            result.result()
+        );
+    }
 }
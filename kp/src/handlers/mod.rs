@@ -1,3 +1,5 @@
 pub mod avreceiver;
 pub mod cec;
+pub mod configuration;
+pub mod health;
 pub mod jsonrpc;
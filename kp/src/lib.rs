@@ -8,24 +8,89 @@ use std::str::FromStr;
 
 fn register_handlers_kp(
     configuration: &configuration::ProxyConfiguration,
+    reloader: Option<std::sync::Arc<configuration::ConfigurationReloader>>,
     router: &mut router::Router,
 ) {
-    let avreceiver = avreceiver::get_avreceiver(&configuration.receiver);
+    let avreceiver = avreceiver::get_avreceiver(
+        &configuration.receiver,
+        router.debug_vars().upstream("receiver"),
+    );
     let cec_interface = cec::get_cec_connection(&configuration.cec);
 
     router
-        .add_handler(handlers::jsonrpc::get_jrpc_handler(
-            &configuration.jrpc,
+        .set_server_header(configuration.server.server_header.clone())
+        .set_canonicalize_paths(configuration.server.canonicalize_paths)
+        .set_debug_routing(configuration.server.debug_routing)
+        .set_send_timing_trailer(configuration.server.send_timing_trailer)
+        .set_json_errors(true)
+        .set_max_uri_length(configuration.server.max_uri_length)
+        .set_retry_after(
+            configuration
+                .server
+                .retry_after_secs
+                .map(std::time::Duration::from_secs),
+        )
+        .set_slow_request_threshold(
+            configuration
+                .server
+                .slow_request_threshold_ms
+                .map(std::time::Duration::from_millis),
+        );
+    router
+        .add_handler(
+            handlers::jsonrpc::get_jrpc_handler(
+                &configuration.jrpc,
+                avreceiver.clone(),
+                cec_interface.clone(),
+                router.debug_vars().upstream("jsonrpc"),
+            )
+            .expect("Incorrect jrpc configuration"),
+        )
+        .add_handlers(files::get_file_handlers(&configuration.file))
+        .add_handlers(handlers::cec::get_cec_handlers(cec_interface.clone()))
+        .add_handlers(handlers::avreceiver::get_handlers(
             avreceiver.clone(),
-            cec_interface.clone(),
+            &configuration.receiver,
         ))
-        .add_handlers(files::get_file_handlers(&configuration.file.root_path))
-        .add_handlers(handlers::cec::get_cec_handlers(cec_interface.clone()))
-        .add_handlers(handlers::avreceiver::get_handlers(avreceiver.clone()));
+        .add_handler(handlers::health::get_health_handler(
+            handlers::jsonrpc::get_upstream_pinger(&configuration.jrpc),
+            Some(handlers::health::DiskCheck::new(
+                configuration.file.root_path.to_owned(),
+                configuration.server.health_min_free_bytes,
+            )),
+        ));
+
+    *router.live_settings().write().unwrap() =
+        configuration::get_router_settings(&configuration.server)
+            .expect("Incorrect server configuration");
+    if let Some(reloader) = &reloader {
+        reloader.set_router_settings(router.live_settings());
+    }
+
+    // the /maintenance/* entry points are all enabled by the file configuration
+    if let (Some(reloader), true) = (reloader, configuration.file.maintenance_enabled) {
+        router
+            .add_handler(handlers::configuration::get_config_handler(
+                reloader.clone(),
+                configuration.file.admin_key.clone(),
+            ))
+            .add_handler(handlers::configuration::get_config_diff_handler(
+                reloader,
+                configuration.file.admin_key.clone(),
+            ));
+    }
+
+    for rule in &configuration.server.redirects {
+        router.add_handler(Box::from(
+            router::redirect::RedirectHandler::new(&rule.source, &rule.target, rule.status)
+                .expect("Incorrect redirect in server configuration"),
+        ));
+    }
 }
 
 pub async fn serve_kp(
     configuration: &configuration::ProxyConfiguration,
+    configuration_path: Option<&str>,
     exit_channel: Option<futures::channel::oneshot::Receiver<()>>,
 ) {
     let addr = std::net::SocketAddr::from_str(&configuration.server.host.as_str())
@@ -38,8 +103,46 @@ pub async fn serve_kp(
         Err(e) => log::warn!("Failed to register server in Avahi: {:?}", e),
     }
 
-    router::serve(addr, exit_channel, |router| {
-        register_handlers_kp(configuration, router)
+    let reloader = configuration_path.map(|path| {
+        std::sync::Arc::new(configuration::ConfigurationReloader::new(
+            path,
+            configuration::read_configuration(path).unwrap(),
+        ))
+    });
+
+    let on_reload = reloader.clone().map(|reloader| {
+        Box::new(move || {
+            if let Err(e) = reloader.reload() {
+                log::error!("Failed to reload the configuration: {}", e);
+            }
+        }) as Box<dyn Fn() + Send + Sync>
+    });
+
+    let options = router::ServeOptions::default()
+        .with_on_reload(on_reload)
+        .with_max_connection_lifetime(
+            configuration
+                .server
+                .max_connection_lifetime_secs
+                .map(std::time::Duration::from_secs),
+        )
+        .with_exit_empty_response(configuration.server.exit_empty_response)
+        .with_exit_delay(std::time::Duration::from_millis(
+            configuration.server.exit_delay_ms,
+        ))
+        .with_tls(
+            configuration
+                .server
+                .tls
+                .as_ref()
+                .map(|tls| router::TlsConfig {
+                    min_version: tls.min_version,
+                    ..router::TlsConfig::new(tls.cert_path.to_owned(), tls.key_path.to_owned())
+                }),
+        );
+
+    router::serve(addr, exit_channel, options, |router| {
+        register_handlers_kp(configuration, reloader.clone(), router)
     })
     .await;
 }
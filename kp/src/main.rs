@@ -20,12 +20,7 @@ struct Args {
 
 fn get_configuration(path: &Option<String>) -> kp::configuration::ProxyConfiguration {
     match path {
-        Some(path) => {
-            let configuration = std::fs::read(path).expect("Configuration file not found");
-            let configuration =
-                String::from_utf8(configuration).expect("Could not decode the configuration file");
-            serde_json::from_str(configuration.as_str()).expect("Invalid configuration file")
-        }
+        Some(path) => kp::configuration::read_configuration(path).unwrap(),
         None => serde_json::from_str("{}").unwrap(),
     }
 }
@@ -46,10 +41,12 @@ fn setup_logging(configuration: &kp::configuration::LoggingConfiguration) {
     } else {
         log::LevelFilter::Off
     };
+    // the logger lets everything through so that the level can be raised when reloading
     env_logger::Builder::from_default_env()
-        .filter_level(level)
+        .filter_level(log::LevelFilter::Trace)
         .target(env_logger::Target::Stdout)
         .init();
+    log::set_max_level(level);
     log::info!("Logger initialized with level {:?}", level);
 }
 
@@ -66,5 +63,5 @@ async fn main() {
         return;
     }
 
-    kp::serve_kp(&configuration, None).await;
+    kp::serve_kp(&configuration, args.configuration.as_deref(), None).await;
 }
@@ -0,0 +1,84 @@
+/// Logger keeping the messages in memory, installed before anything else can install one
+struct CapturingLogger(std::sync::Mutex<Vec<String>>);
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("kp")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger(std::sync::Mutex::new(Vec::new()));
+
+#[tokio::test]
+async fn it_logs_the_upstream_response_of_the_forwards() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    let kodi_mock = wiremock::MockServer::start().await;
+    wiremock::Mock::given(wiremock::matchers::method("POST"))
+        .and(wiremock::matchers::path("/jsonrpc"))
+        .respond_with(wiremock::ResponseTemplate::new(202).set_body_bytes(r#"{"result":"OK"}"#))
+        .expect(1)
+        .mount(&kodi_mock)
+        .await;
+
+    let file_path = "target/test/integration/forward_logging";
+    if std::path::Path::new(file_path).exists() {
+        std::fs::remove_dir_all(file_path).expect("Failed to clean the folder before test");
+    }
+    let configuration: kp::configuration::ProxyConfiguration = serde_json::from_str(&format!(
+        r#"{{
+            "file": {{ "rootPath": "{fp}" }},
+            "jrpc": {{ "target": "{kodi_url}/jsonrpc" }},
+            "server": {{ "host": "127.0.0.1:8076" }}
+        }}"#,
+        fp = file_path,
+        kodi_url = kodi_mock.uri(),
+    ))
+    .unwrap();
+
+    let (exit_channel, receiver) = futures::channel::oneshot::channel::<()>();
+    let serve = std::thread::spawn(move || {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(kp::serve_kp(&configuration, None, Some(receiver)));
+    });
+    // Wait for the server to have started
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    let request = hyper::Request::builder()
+        .uri("http://127.0.0.1:8076/jsonrpc")
+        .method("POST")
+        .header("x-request-id", "forward-42")
+        .body(hyper::Body::from(r#"{"method":"Not.Overloaded","id":1}"#))
+        .unwrap();
+    let response = hyper::Client::new()
+        .request(request)
+        .await
+        .expect("Error while sending POST jsonrpc request");
+
+    assert_eq!(202, response.status());
+
+    exit_channel.send(()).unwrap();
+    serve.join().unwrap();
+
+    let expected = format!(
+        r"^\[forward-42\] Forwarded to {}/jsonrpc: 202 in \d+ms$",
+        regex::escape(&kodi_mock.uri())
+    );
+    let re = regex::Regex::new(&expected).unwrap();
+    let logs = LOGGER.0.lock().unwrap();
+    assert!(
+        logs.iter().any(|line| re.is_match(line)),
+        "No log of the forward in {:?}",
+        logs
+    );
+}
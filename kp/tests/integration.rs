@@ -54,7 +54,7 @@ impl TestFixture {
         let rt_pouet = rt.clone();
         let serve = rt.spawn_blocking(move || {
             let configuration = configuration;
-            rt_pouet.block_on(kp::serve_kp(&configuration, Some(receiver)));
+            rt_pouet.block_on(kp::serve_kp(&configuration, None, Some(receiver)));
         });
         // Wait for the server to have started
         std::thread::sleep(std::time::Duration::from_millis(500));
@@ -143,7 +143,7 @@ async fn it_allows_saving_files(#[with("files", 8079)] fixture: TestFixture) {
 
     assert_eq!(200, parts.status);
     println!("{}", &body);
-    let re = regex::Regex::new(r#"^\[\{"timestamp":"[^"]+","address":"127.0.0.1","entry":\{"type":"Creation","version":0,"hash":"X5DLkAP39ZbbRCA79GreR1pKSQNtCJ2iUIugi4/Xpb8"}}]$"#).unwrap();
+    let re = regex::Regex::new(r#"^\[\{"timestamp":"[^"]+","address":"127.0.0.1","entry":\{"type":"Creation","version":0,"hash":"X5DLkAP39ZbbRCA79GreR1pKSQNtCJ2iUIugi4/Xpb8"},"userAgent":null,"comment":null}]$"#).unwrap();
     assert!(re.is_match(&body));
 }
 
@@ -202,6 +202,34 @@ async fn it_imbues_jrpc_queries(#[with("jrpc", 8078)] fixture: TestFixture) {
         .await
         .expect("Error while sending POST volume request");
 
+    // the query has no id, so it is a notification getting no answer
     let (parts, _) = response.into_parts();
-    assert_eq!(200, parts.status);
+    assert_eq!(204, parts.status);
+}
+
+#[rstest::rstest]
+#[tokio::test]
+#[allow(unused_variables)]
+async fn it_sends_the_errors_as_json(#[with("errors", 8077)] fixture: TestFixture) {
+    let request = hyper::Request::builder()
+        .uri(format!("http://127.0.0.1:{}/not/a/handler", 8077))
+        .method("GET")
+        .body(hyper::Body::empty())
+        .unwrap();
+
+    let response = hyper::Client::new()
+        .request(request)
+        .await
+        .expect("Error while sending GET request");
+
+    let (parts, body) = response.into_parts();
+    let body: serde_json::Value =
+        serde_json::from_slice(&hyper::body::to_bytes(body).await.unwrap()).unwrap();
+
+    assert_eq!(404, parts.status);
+    assert_eq!("application/json", parts.headers["content-type"]);
+    assert_eq!(
+        serde_json::json!({ "error": "Not Found", "status": 404 }),
+        body
+    );
 }
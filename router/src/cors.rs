@@ -0,0 +1,86 @@
+/// Cross-origin settings applied by the [Router](crate::Router) to all the requests
+///
+/// The requests coming from an allowed origin get an `Access-Control-Allow-Origin` header, and
+/// their preflight requests are answered by the router without reaching the handlers
+pub struct CorsConfig {
+    /// Origins allowed to send requests, e.g. `https://ui.example.com`, `*` allowing any origin
+    pub allowed_origins: Vec<String>,
+    /// Methods sent back in the answer of the preflight requests
+    pub allowed_methods: Vec<hyper::Method>,
+    /// Headers sent back in the answer of the preflight requests
+    pub allowed_headers: Vec<String>,
+    /// Time during which the browsers can reuse the answer of a preflight request
+    pub max_age: Option<std::time::Duration>,
+}
+
+impl CorsConfig {
+    /// Origin of the request, if it is allowed
+    pub(crate) fn allowed_origin(
+        &self,
+        request: &hyper::Request<hyper::Body>,
+    ) -> Option<hyper::header::HeaderValue> {
+        let origin = request.headers().get(hyper::header::ORIGIN)?;
+        let allowed = self
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.as_bytes() == origin.as_bytes());
+        if allowed {
+            Some(origin.clone())
+        } else {
+            log::debug!("Origin {:?} is not allowed", origin);
+            None
+        }
+    }
+
+    pub(crate) fn is_preflight(request: &hyper::Request<hyper::Body>) -> bool {
+        request.method() == hyper::Method::OPTIONS
+            && request
+                .headers()
+                .contains_key(hyper::header::ACCESS_CONTROL_REQUEST_METHOD)
+    }
+
+    /// Answer of a preflight request, with the Allow header of the targeted handler if it has one
+    pub(crate) fn preflight_response(
+        &self,
+        origin: &hyper::header::HeaderValue,
+        allow: Option<&[hyper::Method]>,
+    ) -> hyper::Response<hyper::Body> {
+        let join = |values: Vec<&str>| values.join(", ");
+        let mut builder = hyper::Response::builder()
+            .status(204)
+            .header(
+                hyper::header::ACCESS_CONTROL_ALLOW_METHODS,
+                join(self.allowed_methods.iter().map(|m| m.as_str()).collect()),
+            )
+            .header(
+                hyper::header::ACCESS_CONTROL_ALLOW_HEADERS,
+                join(self.allowed_headers.iter().map(String::as_str).collect()),
+            );
+        if let Some(max_age) = self.max_age {
+            builder = builder.header(hyper::header::ACCESS_CONTROL_MAX_AGE, max_age.as_secs());
+        }
+        if let Some(allow) = allow {
+            builder = builder.header(
+                hyper::header::ALLOW,
+                join(allow.iter().map(|m| m.as_str()).collect()),
+            );
+        }
+        let mut response = builder.body(hyper::Body::empty()).unwrap();
+        self.add_headers(origin, &mut response);
+        response
+    }
+
+    pub(crate) fn add_headers(
+        &self,
+        origin: &hyper::header::HeaderValue,
+        response: &mut hyper::Response<hyper::Body>,
+    ) {
+        let headers = response.headers_mut();
+        headers.insert(hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        // the allowed origin depends on the request, so it cannot be cached for all of them
+        headers.append(
+            hyper::header::VARY,
+            hyper::header::HeaderValue::from_static("origin"),
+        );
+    }
+}
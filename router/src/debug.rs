@@ -0,0 +1,151 @@
+/// Internal counters of the server, exposed as json on /debug/vars
+pub struct DebugVars {
+    started: std::time::Instant,
+    in_flight: std::sync::atomic::AtomicUsize,
+    /// Number of responses per status class, from 1xx to 5xx
+    status_classes: [std::sync::atomic::AtomicU64; 5],
+    upstreams: std::sync::Mutex<Vec<(String, std::sync::Arc<UpstreamTally>)>>,
+}
+
+/// Number of successful and failed calls to an upstream server
+#[derive(Default)]
+pub struct UpstreamTally {
+    success: std::sync::atomic::AtomicU64,
+    failure: std::sync::atomic::AtomicU64,
+}
+
+/// Decrements the number of in-flight requests when dropped, even if the request is cancelled
+pub(crate) struct InFlightGuard<'a>(&'a DebugVars);
+
+impl UpstreamTally {
+    pub fn record(&self, success: bool) {
+        let counter = if success {
+            &self.success
+        } else {
+            &self.failure
+        };
+        counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+impl DebugVars {
+    pub fn new() -> DebugVars {
+        DebugVars {
+            started: std::time::Instant::now(),
+            in_flight: std::sync::atomic::AtomicUsize::new(0),
+            status_classes: Default::default(),
+            upstreams: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Gets the tally of the given upstream, creating it if needed
+    ///
+    /// The tally should be kept by the caller, the lock is only taken here
+    pub fn upstream(&self, name: &str) -> std::sync::Arc<UpstreamTally> {
+        let mut upstreams = self.upstreams.lock().unwrap();
+        if let Some((_, tally)) = upstreams.iter().find(|(n, _)| n == name) {
+            return tally.clone();
+        }
+        let tally = std::sync::Arc::new(UpstreamTally::default());
+        upstreams.push((name.to_owned(), tally.clone()));
+        tally
+    }
+
+    pub(crate) fn start_request(&self) -> InFlightGuard<'_> {
+        self.in_flight
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        InFlightGuard(self)
+    }
+
+    pub(crate) fn record_status(&self, status: hyper::StatusCode) {
+        if let Some(counter) = self
+            .status_classes
+            .get((status.as_u16() / 100) as usize - 1)
+        {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let load = |counter: &std::sync::atomic::AtomicU64| {
+            counter.load(std::sync::atomic::Ordering::Relaxed)
+        };
+        let requests: serde_json::Map<String, serde_json::Value> = self
+            .status_classes
+            .iter()
+            .enumerate()
+            .map(|(i, counter)| (format!("{}xx", i + 1), load(counter).into()))
+            .collect();
+        let upstreams: serde_json::Map<String, serde_json::Value> = self
+            .upstreams
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, tally)| {
+                (
+                    name.to_owned(),
+                    serde_json::json!({
+                        "success": load(&tally.success),
+                        "failure": load(&tally.failure),
+                    }),
+                )
+            })
+            .collect();
+        serde_json::json!({
+            "uptimeSeconds": self.started.elapsed().as_secs(),
+            "inFlightRequests": self.in_flight.load(std::sync::atomic::Ordering::Relaxed),
+            "requests": requests,
+            "upstreams": upstreams,
+        })
+    }
+}
+
+impl std::default::Default for DebugVars {
+    fn default() -> Self {
+        DebugVars::new()
+    }
+}
+
+impl std::ops::Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0
+            .in_flight
+            .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+struct DebugVarsHandler {
+    matcher: Box<dyn crate::matcher::Matcher>,
+    vars: std::sync::Arc<DebugVars>,
+}
+
+#[async_trait::async_trait]
+impl crate::router::Handler for DebugVarsHandler {
+    fn get_matcher(&self) -> &Box<dyn crate::matcher::Matcher> {
+        &self.matcher
+    }
+
+    async fn handle(
+        &self,
+        _request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, crate::router::RouterError> {
+        Ok(hyper::Response::builder()
+            .status(200)
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(self.vars.to_json().to_string()))
+            .unwrap())
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
+    }
+}
+
+pub fn get_handler(vars: std::sync::Arc<DebugVars>) -> Box<dyn crate::router::Handler> {
+    let matcher = crate::matcher::builder()
+        .exact_path(String::from("/debug/vars"))
+        .with_method(&hyper::Method::GET)
+        .build()
+        .unwrap();
+    Box::from(DebugVarsHandler { matcher, vars })
+}
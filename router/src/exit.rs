@@ -3,6 +3,8 @@ static PANIC_MSG: &'static str = "Failed to exit server gracefully, panicking...
 struct ExitHandler {
     matcher: Box<dyn crate::matcher::Matcher>,
     sender: std::sync::Mutex<Option<futures::channel::oneshot::Sender<()>>>,
+    empty_response: bool,
+    delay: std::time::Duration,
 }
 
 #[async_trait::async_trait]
@@ -15,31 +17,47 @@ impl crate::router::Handler for ExitHandler {
         &self,
         _request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, crate::router::RouterError> {
-        // This panics if we can't get the lock or if the channel has already been used
-        self.sender
-            .lock()
-            .expect(PANIC_MSG)
-            .take()
-            .ok_or(crate::router::RouterError::HandlerError(
+        // This panics if we can't get the lock
+        let sender = self.sender.lock().expect(PANIC_MSG).take().ok_or(
+            crate::router::RouterError::HandlerError(
                 500,
                 String::from("Server is already shutting down..."),
-            ))?
-            .send(())
-            .expect(PANIC_MSG);
+            ),
+        )?;
 
+        // the shutdown is delayed so that the response reaches the client first
+        let delay = self.delay;
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            if sender.send(()).is_err() {
+                log::error!("{}", PANIC_MSG);
+            }
+        });
+
+        if self.empty_response {
+            return Ok(hyper::Response::builder()
+                .status(204)
+                .body(hyper::Body::empty())
+                .unwrap());
+        }
         Ok(hyper::Response::builder()
-            .status(204)
-            .body(hyper::Body::empty())
+            .status(200)
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(
+                serde_json::json!({ "status": "shutting down" }).to_string(),
+            ))
             .unwrap())
     }
 
-    fn get_timeout(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(1)
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
     }
 }
 
 pub fn get_handler(
     exit_sender: futures::channel::oneshot::Sender<()>,
+    empty_response: bool,
+    delay: std::time::Duration,
 ) -> Box<dyn crate::router::Handler> {
     let matcher = crate::matcher::builder()
         .exact_path(String::from("/exit"))
@@ -49,5 +67,50 @@ pub fn get_handler(
     Box::from(ExitHandler {
         matcher,
         sender: std::sync::Mutex::new(Some(exit_sender)),
+        empty_response,
+        delay,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    fn get_request() -> hyper::Request<hyper::Body> {
+        hyper::Request::builder()
+            .uri("/exit")
+            .body(hyper::Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_confirms_the_shutdown() {
+        let (sender, receiver) = futures::channel::oneshot::channel::<()>();
+        let handler = super::get_handler(sender, false, std::time::Duration::from_millis(100));
+
+        let response = handler.handle(get_request()).await.unwrap();
+
+        assert_eq!(200, response.status());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::json!({ "status": "shutting down" }),
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        );
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), receiver)
+            .await
+            .expect("The server should have been shut down")
+            .unwrap();
+
+        assert!(handler.handle(get_request()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_can_send_an_empty_response() {
+        let (sender, receiver) = futures::channel::oneshot::channel::<()>();
+        let handler = super::get_handler(sender, true, std::time::Duration::ZERO);
+
+        let response = handler.handle(get_request()).await.unwrap();
+
+        assert_eq!(204, response.status());
+        receiver.await.unwrap();
+    }
+}
@@ -1,7 +1,13 @@
+pub use self::cors::CorsConfig;
 pub use self::router::*;
+pub use self::tls::{TlsConfig, TlsVersion};
+mod cors;
+pub mod debug;
 mod exit;
 pub mod matcher;
+pub mod redirect;
 pub mod router;
+mod tls;
 
 use futures::FutureExt;
 
@@ -41,9 +47,154 @@ async fn shutdown_signal(exit_channel: futures::channel::oneshot::Receiver<()>)
     }
 }
 
+/// Calls `on_reload` each time a HUP signal is received
+async fn reload_signal(on_reload: Box<dyn Fn() + Send + Sync>) {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .expect("Could not intercept HUP signal");
+
+    while hangup.recv().await.is_some() {
+        log::info!("Received hangup signal, reloading");
+        on_reload();
+    }
+}
+
+/// Options of [serve]
+#[derive(Default)]
+pub struct ServeOptions {
+    on_reload: Option<Box<dyn Fn() + Send + Sync>>,
+    max_connection_lifetime: Option<std::time::Duration>,
+    exit_empty_response: bool,
+    exit_delay: std::time::Duration,
+    tls: Option<TlsConfig>,
+}
+
+impl ServeOptions {
+    /// Gives the callback called each time a HUP signal is received
+    pub fn with_on_reload(mut self, on_reload: Option<Box<dyn Fn() + Send + Sync>>) -> Self {
+        self.on_reload = on_reload;
+        self
+    }
+
+    /// Closes the connections after the given duration, regardless of their activity
+    pub fn with_max_connection_lifetime(
+        mut self,
+        max_connection_lifetime: Option<std::time::Duration>,
+    ) -> Self {
+        self.max_connection_lifetime = max_connection_lifetime;
+        self
+    }
+
+    /// Makes /exit answer with an empty 204 instead of a json document
+    pub fn with_exit_empty_response(mut self, exit_empty_response: bool) -> Self {
+        self.exit_empty_response = exit_empty_response;
+        self
+    }
+
+    /// Delay between the response of /exit and the shutdown of the server
+    pub fn with_exit_delay(mut self, exit_delay: std::time::Duration) -> Self {
+        self.exit_delay = exit_delay;
+        self
+    }
+
+    /// Serves HTTPS with the given certificate instead of plain HTTP
+    pub fn with_tls(mut self, tls: Option<TlsConfig>) -> Self {
+        self.tls = tls;
+        self
+    }
+}
+
+/// Serves one connection until it is closed, its lifetime is reached, or the server shuts down
+async fn serve_connection(
+    stream: tokio::net::TcpStream,
+    remote_address: std::net::SocketAddr,
+    router: std::sync::Arc<Router>,
+    max_connection_lifetime: Option<std::time::Duration>,
+    shutdown: tokio::sync::watch::Receiver<bool>,
+    tls_acceptor: Option<tls::TlsAcceptor>,
+) {
+    match tls_acceptor {
+        Some(tls_acceptor) => match tls_acceptor.accept(stream).await {
+            Ok(stream) => {
+                serve_stream(
+                    stream,
+                    remote_address,
+                    router,
+                    max_connection_lifetime,
+                    shutdown,
+                )
+                .await
+            }
+            Err(e) => log::debug!("TLS handshake with {:?} failed: {}", remote_address, e),
+        },
+        None => {
+            serve_stream(
+                stream,
+                remote_address,
+                router,
+                max_connection_lifetime,
+                shutdown,
+            )
+            .await
+        }
+    }
+}
+
+async fn serve_stream<S>(
+    stream: S,
+    remote_address: std::net::SocketAddr,
+    router: std::sync::Arc<Router>,
+    max_connection_lifetime: Option<std::time::Duration>,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    match remote_address {
+        std::net::SocketAddr::V4(addr) => {
+            log::debug!("Got connection from ipv4 {:?}", addr.ip());
+        }
+        std::net::SocketAddr::V6(addr) => {
+            log::debug!("Got connection from ipv6 {:?}", addr.ip());
+            log::debug!("IPv4 {:?}", addr.ip().to_ipv4());
+        }
+    }
+
+    let service = hyper::service::service_fn(move |mut req| {
+        req.extensions_mut().insert(remote_address);
+        let router = router.clone();
+        async move { router.handle(req).await }
+    });
+
+    let connection = hyper::server::conn::Http::new().serve_connection(stream, service);
+    tokio::pin!(connection);
+
+    let lifetime = async {
+        match max_connection_lifetime {
+            Some(max_connection_lifetime) => tokio::time::sleep(max_connection_lifetime).await,
+            None => futures::future::pending().await,
+        }
+    };
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = lifetime => {
+            log::debug!("Closing connection from {:?} that reached its lifetime", remote_address);
+            return;
+        }
+        _ = shutdown.changed() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+
+    if let Err(e) = result {
+        log::debug!("Connection error: {}", e);
+    }
+}
+
 pub async fn serve<F>(
     host: std::net::SocketAddr,
     exit_channel: Option<futures::channel::oneshot::Receiver<()>>,
+    options: ServeOptions,
     register_handlers: F,
 ) where
     F: FnOnce(&mut Router),
@@ -62,44 +213,157 @@ pub async fn serve<F>(
 
     let mut router = router::Router::new();
     if let Some(exit_sender) = exit_sender {
-        router.add_handler(exit::get_handler(exit_sender));
+        router.add_handler(exit::get_handler(
+            exit_sender,
+            options.exit_empty_response,
+            options.exit_delay,
+        ));
     }
+    router.add_handler(debug::get_handler(router.debug_vars()));
     register_handlers(&mut router);
     let router = std::sync::Arc::new(router);
 
-    let make_svc =
-        hyper::service::make_service_fn(move |connection: &hyper::server::conn::AddrStream| {
-            let remote_address = connection.remote_addr();
+    let tls_acceptor = match options.tls.as_ref().map(TlsConfig::acceptor).transpose() {
+        Ok(tls_acceptor) => tls_acceptor,
+        Err(e) => {
+            log::error!("TLS configuration error: {}", e);
+            return;
+        }
+    };
 
-            match remote_address {
-                std::net::SocketAddr::V4(addr) => {
-                    log::debug!("Got connection from ipv4 {:?}", addr.ip());
-                }
-                std::net::SocketAddr::V6(addr) => {
-                    log::debug!("Got connection from ipv6 {:?}", addr.ip());
-                    log::debug!("IPv4 {:?}", addr.ip().to_ipv4());
-                }
-            }
+    let listener = match tokio::net::TcpListener::bind(&host).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("server error: {}", e);
+            return;
+        }
+    };
 
-            let router = router.clone();
-            async move {
-                Ok::<_, std::convert::Infallible>(hyper::service::service_fn(move |mut req| {
-                    req.extensions_mut().insert(remote_address);
-                    let router = router.clone();
-                    async move { router.handle(req).await }
-                }))
-            }
-        });
+    log::info!(
+        "Server now listening on {:?}{}",
+        host,
+        if tls_acceptor.is_some() {
+            " with TLS"
+        } else {
+            ""
+        }
+    );
 
-    let server = hyper::Server::bind(&host).serve(make_svc);
+    let reload = options
+        .on_reload
+        .map(|on_reload| tokio::spawn(reload_signal(on_reload)));
 
-    let graceful = server.with_graceful_shutdown(shutdown_signal(exit_receiver));
+    let (shutdown_sender, shutdown_receiver) = tokio::sync::watch::channel(false);
+    let mut connections = tokio::task::JoinSet::new();
+    let shutdown = shutdown_signal(exit_receiver);
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, remote_address)) => {
+                    connections.spawn(serve_connection(
+                        stream,
+                        remote_address,
+                        router.clone(),
+                        options.max_connection_lifetime,
+                        shutdown_receiver.clone(),
+                        tls_acceptor.clone(),
+                    ));
+                }
+                Err(e) => log::error!("server error: {}", e),
+            },
+            // reaps the finished connections
+            Some(_) = connections.join_next(), if !connections.is_empty() => (),
+        }
+    }
 
-    log::info!("Server now listening on {:?}", host);
+    drop(listener);
+    let _ = shutdown_sender.send(true);
+    while connections.join_next().await.is_some() {}
 
-    if let Err(e) = graceful.await {
-        log::error!("server error: {}", e);
+    if let Some(reload) = reload {
+        reload.abort();
     }
 
     log::info!("Exiting");
 }
+
+#[cfg(test)]
+mod tests {
+    struct LongPollHandler {
+        matcher: Box<dyn crate::matcher::Matcher>,
+    }
+
+    #[async_trait::async_trait]
+    impl crate::router::Handler for LongPollHandler {
+        fn get_matcher(&self) -> &Box<dyn crate::matcher::Matcher> {
+            &self.matcher
+        }
+
+        async fn handle(
+            &self,
+            _request: hyper::Request<hyper::Body>,
+        ) -> Result<hyper::Response<hyper::Body>, crate::router::RouterError> {
+            tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+            Ok(hyper::Response::builder()
+                .status(204)
+                .body(hyper::Body::empty())
+                .unwrap())
+        }
+
+        fn get_timeout(&self) -> Option<std::time::Duration> {
+            Some(std::time::Duration::from_secs(60))
+        }
+    }
+
+    #[tokio::test]
+    async fn it_closes_connections_at_their_max_lifetime() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let host = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let (exit_sender, exit_receiver) = futures::channel::oneshot::channel::<()>();
+
+        let server = tokio::spawn(super::serve(
+            host,
+            Some(exit_receiver),
+            super::ServeOptions::default()
+                .with_max_connection_lifetime(Some(std::time::Duration::from_millis(300))),
+            |router| {
+                router.add_handler(Box::new(LongPollHandler {
+                    matcher: crate::matcher::builder()
+                        .exact_path("/poll")
+                        .build()
+                        .unwrap(),
+                }));
+            },
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let start = std::time::Instant::now();
+        let mut stream = tokio::net::TcpStream::connect(host).await.unwrap();
+        stream
+            .write_all(b"GET /poll HTTP/1.1\r\nhost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = Vec::new();
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            stream.read_to_end(&mut response),
+        )
+        .await
+        .expect("The connection should have been closed")
+        .unwrap();
+
+        assert!(start.elapsed() >= std::time::Duration::from_millis(300));
+        assert!(response.is_empty());
+
+        exit_sender.send(()).unwrap();
+        server.await.unwrap();
+    }
+}
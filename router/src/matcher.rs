@@ -12,6 +12,21 @@ pub enum MatcherResult {
 /// Trait to implement to be able to tell whether a [crate::router::Handler] can handle a query or not
 pub trait Matcher: Sync + Send {
     fn matches(&self, request: &hyper::Request<hyper::Body>) -> MatcherResult;
+
+    /// Named groups captured from the path of the request, if the path matches a regex
+    fn captures(
+        &self,
+        _request: &hyper::Request<hyper::Body>,
+    ) -> Option<std::collections::HashMap<String, String>> {
+        None
+    }
+
+    /// Path the requests must have to be matched, if it is exact
+    ///
+    /// The [crate::Router] indexes the handlers by this path instead of trying them one by one
+    fn exact_path(&self) -> Option<&str> {
+        None
+    }
 }
 
 #[derive(Debug)]
@@ -28,6 +43,7 @@ enum UriMatcher {
 
 enum MethodMatcher {
     All,
+    OneOf(Vec<hyper::Method>),
     Exact(hyper::Method),
 }
 
@@ -47,6 +63,7 @@ impl Matcher for MatcherImpl {
         if uri_match {
             let method_match = match &self.method_matcher {
                 MethodMatcher::All => true,
+                MethodMatcher::OneOf(methods) => methods.contains(request.method()),
                 MethodMatcher::Exact(method) => request.method() == method,
             };
 
@@ -59,6 +76,35 @@ impl Matcher for MatcherImpl {
             MatcherResult::KO
         }
     }
+
+    fn captures(
+        &self,
+        request: &hyper::Request<hyper::Body>,
+    ) -> Option<std::collections::HashMap<String, String>> {
+        match &self.uri_matcher {
+            UriMatcher::Regex(re) => {
+                let captures = re.captures(request.uri().path())?;
+                Some(
+                    re.capture_names()
+                        .flatten()
+                        .filter_map(|name| {
+                            captures
+                                .name(name)
+                                .map(|value| (name.to_owned(), value.as_str().to_owned()))
+                        })
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    fn exact_path(&self) -> Option<&str> {
+        match &self.uri_matcher {
+            UriMatcher::Exact(uri) => Some(uri),
+            _ => None,
+        }
+    }
 }
 
 pub struct MatcherBuilder {
@@ -111,6 +157,23 @@ impl MatcherBuilder {
         self
     }
 
+    /// Matches any of the given methods
+    pub fn with_methods<I, T>(mut self, methods: I) -> MatcherBuilder
+    where
+        I: IntoIterator<Item = T>,
+        hyper::Method: std::convert::TryFrom<T>,
+    {
+        let methods: std::result::Result<Vec<hyper::Method>, _> = methods
+            .into_iter()
+            .map(std::convert::TryFrom::try_from)
+            .collect();
+        self.method_matcher = match methods {
+            Ok(methods) => Some(MethodMatcher::OneOf(methods)),
+            _ => None,
+        };
+        self
+    }
+
     pub fn build(self) -> Result<Box<dyn Matcher>, MatcherBuilderError> {
         match self.method_matcher {
             None => Err(MatcherBuilderError::IncorrectMethod),
@@ -176,4 +239,67 @@ mod tests {
         let request = get_request("/other_uri", &hyper::Method::POST);
         assert_eq!(MatcherResult::UriOnly, matcher.matches(&request));
     }
+
+    #[test]
+    fn it_builds_one_of_method_matchers() {
+        let matcher = builder()
+            .with_methods(&[hyper::Method::GET, hyper::Method::HEAD])
+            .build()
+            .unwrap();
+
+        let request = get_request("/test_uri", &hyper::Method::GET);
+        assert_eq!(MatcherResult::OK, matcher.matches(&request));
+
+        let request = get_request("/test_uri", &hyper::Method::HEAD);
+        assert_eq!(MatcherResult::OK, matcher.matches(&request));
+
+        let request = get_request("/test_uri", &hyper::Method::POST);
+        assert_eq!(MatcherResult::UriOnly, matcher.matches(&request));
+    }
+
+    #[test]
+    fn it_builds_one_of_method_matchers_from_names() {
+        let matcher = builder()
+            .exact_path("/test_uri")
+            .with_methods(["PUT", "DELETE"])
+            .build()
+            .unwrap();
+
+        let request = get_request("/test_uri", &hyper::Method::PUT);
+        assert_eq!(MatcherResult::OK, matcher.matches(&request));
+
+        let request = get_request("/test_uri", &hyper::Method::DELETE);
+        assert_eq!(MatcherResult::OK, matcher.matches(&request));
+
+        let request = get_request("/test_uri", &hyper::Method::GET);
+        assert_eq!(MatcherResult::UriOnly, matcher.matches(&request));
+
+        let request = get_request("/other_uri", &hyper::Method::PUT);
+        assert_eq!(MatcherResult::KO, matcher.matches(&request));
+
+        assert!(builder()
+            .with_methods(["GET", "NOT A METHOD"])
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn it_captures_the_named_groups() {
+        let matcher = builder()
+            .regex_path("^/files/(?P<path>.+)/(?P<name>[^/]+)$")
+            .build()
+            .unwrap();
+
+        let request = get_request("/files/keepass/backup/pdb.kdbx", &hyper::Method::GET);
+        let captures = matcher.captures(&request).unwrap();
+        assert_eq!(2, captures.len());
+        assert_eq!("keepass/backup", captures["path"]);
+        assert_eq!("pdb.kdbx", captures["name"]);
+
+        let request = get_request("/files/pdb.kdbx", &hyper::Method::GET);
+        assert_eq!(None, matcher.captures(&request));
+
+        let matcher = builder().exact_path("/files/pdb.kdbx").build().unwrap();
+        assert_eq!(None, matcher.captures(&request));
+    }
 }
@@ -0,0 +1,80 @@
+/// Handler redirecting the requests made on a path to another location
+pub struct RedirectHandler {
+    location: String,
+    matcher: Box<dyn crate::matcher::Matcher>,
+    status: u16,
+}
+
+impl RedirectHandler {
+    /// Creates the handler, the status must be one of 301, 302, 307 or 308
+    pub fn new(source: &str, location: &str, status: u16) -> Result<RedirectHandler, String> {
+        if ![301, 302, 307, 308].contains(&status) {
+            return Err(format!("Invalid redirect status: {}", status));
+        }
+        hyper::header::HeaderValue::from_str(location)
+            .map_err(|_| format!("Invalid redirect location: {}", location))?;
+        let matcher = crate::matcher::builder()
+            .exact_path(source)
+            .build()
+            .map_err(|_| format!("Invalid redirect source: {}", source))?;
+        Ok(RedirectHandler {
+            location: location.to_owned(),
+            matcher,
+            status,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::router::Handler for RedirectHandler {
+    fn get_matcher(&self) -> &Box<dyn crate::matcher::Matcher> {
+        &self.matcher
+    }
+
+    async fn handle(
+        &self,
+        _request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, crate::router::RouterError> {
+        Ok(hyper::Response::builder()
+            .status(self.status)
+            .header("location", &self.location)
+            .body(hyper::Body::empty())
+            .unwrap())
+    }
+
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::router::Handler;
+
+    #[tokio::test]
+    async fn it_redirects() {
+        for status in [301, 302, 307, 308] {
+            let handler = super::RedirectHandler::new("/", "/files/index.html", status).unwrap();
+
+            let request = hyper::Request::builder()
+                .uri("/")
+                .method("GET")
+                .body(hyper::Body::empty())
+                .unwrap();
+            let response = handler.handle(request).await.unwrap();
+
+            assert_eq!(status, response.status());
+            assert_eq!(
+                "/files/index.html",
+                response.headers().get("location").unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn it_refuses_non_redirect_statuses() {
+        for status in [200, 304, 404] {
+            assert!(super::RedirectHandler::new("/", "/files/index.html", status).is_err());
+        }
+    }
+}
@@ -9,31 +9,222 @@ pub trait Handler: Sync + Send {
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, RouterError>;
-    fn get_timeout(&self) -> std::time::Duration;
+    /// Time after which the handling is abandoned with a 504, the default timeout of the
+    /// [Router] if `None`
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        None
+    }
+    /// Methods sent in the Allow header of the answers to the preflight requests, which the
+    /// [Router] answers without reaching the handler when they come from an allowed origin
+    fn allowed_methods(&self) -> Option<&[hyper::Method]> {
+        None
+    }
+    /// Name of the handler, sent in the X-Handler header when the routing is debugged
+    fn name(&self) -> &str {
+        let type_name = std::any::type_name::<Self>();
+        type_name.rsplit("::").next().unwrap_or(type_name)
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum RouterError {
+    /// Precondition on the state of the resource failed, e.g. its version changed
+    Conflict(String),
     ForwardingError(String),
     HandlerError(u16, String),
     InvalidRequest(String),
+    /// Error whose message is already a json document
+    JsonError(u16, String),
     MethodNotAllowed,
     NotFound,
 }
 
+/// Position of a handler in the order in which the handlers are tried, lowest first
+///
+/// Descending priority, then insertion order
+type HandlerOrder = (std::cmp::Reverse<i32>, usize);
+
+type OrderedHandler = (HandlerOrder, Box<dyn Handler>);
+
+/// Settings of the [Router] that can be changed while it is serving, see [Router::live_settings]
+pub struct LiveSettings {
+    /// Timeout of the handlers that do not give their own
+    pub default_timeout: std::time::Duration,
+    pub cors: Option<std::sync::Arc<crate::cors::CorsConfig>>,
+}
+
+impl std::default::Default for LiveSettings {
+    fn default() -> Self {
+        LiveSettings {
+            default_timeout: std::time::Duration::from_secs(10),
+            cors: None,
+        }
+    }
+}
+
 pub struct Router {
-    handlers: Vec<Box<dyn Handler>>,
+    /// Handlers whose matcher has an exact path, indexed by that path and sorted by order
+    exact_handlers: std::collections::HashMap<String, Vec<OrderedHandler>>,
+    /// Other handlers, sorted by order
+    handlers: Vec<OrderedHandler>,
+    handler_count: usize,
+    fallback: Option<Box<dyn Handler>>,
+    vars: std::sync::Arc<crate::debug::DebugVars>,
+    server_header: Option<hyper::header::HeaderValue>,
+    canonicalize_paths: bool,
+    slow_request_threshold: Option<std::time::Duration>,
+    debug_routing: bool,
+    send_timing_trailer: bool,
+    json_errors: bool,
+    live: std::sync::Arc<std::sync::RwLock<LiveSettings>>,
+    retry_after: Option<std::time::Duration>,
+    max_uri_length: usize,
 }
 
 impl Router {
     pub fn new() -> Router {
         Router {
+            exact_handlers: std::collections::HashMap::new(),
             handlers: Vec::new(),
+            handler_count: 0,
+            fallback: None,
+            vars: std::sync::Arc::new(crate::debug::DebugVars::new()),
+            server_header: None,
+            canonicalize_paths: false,
+            slow_request_threshold: None,
+            debug_routing: false,
+            send_timing_trailer: false,
+            json_errors: false,
+            live: std::sync::Arc::new(std::sync::RwLock::new(LiveSettings::default())),
+            retry_after: None,
+            max_uri_length: 8192,
         }
     }
 
+    /// Counters maintained by the router, the handlers can also register their upstream servers
+    pub fn debug_vars(&self) -> std::sync::Arc<crate::debug::DebugVars> {
+        self.vars.clone()
+    }
+
+    /// Sets the Server header of all the responses
+    ///
+    /// If `None` or empty, the Server header set by the handlers (e.g. coming from a forwarded
+    /// response) is removed instead
+    pub fn set_server_header(&mut self, server_header: Option<String>) -> &mut Self {
+        self.server_header = server_header
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                hyper::header::HeaderValue::from_str(&value).expect("Incorrect Server header")
+            });
+        self
+    }
+
+    /// Strips the trailing slash of the request paths (except for the root) before routing them
+    ///
+    /// The request is rewritten internally, no redirection is sent to the client
+    pub fn set_canonicalize_paths(&mut self, canonicalize_paths: bool) -> &mut Self {
+        self.canonicalize_paths = canonicalize_paths;
+        self
+    }
+
+    /// Only logs the requests taking more than the given duration, at warn level
+    ///
+    /// If `None`, all the requests are logged at info level
+    pub fn set_slow_request_threshold(
+        &mut self,
+        slow_request_threshold: Option<std::time::Duration>,
+    ) -> &mut Self {
+        self.slow_request_threshold = slow_request_threshold;
+        self
+    }
+
+    /// Adds an X-Handler header with the name of the handler to the responses
+    pub fn set_debug_routing(&mut self, debug_routing: bool) -> &mut Self {
+        self.debug_routing = debug_routing;
+        self
+    }
+
+    /// Sends the time spent handling the requests in a `Server-Timing: total;dur=<ms>` trailer
+    ///
+    /// Trailers are only sent over HTTP/2, hyper cannot send them over HTTP/1.1, and only for the
+    /// responses whose whole body is already known, e.g. not for the forwarded streams
+    pub fn set_send_timing_trailer(&mut self, send_timing_trailer: bool) -> &mut Self {
+        self.send_timing_trailer = send_timing_trailer;
+        self
+    }
+
+    /// Settings that can still be changed once the router is shared, e.g. on a configuration
+    /// reload. They apply to the requests received after the change
+    pub fn live_settings(&self) -> std::sync::Arc<std::sync::RwLock<LiveSettings>> {
+        self.live.clone()
+    }
+
+    /// Timeout of the handlers that do not give their own, 10 seconds by default
+    pub fn set_default_timeout(&mut self, default_timeout: std::time::Duration) -> &mut Self {
+        self.live.write().unwrap().default_timeout = default_timeout;
+        self
+    }
+
+    /// Answers the preflight requests and adds the Access-Control-Allow-Origin header to the
+    /// successful responses, for the allowed origins
+    pub fn set_cors(&mut self, cors: Option<crate::cors::CorsConfig>) -> &mut Self {
+        self.live.write().unwrap().cors = cors.map(std::sync::Arc::new);
+        self
+    }
+
+    /// Renders the errors as `{"error": "...", "status": n}` instead of plain text
+    ///
+    /// The [RouterError::JsonError] are still sent as is
+    pub fn set_json_errors(&mut self, json_errors: bool) -> &mut Self {
+        self.json_errors = json_errors;
+        self
+    }
+
+    /// Adds a Retry-After header with the given delay to the 502, 503 and 504 errors, e.g. the
+    /// failed forwards and the handlers that timed out, so that the clients pace their retries
+    pub fn set_retry_after(&mut self, retry_after: Option<std::time::Duration>) -> &mut Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Refuses with a 414 the requests whose path and query are longer than this many bytes,
+    /// before routing them, 8192 by default
+    pub fn set_max_uri_length(&mut self, max_uri_length: usize) -> &mut Self {
+        self.max_uri_length = max_uri_length;
+        self
+    }
+
     pub fn add_handler(&mut self, handler: Box<dyn Handler>) -> &mut Self {
-        self.handlers.push(handler);
+        self.add_handler_with_priority(0, handler)
+    }
+
+    /// Adds a handler tried before the ones with a lower priority, whatever their insertion order
+    ///
+    /// The handlers with the same priority are tried in their insertion order, [Router::add_handler]
+    /// uses a priority of 0
+    pub fn add_handler_with_priority(
+        &mut self,
+        priority: i32,
+        handler: Box<dyn Handler>,
+    ) -> &mut Self {
+        let order = (std::cmp::Reverse(priority), self.handler_count);
+        self.handler_count += 1;
+        // the handlers with an exact path are only tried for the requests with that path, so that
+        // many of them (e.g. redirects) do not slow down the routing of the other requests
+        let handlers = match handler.get_matcher().exact_path() {
+            Some(path) => self.exact_handlers.entry(path.to_owned()).or_default(),
+            None => &mut self.handlers,
+        };
+        let index = handlers.partition_point(|(other_order, _)| *other_order < order);
+        handlers.insert(index, (order, handler));
+        self
+    }
+
+    /// Handles the requests whose path is not matched by any handler, whatever its matcher
+    ///
+    /// The requests matching the path of a handler but not its method still get a 405
+    pub fn set_fallback(&mut self, handler: Box<dyn Handler>) -> &mut Self {
+        self.fallback = Some(handler);
         self
     }
 
@@ -42,67 +233,229 @@ impl Router {
         T: IntoIterator<Item = Box<dyn Handler>>,
     {
         for handler in handlers {
-            self.handlers.push(handler);
+            self.add_handler(handler);
         }
         self
     }
 
     async fn handle_inner(
         &self,
-        request: hyper::Request<hyper::Body>,
+        mut request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, RouterError> {
+        let uri_length = request
+            .uri()
+            .path_and_query()
+            .map_or(0, |path_and_query| path_and_query.as_str().len());
+        if uri_length > self.max_uri_length {
+            log::warn!(
+                "Refused a URI of {} bytes, the limit is {}",
+                uri_length,
+                self.max_uri_length
+            );
+            return Err(RouterError::HandlerError(414, String::from("URI too long")));
+        }
+        if self.canonicalize_paths {
+            Router::canonicalize_path(&mut request)?;
+        }
+        let (default_timeout, cors) = {
+            let live = self.live.read().unwrap();
+            (live.default_timeout, live.cors.clone())
+        };
+        let cors_origin = cors.as_ref().and_then(|cors| cors.allowed_origin(&request));
+        if let (Some(cors), Some(origin)) = (&cors, &cors_origin) {
+            if crate::cors::CorsConfig::is_preflight(&request) {
+                let allowed_methods = self
+                    .get_handler(&request)
+                    .ok()
+                    .and_then(|handler| handler.allowed_methods());
+                return Ok(cors.preflight_response(origin, allowed_methods));
+            }
+        }
         let handler = self.get_handler(&request)?;
-        async_std::future::timeout(handler.get_timeout(), handler.handle(request))
+        let timeout = handler.get_timeout().unwrap_or(default_timeout);
+        let mut result = async_std::future::timeout(timeout, handler.handle(request))
             .await
-            .map_err(|_| RouterError::HandlerError(504, String::from("Handler time outed")))?
+            .map_err(|_| RouterError::HandlerError(504, String::from("Handler time outed")))
+            .and_then(|result| result);
+        if let (Some(cors), Some(origin), Ok(response)) = (&cors, &cors_origin, &mut result) {
+            cors.add_headers(origin, response);
+        }
+        if !self.debug_routing {
+            return result;
+        }
+        let mut response = result.unwrap_or_else(|err| self.error(err));
+        if let Ok(name) = hyper::header::HeaderValue::from_str(handler.name()) {
+            response.headers_mut().insert("x-handler", name);
+        }
+        Ok(response)
     }
 
     pub async fn handle(
         &self,
         request: hyper::Request<hyper::Body>,
     ) -> Result<hyper::Response<hyper::Body>, std::convert::Infallible> {
-        Ok(self
+        let _in_flight = self.vars.start_request();
+        let start = std::time::Instant::now();
+        let method = request.method().clone();
+        let path = String::from(request.uri().path());
+        let accepts_trailers = request.version() == hyper::Version::HTTP_2;
+        let mut response = self
             .handle_inner(request)
             .await
-            .unwrap_or_else(|err| Router::error(err)))
+            .unwrap_or_else(|err| self.error(err));
+        let elapsed = start.elapsed();
+        if matches!(self.slow_request_threshold, Some(threshold) if elapsed > threshold) {
+            log::warn!(
+                "Slow request {} {} answered {} in {}ms",
+                method,
+                path,
+                response.status().as_u16(),
+                elapsed.as_millis()
+            );
+        }
+        match &self.server_header {
+            Some(server_header) => {
+                response
+                    .headers_mut()
+                    .insert(hyper::header::SERVER, server_header.clone());
+            }
+            None => {
+                response.headers_mut().remove(hyper::header::SERVER);
+            }
+        }
+        self.vars.record_status(response.status());
+        if self.send_timing_trailer && accepts_trailers {
+            response = Router::with_timing_trailer(response, elapsed);
+        }
+        Ok(response)
+    }
+
+    fn with_timing_trailer(
+        response: hyper::Response<hyper::Body>,
+        elapsed: std::time::Duration,
+    ) -> hyper::Response<hyper::Body> {
+        if hyper::body::HttpBody::size_hint(response.body())
+            .exact()
+            .is_none()
+        {
+            return response;
+        }
+        let (mut parts, body) = response.into_parts();
+        let (mut sender, timed_body) = hyper::Body::channel();
+        let timing = format!("total;dur={}", elapsed.as_millis());
+        tokio::spawn(async move {
+            let data = match hyper::body::to_bytes(body).await {
+                Ok(data) => data,
+                Err(e) => {
+                    log::warn!("Failed to read the response body: {}", e);
+                    return;
+                }
+            };
+            if sender.send_data(data).await.is_err() {
+                return;
+            }
+            let mut trailers = hyper::HeaderMap::new();
+            trailers.insert("server-timing", timing.parse().unwrap());
+            let _ = sender.send_trailers(trailers).await;
+        });
+        parts.headers.insert(
+            hyper::header::TRAILER,
+            hyper::header::HeaderValue::from_static("server-timing"),
+        );
+        hyper::Response::from_parts(parts, timed_body)
+    }
+
+    fn canonicalize_path(request: &mut hyper::Request<hyper::Body>) -> Result<(), RouterError> {
+        let path = request.uri().path();
+        if path.len() <= 1 || !path.ends_with('/') {
+            return Ok(());
+        }
+        let path_and_query = match request.uri().query() {
+            Some(query) => format!("{}?{}", path.trim_end_matches('/'), query),
+            None => String::from(path.trim_end_matches('/')),
+        };
+        let mut parts = request.uri().clone().into_parts();
+        parts.path_and_query = Some(
+            path_and_query
+                .parse::<hyper::http::uri::PathAndQuery>()
+                .map_err(|_| RouterError::InvalidRequest(String::from("Invalid path")))?,
+        );
+        *request.uri_mut() = hyper::Uri::from_parts(parts)
+            .map_err(|_| RouterError::InvalidRequest(String::from("Invalid path")))?;
+        Ok(())
     }
 
     fn get_handler(
         &self,
         request: &hyper::Request<hyper::Body>,
     ) -> Result<&Box<dyn Handler>, RouterError> {
-        log::info!("{:?} {:?}", request.method(), request.uri());
+        if self.slow_request_threshold.is_none() {
+            log::info!("{:?} {:?}", request.method(), request.uri());
+        }
         log::trace!("Headers: {:?}", request.headers());
+        let exact_handlers = self
+            .exact_handlers
+            .get(request.uri().path())
+            .map_or(&[][..], Vec::as_slice);
         let mut server_error = RouterError::NotFound;
-        for handler in self.handlers.iter() {
+        for handler in Router::merge_by_order(exact_handlers, &self.handlers) {
             match handler.get_matcher().matches(request) {
                 MatcherResult::OK => return Ok(handler),
                 MatcherResult::UriOnly => server_error = RouterError::MethodNotAllowed,
                 MatcherResult::KO => (),
             }
         }
-        Err(server_error)
+        match (&server_error, &self.fallback) {
+            (RouterError::NotFound, Some(fallback)) => Ok(fallback),
+            _ => Err(server_error),
+        }
     }
 
-    fn error(error: RouterError) -> hyper::Response<hyper::Body> {
+    /// Iterates over the handlers of both lists, sorted by order
+    fn merge_by_order<'a>(
+        first: &'a [OrderedHandler],
+        second: &'a [OrderedHandler],
+    ) -> impl Iterator<Item = &'a Box<dyn Handler>> {
+        let mut first = first.iter().peekable();
+        let mut second = second.iter().peekable();
+        std::iter::from_fn(move || {
+            let next = match (first.peek(), second.peek()) {
+                (Some((first_order, _)), Some((second_order, _))) if first_order < second_order => {
+                    first.next()
+                }
+                (Some(_), None) => first.next(),
+                _ => second.next(),
+            };
+            next.map(|(_, handler)| handler)
+        })
+    }
+
+    fn error(&self, error: RouterError) -> hyper::Response<hyper::Body> {
         log::info!("Sending error response {:?}", &error);
-        hyper::Response::builder()
-            .status(match &error {
-                RouterError::ForwardingError(_) => 502,
-                RouterError::HandlerError(status, _) => *status,
-                RouterError::InvalidRequest(_) => 400,
-                RouterError::MethodNotAllowed => 405,
-                RouterError::NotFound => 404,
-            })
-            .header("content-type", "text/plain")
-            .body(hyper::Body::from(match error {
-                RouterError::ForwardingError(msg) => msg,
-                RouterError::HandlerError(_, msg) => msg,
-                RouterError::InvalidRequest(msg) => msg,
-                RouterError::MethodNotAllowed => String::from("Method Not Allowed"),
-                RouterError::NotFound => String::from("Not Found"),
-            }))
-            .unwrap()
+        let (status, message) = match &error {
+            RouterError::Conflict(msg) => (412, msg.as_str()),
+            RouterError::ForwardingError(msg) => (502, msg.as_str()),
+            RouterError::HandlerError(status, msg) => (*status, msg.as_str()),
+            RouterError::InvalidRequest(msg) => (400, msg.as_str()),
+            RouterError::JsonError(status, msg) => (*status, msg.as_str()),
+            RouterError::MethodNotAllowed => (405, "Method Not Allowed"),
+            RouterError::NotFound => (404, "Not Found"),
+        };
+        let (content_type, body) = match &error {
+            RouterError::JsonError(_, _) => ("application/json", message.to_owned()),
+            _ if self.json_errors => (
+                "application/json",
+                serde_json::json!({ "error": message, "status": status }).to_string(),
+            ),
+            _ => ("text/plain", message.to_owned()),
+        };
+        let mut builder = hyper::Response::builder()
+            .status(status)
+            .header("content-type", content_type);
+        if let (Some(retry_after), 502..=504) = (self.retry_after, status) {
+            builder = builder.header(hyper::header::RETRY_AFTER, retry_after.as_secs());
+        }
+        builder.body(hyper::Body::from(body)).unwrap()
     }
 }
 
@@ -110,22 +463,59 @@ impl Router {
 mod tests {
     struct MockHandler {
         matcher: Box<dyn crate::matcher::Matcher>,
-        wait: u64,
+        wait: std::time::Duration,
+        timeout: Option<std::time::Duration>,
+        error: Option<super::RouterError>,
     }
 
     impl MockHandler {
         pub fn new(wait: u64) -> MockHandler {
+            MockHandler::at("/jsonrpc", std::time::Duration::from_secs(wait))
+        }
+
+        pub fn at(path: &str, wait: std::time::Duration) -> MockHandler {
             MockHandler {
                 matcher: crate::matcher::builder()
-                    .exact_path("/jsonrpc")
+                    .exact_path(path)
                     .with_method("GET")
                     .build()
                     .unwrap(),
                 wait,
+                timeout: Some(std::time::Duration::from_secs(1)),
+                error: None,
             }
         }
+
+        pub fn with_timeout(mut self, timeout: Option<std::time::Duration>) -> MockHandler {
+            self.timeout = timeout;
+            self
+        }
+
+        pub fn with_error(mut self, error: super::RouterError) -> MockHandler {
+            self.error = Some(error);
+            self
+        }
     }
 
+    /// Logger keeping the warnings in memory
+    struct CapturingLogger(std::sync::Mutex<Vec<String>>);
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger(std::sync::Mutex::new(Vec::new()));
+
     #[async_trait::async_trait]
     impl super::Handler for MockHandler {
         fn get_matcher(&self) -> &Box<dyn crate::matcher::Matcher> {
@@ -135,14 +525,18 @@ mod tests {
             &self,
             _request: hyper::Request<hyper::Body>,
         ) -> Result<hyper::Response<hyper::Body>, crate::router::RouterError> {
-            async_std::task::sleep(std::time::Duration::from_secs(self.wait)).await;
+            async_std::task::sleep(self.wait).await;
+            if let Some(error) = &self.error {
+                return Err(error.clone());
+            }
             Ok(hyper::Response::builder()
                 .status(200)
+                .header("server", "upstream")
                 .body(hyper::Body::from("a response"))
                 .unwrap())
         }
-        fn get_timeout(&self) -> std::time::Duration {
-            std::time::Duration::from_secs(1)
+        fn get_timeout(&self) -> Option<std::time::Duration> {
+            self.timeout
         }
     }
 
@@ -179,6 +573,141 @@ mod tests {
         assert_eq!(404, parts.status);
     }
 
+    #[tokio::test]
+    async fn it_applies_the_default_timeout() {
+        let wait = std::time::Duration::from_millis(1500);
+        let mut router = super::Router::new();
+        router
+            .set_default_timeout(std::time::Duration::from_secs(1))
+            .add_handler(Box::new(
+                MockHandler::at("/default", wait).with_timeout(None),
+            ))
+            .add_handler(Box::new(
+                MockHandler::at("/overridden", wait)
+                    .with_timeout(Some(std::time::Duration::from_secs(5))),
+            ));
+
+        let request = get_request("/default", &hyper::Method::GET);
+        assert_eq!(504, router.handle(request).await.unwrap().status());
+
+        let request = get_request("/overridden", &hyper::Method::GET);
+        assert_eq!(200, router.handle(request).await.unwrap().status());
+    }
+
+    fn get_cors_router() -> super::Router {
+        let mut router = super::Router::new();
+        router
+            .add_handler(Box::new(MockHandler::new(0)))
+            .set_cors(Some(crate::CorsConfig {
+                allowed_origins: vec![String::from("https://ui.example.com")],
+                allowed_methods: vec![hyper::Method::GET, hyper::Method::POST],
+                allowed_headers: vec![String::from("content-type")],
+                max_age: Some(std::time::Duration::from_secs(600)),
+            }));
+        router
+    }
+
+    #[tokio::test]
+    async fn it_answers_the_cors_preflight_requests() {
+        let router = get_cors_router();
+
+        let request = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("OPTIONS")
+            .header("origin", "https://ui.example.com")
+            .header("access-control-request-method", "POST")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+        assert_eq!(204, parts.status);
+        assert_eq!(
+            "https://ui.example.com",
+            parts.headers["access-control-allow-origin"]
+        );
+        assert_eq!("GET, POST", parts.headers["access-control-allow-methods"]);
+        assert_eq!(
+            "content-type",
+            parts.headers["access-control-allow-headers"]
+        );
+        assert_eq!("600", parts.headers["access-control-max-age"]);
+
+        let request = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("OPTIONS")
+            .header("origin", "https://evil.example.com")
+            .header("access-control-request-method", "POST")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+        assert_eq!(405, parts.status);
+        assert!(!parts.headers.contains_key("access-control-allow-origin"));
+    }
+
+    #[tokio::test]
+    async fn it_adds_the_cors_header_to_the_responses() {
+        let router = get_cors_router();
+
+        let request = hyper::Request::builder()
+            .uri("/jsonrpc")
+            .method("GET")
+            .header("origin", "https://ui.example.com")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+        assert_eq!(200, parts.status);
+        assert_eq!(
+            "https://ui.example.com",
+            parts.headers["access-control-allow-origin"]
+        );
+        assert_eq!("origin", parts.headers["vary"]);
+
+        let (parts, _) = router
+            .handle(get_request("/jsonrpc", &hyper::Method::GET))
+            .await
+            .unwrap()
+            .into_parts();
+
+        assert!(!parts.headers.contains_key("access-control-allow-origin"));
+    }
+
+    #[tokio::test]
+    async fn it_applies_the_live_settings_to_the_next_requests() {
+        let router = std::sync::Arc::new(get_cors_router());
+        let live_settings = router.live_settings();
+
+        let preflight = || {
+            hyper::Request::builder()
+                .uri("/jsonrpc")
+                .method("OPTIONS")
+                .header("origin", "https://ui.example.com")
+                .header("access-control-request-method", "PUT")
+                .body(hyper::Body::empty())
+                .unwrap()
+        };
+
+        let (parts, _) = router.handle(preflight()).await.unwrap().into_parts();
+
+        assert_eq!("GET, POST", parts.headers["access-control-allow-methods"]);
+
+        live_settings.write().unwrap().cors = Some(std::sync::Arc::new(crate::CorsConfig {
+            allowed_origins: vec![String::from("https://ui.example.com")],
+            allowed_methods: vec![hyper::Method::PUT],
+            allowed_headers: Vec::new(),
+            max_age: None,
+        }));
+        let (parts, _) = router.handle(preflight()).await.unwrap().into_parts();
+
+        assert_eq!("PUT", parts.headers["access-control-allow-methods"]);
+
+        live_settings.write().unwrap().cors = None;
+        let (parts, _) = router.handle(preflight()).await.unwrap().into_parts();
+
+        assert_eq!(405, parts.status);
+    }
+
     #[tokio::test]
     async fn it_answers_405() {
         let mut router = super::Router::new();
@@ -190,6 +719,339 @@ mod tests {
         assert_eq!(405, parts.status);
     }
 
+    struct NamedHandler {
+        matcher: Box<dyn crate::matcher::Matcher>,
+        name: &'static str,
+    }
+
+    #[async_trait::async_trait]
+    impl super::Handler for NamedHandler {
+        fn get_matcher(&self) -> &Box<dyn crate::matcher::Matcher> {
+            &self.matcher
+        }
+        async fn handle(
+            &self,
+            _request: hyper::Request<hyper::Body>,
+        ) -> Result<hyper::Response<hyper::Body>, crate::router::RouterError> {
+            Ok(hyper::Response::builder()
+                .status(200)
+                .body(hyper::Body::from(self.name))
+                .unwrap())
+        }
+        fn get_timeout(&self) -> Option<std::time::Duration> {
+            Some(std::time::Duration::from_secs(1))
+        }
+    }
+
+    #[tokio::test]
+    async fn it_tries_the_handlers_by_priority() {
+        let exact = || {
+            Box::new(NamedHandler {
+                matcher: crate::matcher::builder()
+                    .exact_path("/jsonrpc")
+                    .build()
+                    .unwrap(),
+                name: "exact",
+            })
+        };
+        let regex = |name| {
+            Box::new(NamedHandler {
+                matcher: crate::matcher::builder().regex_path("^/").build().unwrap(),
+                name,
+            })
+        };
+        let mut router = super::Router::new();
+        router
+            .add_handler_with_priority(-1, regex("low"))
+            .add_handler(regex("default"))
+            .add_handler_with_priority(10, exact())
+            .add_handler(regex("default bis"));
+
+        let get_body = |path: &'static str| {
+            let router = &router;
+            async move {
+                let response = router
+                    .handle(get_request(path, &hyper::Method::GET))
+                    .await
+                    .unwrap();
+                hyper::body::to_bytes(response.into_body()).await.unwrap()
+            }
+        };
+
+        assert_eq!("exact", get_body("/jsonrpc").await);
+        assert_eq!("default", get_body("/files/pdb.kdbx").await);
+    }
+
+    #[tokio::test]
+    async fn it_indexes_the_handlers_with_an_exact_path() {
+        let rule_count = 10_000;
+        let regex = |path: &str, name| {
+            Box::new(NamedHandler {
+                matcher: crate::matcher::builder().regex_path(path).build().unwrap(),
+                name,
+            })
+        };
+        let mut router = super::Router::new();
+        router.add_handler(regex("^/old/3$", "added before"));
+        for i in 0..rule_count {
+            router.add_handler(Box::new(
+                crate::redirect::RedirectHandler::new(
+                    &format!("/old/{}", i),
+                    &format!("/new/{}", i),
+                    308,
+                )
+                .unwrap(),
+            ));
+        }
+        router
+            .add_handler(regex("^/old/5$", "added after"))
+            .add_handler_with_priority(1, regex("^/old/42$", "higher priority"))
+            .add_handler_with_priority(-1, regex("^/", "catch all"));
+
+        let get = |path: String| {
+            let router = &router;
+            async move {
+                router
+                    .handle(get_request(&path, &hyper::Method::GET))
+                    .await
+                    .unwrap()
+            }
+        };
+        let get_body = |path: &'static str| async move {
+            hyper::body::to_bytes(get(String::from(path)).await.into_body())
+                .await
+                .unwrap()
+        };
+
+        for i in (0..rule_count).step_by(7).filter(|i| *i != 42) {
+            let response = get(format!("/old/{}", i)).await;
+
+            assert_eq!(308, response.status());
+            assert_eq!(format!("/new/{}", i), response.headers()["location"]);
+        }
+
+        // the exact and regex handlers are tried by priority, then by insertion order
+        assert_eq!("added before", get_body("/old/3").await);
+        assert_eq!(308, get(String::from("/old/5")).await.status());
+        assert_eq!("higher priority", get_body("/old/42").await);
+        assert_eq!("catch all", get_body("/old/not-a-rule").await);
+    }
+
+    #[tokio::test]
+    async fn it_sends_the_unmatched_requests_to_the_fallback() {
+        let mut router = super::Router::new();
+        router.add_handler(Box::new(MockHandler::new(0)));
+        router.set_fallback(Box::new(MockHandler::at(
+            "/index.html",
+            std::time::Duration::ZERO,
+        )));
+
+        let request = get_request("/ui/settings", &hyper::Method::GET);
+        let (parts, body) = router.handle(request).await.unwrap().into_parts();
+
+        assert_eq!(200, parts.status);
+        assert_eq!("a response", hyper::body::to_bytes(body).await.unwrap());
+
+        let request = get_request("/jsonrpc", &hyper::Method::POST);
+        let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+        assert_eq!(405, parts.status);
+    }
+
+    #[tokio::test]
+    async fn it_counts_requests_in_debug_vars() {
+        let mut router = super::Router::new();
+        router.add_handler(Box::new(MockHandler::new(0)));
+        router.add_handler(crate::debug::get_handler(router.debug_vars()));
+        router.debug_vars().upstream("kodi").record(false);
+
+        router
+            .handle(get_request("/jsonrpc", &hyper::Method::GET))
+            .await
+            .unwrap();
+        router
+            .handle(get_request("/not_found", &hyper::Method::GET))
+            .await
+            .unwrap();
+
+        let request = get_request("/debug/vars", &hyper::Method::GET);
+        let (parts, body) = router.handle(request).await.unwrap().into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        let vars: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(200, parts.status);
+        assert_eq!(1, vars["requests"]["2xx"]);
+        assert_eq!(1, vars["requests"]["4xx"]);
+        assert_eq!(1, vars["inFlightRequests"]);
+        assert_eq!(0, vars["upstreams"]["kodi"]["success"]);
+        assert_eq!(1, vars["upstreams"]["kodi"]["failure"]);
+
+        let vars = router.debug_vars().to_json();
+        assert_eq!(2, vars["requests"]["2xx"]);
+        assert_eq!(0, vars["inFlightRequests"]);
+    }
+
+    #[tokio::test]
+    async fn it_sets_the_server_header() {
+        let mut router = super::Router::new();
+        router.add_handler(Box::new(MockHandler::new(0)));
+
+        let request = get_request("/jsonrpc", &hyper::Method::GET);
+        let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+        assert!(parts.headers.get("server").is_none());
+
+        router.set_server_header(Some(String::from("kodiproxy")));
+        for uri in ["/jsonrpc", "/not_found"] {
+            let request = get_request(uri, &hyper::Method::GET);
+            let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+            assert_eq!("kodiproxy", parts.headers["server"]);
+        }
+
+        router.set_server_header(Some(String::new()));
+        let request = get_request("/jsonrpc", &hyper::Method::GET);
+        let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+        assert!(parts.headers.get("server").is_none());
+    }
+
+    #[tokio::test]
+    async fn it_canonicalizes_trailing_slashes() {
+        let mut router = super::Router::new();
+        router.add_handler(Box::new(MockHandler::new(0)));
+
+        let request = get_request("/jsonrpc/", &hyper::Method::GET);
+        let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+        assert_eq!(404, parts.status);
+
+        router.set_canonicalize_paths(true);
+        for uri in ["/jsonrpc", "/jsonrpc/", "/jsonrpc//?request=1"] {
+            let request = get_request(uri, &hyper::Method::GET);
+            let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+            assert_eq!(200, parts.status);
+        }
+
+        let request = get_request("/", &hyper::Method::GET);
+        let (parts, _) = router.handle(request).await.unwrap().into_parts();
+
+        assert_eq!(404, parts.status);
+    }
+
+    #[tokio::test]
+    async fn it_only_logs_slow_requests() {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Warn);
+
+        let mut router = super::Router::new();
+        router
+            .set_slow_request_threshold(Some(std::time::Duration::from_millis(100)))
+            .add_handler(Box::new(MockHandler::at(
+                "/fast",
+                std::time::Duration::from_millis(0),
+            )))
+            .add_handler(Box::new(MockHandler::at(
+                "/slow",
+                std::time::Duration::from_millis(200),
+            )));
+
+        for uri in ["/fast", "/slow"] {
+            router
+                .handle(get_request(uri, &hyper::Method::GET))
+                .await
+                .unwrap();
+        }
+
+        let records = LOGGER.0.lock().unwrap();
+        assert!(records
+            .iter()
+            .any(|record| record.starts_with("Slow request GET /slow answered 200 in ")));
+        assert!(!records.iter().any(|record| record.contains("/fast")));
+    }
+
+    #[tokio::test]
+    async fn it_sends_the_timing_trailer() {
+        use hyper::body::HttpBody;
+
+        let mut router = super::Router::new();
+        router
+            .add_handler(Box::new(MockHandler::new(0)))
+            .set_send_timing_trailer(true);
+
+        let mut request = get_request("/jsonrpc", &hyper::Method::GET);
+        *request.version_mut() = hyper::Version::HTTP_2;
+        let mut response = router.handle(request).await.unwrap();
+
+        assert_eq!("server-timing", response.headers()["trailer"]);
+        let body = response.body_mut();
+        assert_eq!("a response", body.data().await.unwrap().unwrap());
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert!(trailers["server-timing"]
+            .to_str()
+            .unwrap()
+            .starts_with("total;dur="));
+
+        let response = router
+            .handle(get_request("/jsonrpc", &hyper::Method::GET))
+            .await
+            .unwrap();
+
+        assert!(!response.headers().contains_key("trailer"));
+    }
+
+    #[tokio::test]
+    async fn it_sends_a_retry_after_with_the_upstream_errors() {
+        let mut router = super::Router::new();
+        router
+            .add_handler(Box::new(
+                MockHandler::at("/forward", std::time::Duration::ZERO)
+                    .with_error(super::ForwardingError(String::from("Kodi is down"))),
+            ))
+            .add_handler(Box::new(
+                MockHandler::at("/missing", std::time::Duration::ZERO).with_error(super::NotFound),
+            ));
+
+        let request = get_request("/forward", &hyper::Method::GET);
+        let response = router.handle(request).await.unwrap();
+
+        assert_eq!(502, response.status());
+        assert!(!response.headers().contains_key("retry-after"));
+
+        router.set_retry_after(Some(std::time::Duration::from_secs(5)));
+        let request = get_request("/forward", &hyper::Method::GET);
+        let response = router.handle(request).await.unwrap();
+
+        assert_eq!(502, response.status());
+        assert_eq!("5", response.headers()["retry-after"]);
+
+        let request = get_request("/missing", &hyper::Method::GET);
+        let response = router.handle(request).await.unwrap();
+
+        assert_eq!(404, response.status());
+        assert!(!response.headers().contains_key("retry-after"));
+    }
+
+    #[tokio::test]
+    async fn it_refuses_the_uris_over_the_limit() {
+        let mut router = super::Router::new();
+        router
+            .add_handler(Box::new(MockHandler::new(0)))
+            .set_max_uri_length(20);
+
+        // exactly 20 bytes
+        let request = get_request("/jsonrpc?q=123456789", &hyper::Method::GET);
+        let response = router.handle(request).await.unwrap();
+
+        assert_eq!(200, response.status());
+
+        let request = get_request("/jsonrpc?q=1234567890", &hyper::Method::GET);
+        let response = router.handle(request).await.unwrap();
+
+        assert_eq!(414, response.status());
+    }
+
     #[tokio::test]
     async fn it_answers_504_when_handler_timeouts() {
         let mut router = super::Router::new();
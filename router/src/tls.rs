@@ -0,0 +1,127 @@
+/// Certificate and private key with which [serve](crate::serve) accepts HTTPS connections
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// PEM file containing the certificate chain, the certificate of the server first
+    pub cert_path: std::path::PathBuf,
+    /// PEM file containing the private key of the certificate, in PKCS#8, PKCS#1 or SEC1 format
+    pub key_path: std::path::PathBuf,
+    /// Oldest version of TLS accepted, the clients only supporting older ones are refused
+    pub min_version: TlsVersion,
+    /// Time after which the connections that did not complete their handshake are closed
+    pub handshake_timeout: std::time::Duration,
+}
+
+/// Version of the TLS protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+/// Accepts the TLS connections, giving up on the handshakes that take too long
+#[derive(Clone)]
+pub(crate) struct TlsAcceptor {
+    acceptor: tokio_rustls::TlsAcceptor,
+    handshake_timeout: std::time::Duration,
+}
+
+impl TlsAcceptor {
+    pub(crate) async fn accept(
+        &self,
+        stream: tokio::net::TcpStream,
+    ) -> Result<tokio_rustls::server::TlsStream<tokio::net::TcpStream>, String> {
+        tokio::time::timeout(self.handshake_timeout, self.acceptor.accept(stream))
+            .await
+            .map_err(|_| String::from("handshake timed out"))?
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl std::str::FromStr for TlsVersion {
+    type Err = String;
+
+    /// Parses `1.2` or `1.3`
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        match version {
+            "1.2" => Ok(TlsVersion::Tls12),
+            "1.3" => Ok(TlsVersion::Tls13),
+            _ => Err(format!("Unsupported TLS version: {}", version)),
+        }
+    }
+}
+
+impl std::fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsVersion::Tls12 => write!(f, "1.2"),
+            TlsVersion::Tls13 => write!(f, "1.3"),
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Configuration accepting TLS 1.2 and 1.3, with a handshake timeout of 10 seconds
+    pub fn new(cert_path: std::path::PathBuf, key_path: std::path::PathBuf) -> TlsConfig {
+        TlsConfig {
+            cert_path,
+            key_path,
+            min_version: TlsVersion::Tls12,
+            handshake_timeout: std::time::Duration::from_secs(10),
+        }
+    }
+
+    /// Loads the certificate and the key to build the acceptor of the connections
+    pub(crate) fn acceptor(&self) -> Result<TlsAcceptor, String> {
+        let certs = TlsConfig::read_pem(&self.cert_path, rustls_pemfile::certs)?
+            .into_iter()
+            .map(tokio_rustls::rustls::Certificate)
+            .collect();
+        let key = TlsConfig::read_pem(&self.key_path, |reader| {
+            rustls_pemfile::read_all(reader).map(|items| {
+                items
+                    .into_iter()
+                    .filter_map(|item| match item {
+                        rustls_pemfile::Item::PKCS8Key(key)
+                        | rustls_pemfile::Item::RSAKey(key)
+                        | rustls_pemfile::Item::ECKey(key) => Some(key),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })?
+        .into_iter()
+        .next()
+        .ok_or(format!("No private key found in {:?}", self.key_path))?;
+
+        let versions: &[&'static tokio_rustls::rustls::SupportedProtocolVersion] =
+            match self.min_version {
+                TlsVersion::Tls12 => &[
+                    &tokio_rustls::rustls::version::TLS13,
+                    &tokio_rustls::rustls::version::TLS12,
+                ],
+                TlsVersion::Tls13 => &[&tokio_rustls::rustls::version::TLS13],
+            };
+        let config = tokio_rustls::rustls::ServerConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(versions)
+            .map_err(|e| format!("Invalid TLS versions: {}", e))?
+            .with_no_client_auth()
+            .with_single_cert(certs, tokio_rustls::rustls::PrivateKey(key))
+            .map_err(|e| format!("Invalid certificate or key: {}", e))?;
+        Ok(TlsAcceptor {
+            acceptor: tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(config)),
+            handshake_timeout: self.handshake_timeout,
+        })
+    }
+
+    fn read_pem<T, F>(path: &std::path::Path, read: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut dyn std::io::BufRead) -> std::io::Result<T>,
+    {
+        let file =
+            std::fs::File::open(path).map_err(|e| format!("Could not open {:?}: {}", path, e))?;
+        read(&mut std::io::BufReader::new(file))
+            .map_err(|e| format!("Could not read {:?}: {}", path, e))
+    }
+}
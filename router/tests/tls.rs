@@ -0,0 +1,160 @@
+const TEST_PATH: &str = "target/test/router/tls";
+
+/// Writes a self-signed certificate for localhost, returning the TLS configuration using it and
+/// the certificate to trust
+fn write_self_signed_certificate(
+    name: &str,
+) -> (router::TlsConfig, tokio_rustls::rustls::Certificate) {
+    let certificate = rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+    std::fs::create_dir_all(TEST_PATH).unwrap();
+    let cert_path = std::path::Path::new(TEST_PATH).join(format!("{}.cert.pem", name));
+    let key_path = std::path::Path::new(TEST_PATH).join(format!("{}.key.pem", name));
+    std::fs::write(&cert_path, certificate.serialize_pem().unwrap()).unwrap();
+    std::fs::write(&key_path, certificate.serialize_private_key_pem()).unwrap();
+    (
+        router::TlsConfig::new(cert_path, key_path),
+        tokio_rustls::rustls::Certificate(certificate.serialize_der().unwrap()),
+    )
+}
+
+#[tokio::test]
+async fn it_serves_https() {
+    let (tls, certificate) = write_self_signed_certificate("https");
+    let host = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let server = tokio::spawn(router::serve(
+        host,
+        None,
+        router::ServeOptions::default().with_tls(Some(tls)),
+        |_| {},
+    ));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let mut roots = tokio_rustls::rustls::RootCertStore::empty();
+    roots.add(&certificate).unwrap();
+    let client_config = tokio_rustls::rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let stream = tokio::net::TcpStream::connect(host).await.unwrap();
+    let stream = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client_config))
+        .connect("localhost".try_into().unwrap(), stream)
+        .await
+        .expect("TLS handshake failed");
+
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    let request = hyper::Request::builder()
+        .uri("/exit")
+        .header("host", "localhost")
+        .body(hyper::Body::empty())
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+
+    assert_eq!(200, response.status());
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), server)
+        .await
+        .expect("The server should have exited")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn it_refuses_plain_http_when_serving_https() {
+    let (tls, _) = write_self_signed_certificate("plain_http");
+    let host = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let (exit_sender, exit_receiver) = futures::channel::oneshot::channel::<()>();
+
+    let server = tokio::spawn(router::serve(
+        host,
+        Some(exit_receiver),
+        router::ServeOptions::default().with_tls(Some(tls)),
+        |_| {},
+    ));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let result = hyper::Client::new()
+        .get(format!("http://{}/debug/vars", host).parse().unwrap())
+        .await;
+
+    assert!(result.is_err());
+
+    exit_sender.send(()).unwrap();
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn it_refuses_the_clients_below_the_min_version() {
+    let (mut tls, certificate) = write_self_signed_certificate("min_version");
+    tls.min_version = router::TlsVersion::Tls13;
+    let host = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let (exit_sender, exit_receiver) = futures::channel::oneshot::channel::<()>();
+
+    let server = tokio::spawn(router::serve(
+        host,
+        Some(exit_receiver),
+        router::ServeOptions::default().with_tls(Some(tls)),
+        |_| {},
+    ));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let mut roots = tokio_rustls::rustls::RootCertStore::empty();
+    roots.add(&certificate).unwrap();
+    let client_config = tokio_rustls::rustls::ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&tokio_rustls::rustls::version::TLS12])
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let stream = tokio::net::TcpStream::connect(host).await.unwrap();
+    let result = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client_config))
+        .connect("localhost".try_into().unwrap(), stream)
+        .await;
+
+    assert!(result.is_err());
+
+    exit_sender.send(()).unwrap();
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn it_closes_the_connections_stalled_in_the_handshake() {
+    use tokio::io::AsyncReadExt;
+
+    let (mut tls, _) = write_self_signed_certificate("handshake_timeout");
+    tls.handshake_timeout = std::time::Duration::from_millis(200);
+    let host = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let (exit_sender, exit_receiver) = futures::channel::oneshot::channel::<()>();
+
+    let server = tokio::spawn(router::serve(
+        host,
+        Some(exit_receiver),
+        router::ServeOptions::default().with_tls(Some(tls)),
+        |_| {},
+    ));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // the client never starts the handshake
+    let mut stream = tokio::net::TcpStream::connect(host).await.unwrap();
+    let read = tokio::time::timeout(std::time::Duration::from_secs(2), stream.read(&mut [0; 16]))
+        .await
+        .expect("The server should have closed the connection");
+
+    assert_eq!(0, read.unwrap());
+
+    exit_sender.send(()).unwrap();
+    server.await.unwrap();
+}